@@ -0,0 +1,21 @@
+//! `cargo bench --features bench`, measures parsing the fixture tile and rendering a frame of it
+
+use criterion::{Criterion, criterion_group, criterion_main};
+
+use rod_animations::bench::{FrameFixture, TileFixture};
+
+fn parse_tile(c: &mut Criterion) {
+    let tile = TileFixture::load().expect("fixture tile exists");
+    c.bench_function("parse_tile", |b| {
+        b.iter(|| tile.parse().expect("fixture tile is valid"))
+    });
+}
+
+fn render_frame(c: &mut Criterion) {
+    let frame = FrameFixture::load().expect("fixture tile exists");
+    c.bench_function("render_frame", |b| b.iter(|| frame.render()));
+    c.bench_function("draw_map", |b| b.iter(|| frame.draw_map()));
+}
+
+criterion_group!(benches, parse_tile, render_frame);
+criterion_main!(benches);
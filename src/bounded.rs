@@ -4,6 +4,8 @@ pub trait Bounded {
     fn bounding_box(&self) -> Rect;
 }
 
+/// Axis aligned rectangle including its edges, rectangles which only share
+/// an edge or a corner intersect and a rectangle contains its own edges.
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct Rect {
     pub x_min: f32,
@@ -41,6 +43,7 @@ impl Rect {
         }
     }
 
+    /// true if the rectangles share at least one point, touching edges count
     pub fn intersects(&self, other: &Rect) -> bool {
         !(self.x_max < other.x_min
             || self.x_min > other.x_max
@@ -48,6 +51,7 @@ impl Rect {
             || self.y_min > other.y_max)
     }
 
+    /// true if every point of `other` is in `self`, `other` may touch the edges
     pub fn contains(&self, other: &Rect) -> bool {
         self.x_min <= other.x_min
             && self.x_max >= other.x_max
@@ -171,3 +175,152 @@ impl Rect {
         ]
     }
 }
+
+/// nodes with more items than this are split into quadrants
+const QUAD_TREE_MAX_ITEMS: usize = 8;
+/// nodes this deep are not split any further
+const QUAD_TREE_MAX_DEPTH: u32 = 8;
+
+/// Quadtree over rectangles which are identified by their index in the input.
+/// Each rectangle is kept in the smallest node containing it, rectangles reaching
+/// outside of the bounds of the tree stay in the root.
+pub struct QuadTree {
+    bounds: Rect,
+    items: Vec<(Rect, usize)>,
+    children: Option<Box<[QuadTree; 4]>>,
+}
+
+impl QuadTree {
+    pub fn new(bounds: Rect, rects: impl IntoIterator<Item = Rect>) -> Self {
+        let mut tree = Self::leaf(bounds);
+        for (idx, rect) in rects.into_iter().enumerate() {
+            tree.insert(rect, idx, 0);
+        }
+        tree
+    }
+
+    fn leaf(bounds: Rect) -> Self {
+        Self {
+            bounds,
+            items: Vec::new(),
+            children: None,
+        }
+    }
+
+    fn insert(&mut self, rect: Rect, idx: usize, depth: u32) {
+        if let Some(children) = &mut self.children
+            && let Some(child) = children.iter_mut().find(|c| c.bounds.contains(&rect))
+        {
+            child.insert(rect, idx, depth + 1);
+            return;
+        }
+        self.items.push((rect, idx));
+        if self.children.is_none()
+            && self.items.len() > QUAD_TREE_MAX_ITEMS
+            && depth < QUAD_TREE_MAX_DEPTH
+        {
+            self.children = Some(Box::new(self.bounds.get_quadrants().map(Self::leaf)));
+            for (rect, idx) in std::mem::take(&mut self.items) {
+                self.insert(rect, idx, depth);
+            }
+        }
+    }
+
+    /// indices of the rectangles intersecting `area`, in increasing order
+    pub fn query(&self, area: &Rect) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.collect(area, &mut found);
+        found.sort_unstable();
+        found
+    }
+
+    fn collect(&self, area: &Rect, found: &mut Vec<usize>) {
+        found.extend(
+            self.items
+                .iter()
+                .filter(|(rect, _)| rect.intersects(area))
+                .map(|(_, idx)| *idx),
+        );
+        for child in self.children.iter().flat_map(|children| children.iter()) {
+            if child.bounds.intersects(area) {
+                child.collect(area, found);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn quad_tree() {
+        // deterministic pseudo random numbers from 0 to 1
+        let mut state = 12345u32;
+        let mut next = move || {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 8) as f32 / (1 << 24) as f32
+        };
+        let rects: Vec<Rect> = (0..500)
+            .map(|_| {
+                let (x, y) = (next() * 1.2 - 0.1, next() * 1.2 - 0.1);
+                let size = next() * next() * 0.3;
+                Rect::new(x, x + size, y, y + size * next())
+            })
+            .collect();
+        let tree = QuadTree::new(Rect::new(0.0, 1.0, 0.0, 1.0), rects.iter().copied());
+        for _ in 0..200 {
+            let (x, y) = (next(), next());
+            let area = Rect::new(x, x + next() * 0.2, y, y + next() * 0.2);
+            let linear: Vec<usize> = (0..rects.len())
+                .filter(|&idx| rects[idx].intersects(&area))
+                .collect();
+            assert_eq!(tree.query(&area), linear);
+        }
+        assert_eq!(
+            tree.query(&Rect::new(-1.0, 2.0, -1.0, 2.0)),
+            (0..rects.len()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn intersects() {
+        let unit = Rect::new(0.0, 1.0, 0.0, 1.0);
+        assert!(unit.intersects(&Rect::new(0.5, 2.0, 0.5, 2.0)));
+        assert!(unit.intersects(&Rect::new(0.2, 0.8, 0.2, 0.8)));
+        // shared edges
+        assert!(unit.intersects(&Rect::new(1.0, 2.0, 0.0, 1.0)));
+        assert!(unit.intersects(&Rect::new(-1.0, 0.0, 0.0, 1.0)));
+        assert!(unit.intersects(&Rect::new(0.0, 1.0, 1.0, 2.0)));
+        assert!(unit.intersects(&Rect::new(0.0, 1.0, -1.0, 0.0)));
+        // shared corners
+        assert!(unit.intersects(&Rect::new(1.0, 2.0, 1.0, 2.0)));
+        assert!(unit.intersects(&Rect::new(-1.0, 0.0, -1.0, 0.0)));
+        // apart
+        assert!(!unit.intersects(&Rect::new(1.01, 2.0, 0.0, 1.0)));
+        assert!(!unit.intersects(&Rect::new(0.0, 1.0, -1.0, -0.01)));
+        assert!(!unit.intersects(&Rect::new(1.01, 2.0, 1.01, 2.0)));
+        // symmetric
+        let other = Rect::new(1.0, 2.0, 1.0, 2.0);
+        assert_eq!(unit.intersects(&other), other.intersects(&unit));
+    }
+
+    #[test]
+    fn contains() {
+        let unit = Rect::new(0.0, 1.0, 0.0, 1.0);
+        assert!(unit.contains(&unit));
+        assert!(unit.contains(&Rect::new(0.0, 0.5, 0.5, 1.0)));
+        assert!(unit.contains(&Rect::new(1.0, 1.0, 1.0, 1.0)));
+        assert!(!unit.contains(&Rect::new(0.5, 1.5, 0.0, 1.0)));
+        assert!(unit.contains_point(Vector::new(1.0, 0.0)));
+        assert!(!unit.contains_point(Vector::new(1.0, 1.01)));
+        // everything contained intersects
+        for other in [
+            unit,
+            Rect::new(1.0, 1.0, 0.0, 0.0),
+            Rect::new(0.0, 0.0, 0.0, 1.0),
+        ] {
+            assert!(unit.contains(&other) && unit.intersects(&other));
+        }
+    }
+}
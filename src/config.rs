@@ -0,0 +1,586 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::{Deserialize, Deserializer, de::Error};
+use skia_safe::{FilterMode, MipmapMode, SamplingOptions};
+
+use crate::{
+    draw::{Color, Corner},
+    smooth_step,
+};
+
+/// Settings read from `config.json` in the resource directory.
+/// Every field is optional in the file and falls back to the values in `Default`.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// allowed latitude range for coordinates in input files (min, max)
+    pub lat_bounds: (f32, f32),
+    /// allowed longitude range for coordinates in input files (min, max)
+    pub lon_bounds: (f32, f32),
+    /// h264 encoder used by ffmpeg, hardware encoders fall back to libx264 if they fail
+    pub video_encoder: VideoEncoder,
+    /// if set videos are encoded with two libx264 passes instead of `video_encoder`
+    pub two_pass: Option<TwoPass>,
+    /// if set the palette shifts towards a night palette depending on the hour of the frame
+    pub day_night: Option<DayNight>,
+    /// if set a soft shadow is drawn below the tip of every pin
+    pub pin_shadow: Option<PinShadow>,
+    /// if set a ring bursts out of a checkpoint when the event time passes its departure
+    /// or arrival times, only in renders with `Checkpoints`
+    pub checkpoint_events: Option<CheckpointEvents>,
+    /// images drawn over the pin of a person or checkpoint, by its name, in order
+    pub extra_pins: HashMap<String, Vec<ExtraPin>>,
+    /// How much pins follow the zoom when a sweep zooms out to show both of its ends.
+    /// The map shrinks by `2^dz` where `dz` is how far the camera is zoomed out compared to
+    /// zooming straight from the start to the end zoom, the pins by `2^(dz * exponent)`.
+    /// 0 keeps the pins at the same size on screen, 1 shrinks them with the map.
+    pub sweep_pin_exponent: f32,
+    /// pins are drawn at least and at most this high in pixels, whatever the render asks for
+    pub pin_height_limits: PinHeightLimits,
+    /// if set pins of tracks with altitude are tinted by their elevation
+    pub elevation_tint: Option<ElevationTint>,
+    /// if set pins are tinted by the speed of their person, takes precedence over `elevation_tint`
+    pub speed_tint: Option<SpeedTint>,
+    /// if set the water layers of the style ripple, moving with the time of the video
+    pub water_ripple: Option<WaterRipple>,
+    /// number of threads rendering frames in parallel, defaults to the number of cores
+    pub render_threads: Option<usize>,
+    /// quality of renders which do not set their own
+    pub quality: Quality,
+    /// Tiles of neighboring zoom levels fade into each other while zooming. Without the fade
+    /// only the nearest level is drawn, which is faster but the map pops at each switch.
+    pub zoom_cross_fade: bool,
+    /// Final frames are drawn this many times larger and scaled down to the output size,
+    /// which smooths text and thin lines. 1 draws them at the output size.
+    pub supersample: f32,
+    /// Draws stills and video frames with 16 bit channels and converts them to 8 bits with an
+    /// ordered dither, it breaks up the banding of smooth gradients and flat map areas.
+    pub dither: bool,
+    /// inset of overlays from the frame edges as a fraction of the frame height
+    pub safe_area: f32,
+    /// placement of the leaderboard of renders with `Rangliste`
+    pub leaderboard: LeaderboardConfig,
+    /// look of the latitude and longitude lines of renders with `Gradnetz`
+    pub graticule: GraticuleConfig,
+    /// placement of the overview map of renders with `Übersicht`
+    pub overview: OverviewConfig,
+    /// directory of the still frames relative to the out directory
+    pub image_dir: PathBuf,
+    /// directory of the videos relative to the out directory
+    pub video_dir: PathBuf,
+    /// connection settings for downloading tiles
+    pub tile_downloads: TileDownloads,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            lat_bounds: (20.0, 50.0),
+            lon_bounds: (0.0, 10.0),
+            video_encoder: VideoEncoder::Libx264,
+            two_pass: None,
+            day_night: None,
+            pin_shadow: None,
+            checkpoint_events: None,
+            extra_pins: HashMap::new(),
+            sweep_pin_exponent: 0.2,
+            pin_height_limits: PinHeightLimits::default(),
+            elevation_tint: None,
+            speed_tint: None,
+            water_ripple: None,
+            render_threads: None,
+            quality: Quality::Final,
+            zoom_cross_fade: true,
+            supersample: 1.0,
+            dither: false,
+            safe_area: 0.05,
+            leaderboard: LeaderboardConfig::default(),
+            graticule: GraticuleConfig::default(),
+            overview: OverviewConfig::default(),
+            image_dir: PathBuf::new(),
+            video_dir: PathBuf::new(),
+            tile_downloads: TileDownloads::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VideoEncoder {
+    Libx264,
+    /// NVIDIA GPUs
+    H264Nvenc,
+    /// macOS
+    H264Videotoolbox,
+}
+
+impl VideoEncoder {
+    /// name of the encoder for ffmpeg's `-c:v`
+    pub fn codec(&self) -> &'static str {
+        match self {
+            VideoEncoder::Libx264 => "libx264",
+            VideoEncoder::H264Nvenc => "h264_nvenc",
+            VideoEncoder::H264Videotoolbox => "h264_videotoolbox",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Quality {
+    /// no anti-aliasing and nearest sampling for fast previews
+    Draft,
+    Final,
+}
+
+impl Quality {
+    pub fn anti_alias(&self) -> bool {
+        *self == Quality::Final
+    }
+
+    /// sampling used to scale images like the pins
+    pub fn sampling(&self) -> SamplingOptions {
+        match self {
+            Quality::Draft => SamplingOptions::new(FilterMode::Nearest, MipmapMode::None),
+            Quality::Final => SamplingOptions::new(FilterMode::Linear, MipmapMode::Linear),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct LeaderboardConfig {
+    pub corner: Corner,
+    /// only the leading people are listed
+    pub max_entries: usize,
+}
+
+impl Default for LeaderboardConfig {
+    fn default() -> Self {
+        Self {
+            corner: Corner::TopRight,
+            max_entries: 5,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct GraticuleConfig {
+    pub color: Color,
+    /// from 0 to 1
+    pub opacity: f32,
+    /// line width in pixels
+    pub width: f32,
+}
+
+impl Default for GraticuleConfig {
+    fn default() -> Self {
+        Self {
+            color: Color::new(255, 255, 255),
+            opacity: 0.3,
+            width: 2.0,
+        }
+    }
+}
+
+/// the overview is the whole frame at its own zoom, scaled down by `size`
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct OverviewConfig {
+    pub corner: Corner,
+    /// fraction of the frame width and height
+    pub size: f32,
+    pub border_color: Color,
+    /// in pixels
+    pub border_width: f32,
+    /// color of the dot at the center of the camera
+    pub marker_color: Color,
+}
+
+impl Default for OverviewConfig {
+    fn default() -> Self {
+        Self {
+            corner: Corner::BottomLeft,
+            size: 0.25,
+            border_color: Color::new(255, 255, 255),
+            border_width: 4.0,
+            marker_color: Color::new(230, 40, 40),
+        }
+    }
+}
+
+/// the connections to the tile server are kept open so consecutive downloads reuse them
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct TileDownloads {
+    /// seconds an unused connection is kept open
+    pub pool_idle_timeout_s: u64,
+    /// number of unused connections kept open
+    pub pool_max_idle_per_host: usize,
+    /// seconds between TCP keep-alive probes, `None` turns them off
+    pub tcp_keepalive_s: Option<u64>,
+    /// maximum number of tile downloads per second, `None` does not limit them
+    #[serde(deserialize_with = "positive_or_null")]
+    pub requests_per_second: Option<f32>,
+}
+
+impl Default for TileDownloads {
+    fn default() -> Self {
+        Self {
+            pool_idle_timeout_s: 90,
+            pool_max_idle_per_host: 4,
+            tcp_keepalive_s: Some(60),
+            requests_per_second: Some(10.0),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TwoPass {
+    /// target bitrate in ffmpeg notation, e.g. `40M`
+    pub bitrate: String,
+    /// x264 preset, slower presets give better quality at the same bitrate
+    #[serde(default = "default_preset")]
+    pub preset: String,
+}
+
+fn default_preset() -> String {
+    "slow".to_string()
+}
+
+#[derive(Deserialize)]
+pub struct DayNight {
+    /// defaults to the palette of the style
+    #[serde(default)]
+    pub day_palette: Option<Vec<Color>>,
+    /// colors missing here keep their day color
+    pub night_palette: Vec<Color>,
+    /// hour in the middle of the dawn transition
+    #[serde(default = "default_sunrise")]
+    pub sunrise: f32,
+    /// hour in the middle of the dusk transition
+    #[serde(default = "default_sunset")]
+    pub sunset: f32,
+    /// length of dawn and dusk in hours
+    #[serde(default = "default_transition")]
+    pub transition: f32,
+}
+
+fn default_sunrise() -> f32 {
+    6.0
+}
+
+fn default_sunset() -> f32 {
+    20.0
+}
+
+fn default_transition() -> f32 {
+    1.0
+}
+
+impl DayNight {
+    /// a `day_palette` has to have a color for every color of the palette of the style
+    pub fn problems(&self, palette_len: usize) -> Vec<String> {
+        match &self.day_palette {
+            Some(palette) if palette.len() < palette_len => vec![format!(
+                "day_night.day_palette: has {} colors, the palette of the style has {palette_len}",
+                palette.len()
+            )],
+            _ => Vec::new(),
+        }
+    }
+
+    /// 1 during the day, 0 at night, `time` is in seconds since `TIME_ZERO` which is at midnight
+    pub fn daylight(&self, time: u32) -> f32 {
+        let hour = (time % (24 * 60 * 60)) as f32 / (60.0 * 60.0);
+        let half = self.transition / 2.0;
+        if hour < (self.sunrise + self.sunset) / 2.0 {
+            smooth_step(hour, self.sunrise - half, self.sunrise + half)
+        } else {
+            1.0 - smooth_step(hour, self.sunset - half, self.sunset + half)
+        }
+    }
+}
+
+/// sizes are fractions of the pin height
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct PinShadow {
+    /// offset of the shadow center from the pin tip
+    pub offset: (f32, f32),
+    /// width of the shadow, it is a third as high
+    pub width: f32,
+    /// blur sigma
+    pub softness: f32,
+    pub opacity: f32,
+}
+
+impl Default for PinShadow {
+    fn default() -> Self {
+        Self {
+            offset: (0.02, 0.01),
+            width: 0.3,
+            softness: 0.03,
+            opacity: 0.5,
+        }
+    }
+}
+
+/// an image from the pins directory drawn centered at an offset from the tip of a pin,
+/// sizes are fractions of the pin height
+#[derive(Deserialize)]
+pub struct ExtraPin {
+    /// file name in the pins directory without `.png`, or the path to an image file
+    pub image: String,
+    #[serde(default)]
+    pub offset: (f32, f32),
+    #[serde(default = "full_height")]
+    pub height: f32,
+}
+
+fn full_height() -> f32 {
+    1.0
+}
+
+/// rings expanding from checkpoints around their departure and arrival times,
+/// sizes are fractions of the pin height
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct CheckpointEvents {
+    /// times by checkpoint name in the format of the tracks, like `2025-04-15T18:30:00`
+    pub times: HashMap<String, Vec<String>>,
+    pub color: Color,
+    /// seconds of event time before and after an event the ring is visible
+    pub duration_s: u32,
+    /// radius of the ring when it fades out
+    pub radius: f32,
+    pub width: f32,
+}
+
+impl Default for CheckpointEvents {
+    fn default() -> Self {
+        Self {
+            times: HashMap::new(),
+            color: Color::new(255, 255, 255),
+            duration_s: 1800,
+            radius: 0.5,
+            width: 0.03,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct PinHeightLimits {
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+}
+
+impl PinHeightLimits {
+    pub fn clamp(&self, pin_height: f32) -> f32 {
+        let pin_height = self.min.map_or(pin_height, |min| pin_height.max(min));
+        self.max.map_or(pin_height, |max| pin_height.min(max))
+    }
+}
+
+/// tint of the pins going from `low` at `min_altitude` to `high` at `max_altitude`
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ElevationTint {
+    pub low: Color,
+    pub high: Color,
+    /// meters above sea level
+    pub min_altitude: f32,
+    pub max_altitude: f32,
+    /// how much of the pin is covered by the tint, from 0 to 1
+    pub strength: f32,
+}
+
+impl ElevationTint {
+    pub fn color(&self, altitude: f32) -> Color {
+        let t = ((altitude - self.min_altitude) / (self.max_altitude - self.min_altitude))
+            .clamp(0.0, 1.0);
+        self.low.lerp(self.high, t).with_alpha(self.strength)
+    }
+}
+
+impl Default for ElevationTint {
+    fn default() -> Self {
+        Self {
+            low: Color::new(46, 158, 68),
+            high: Color::new(255, 255, 255),
+            min_altitude: 0.0,
+            max_altitude: 3000.0,
+            strength: 0.5,
+        }
+    }
+}
+
+/// tint of the pins going from `slow` at `min_speed` to `fast` at `max_speed`
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct SpeedTint {
+    pub slow: Color,
+    pub fast: Color,
+    /// km/h
+    pub min_speed: f32,
+    pub max_speed: f32,
+    /// how much of the pin is covered by the tint, from 0 to 1
+    pub strength: f32,
+}
+
+impl SpeedTint {
+    /// `speed` in meters per second
+    pub fn color(&self, speed: f32) -> Color {
+        let t =
+            ((speed * 3.6 - self.min_speed) / (self.max_speed - self.min_speed)).clamp(0.0, 1.0);
+        self.slow.lerp(self.fast, t).with_alpha(self.strength)
+    }
+}
+
+impl Default for SpeedTint {
+    fn default() -> Self {
+        Self {
+            slow: Color::new(214, 58, 48),
+            fast: Color::new(46, 158, 68),
+            min_speed: 0.0,
+            max_speed: 30.0,
+            strength: 0.5,
+        }
+    }
+}
+
+/// the water is displaced by noise which drifts across the screen
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct WaterRipple {
+    /// names of the layers in the style which ripple
+    pub layers: Vec<String>,
+    /// largest displacement in pixels
+    pub amplitude: f32,
+    /// size of the ripples in pixels, has to be positive
+    #[serde(deserialize_with = "positive")]
+    pub wavelength: f32,
+    /// how far the ripples drift in pixels per second of video
+    pub speed: f32,
+}
+
+impl Default for WaterRipple {
+    fn default() -> Self {
+        Self {
+            layers: vec!["water_polygons".to_string(), "water_lines".to_string()],
+            amplitude: 3.0,
+            wavelength: 60.0,
+            speed: 20.0,
+        }
+    }
+}
+
+/// rejects numbers which are not larger than 0 when the config is loaded
+fn positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    let value = f32::deserialize(deserializer)?;
+    if value > 0.0 {
+        Ok(value)
+    } else {
+        Err(D::Error::custom(format!(
+            "expected a positive number, got {value}"
+        )))
+    }
+}
+
+/// like `positive` but also accepts `null`
+fn positive_or_null<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f32>, D::Error> {
+    match Option::<f32>::deserialize(deserializer)? {
+        Some(value) if value <= 0.0 || value.is_nan() => Err(D::Error::custom(format!(
+            "expected a positive number or null, got {value}"
+        ))),
+        value => Ok(value),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn daylight() {
+        let day_night: DayNight =
+            serde_json::from_str(r##"{"night_palette": ["#000000"]}"##).expect("in test");
+        let hour = |h: f32| (h * 60.0 * 60.0) as u32;
+        assert_eq!(day_night.daylight(hour(3.0)), 0.0);
+        assert_eq!(day_night.daylight(hour(6.0)), 0.5);
+        assert_eq!(day_night.daylight(hour(12.0)), 1.0);
+        assert_eq!(day_night.daylight(hour(20.0)), 0.5);
+        assert_eq!(day_night.daylight(hour(23.0)), 0.0);
+        assert_eq!(day_night.daylight(hour(24.0 + 12.0)), 1.0);
+
+        let hard: DayNight =
+            serde_json::from_str(r##"{"night_palette": ["#000000"], "transition": 0.0}"##)
+                .expect("in test");
+        assert_eq!(hard.daylight(hour(5.5)), 0.0);
+        assert_eq!(hard.daylight(hour(6.0)), 1.0);
+        assert_eq!(hard.daylight(hour(20.0)), 0.0);
+        assert_eq!(hard.daylight(hour(19.5)), 1.0);
+    }
+
+    #[test]
+    fn day_palette_length() {
+        let day_night: DayNight = serde_json::from_str(
+            r##"{"day_palette": ["#ffffff", "#eeeeee"], "night_palette": ["#000000"]}"##,
+        )
+        .expect("in test");
+        assert!(day_night.problems(2).is_empty());
+        let problems = day_night.problems(3);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("day_night.day_palette: "));
+    }
+
+    #[test]
+    fn pin_height_limits() {
+        assert_eq!(PinHeightLimits::default().clamp(5000.0), 5000.0);
+        let limits: PinHeightLimits =
+            serde_json::from_str(r#"{"min": 20.0, "max": 600.0}"#).expect("in test");
+        assert_eq!(limits.clamp(5.0), 20.0);
+        assert_eq!(limits.clamp(200.0), 200.0);
+        assert_eq!(limits.clamp(5000.0), 600.0);
+        let only_max: PinHeightLimits = serde_json::from_str(r#"{"max": 600.0}"#).expect("in test");
+        assert_eq!(only_max.clamp(5.0), 5.0);
+    }
+
+    #[test]
+    fn extra_pins() {
+        let config: Config = serde_json::from_str(
+            r#"{"extra_pins": {"Luca": [{"image": "flag"}, {"image": "one", "offset": [0.2, -0.9], "height": 0.3}]}}"#,
+        )
+        .expect("in test");
+        let extras = &config.extra_pins["Luca"];
+        assert_eq!((extras[0].offset, extras[0].height), ((0.0, 0.0), 1.0));
+        assert_eq!((extras[1].offset, extras[1].height), ((0.2, -0.9), 0.3));
+    }
+
+    #[test]
+    fn water_ripple() {
+        let config: Config =
+            serde_json::from_str(r#"{"water_ripple": {"amplitude": 1.5}}"#).expect("in test");
+        let ripple = config.water_ripple.expect("in test");
+        assert_eq!(ripple.amplitude, 1.5);
+        assert_eq!(ripple.layers, ["water_polygons", "water_lines"]);
+        assert!(Config::default().water_ripple.is_none());
+        assert!(serde_json::from_str::<Config>(r#"{"water_ripple": {"wavelength": 0}}"#).is_err());
+    }
+
+    #[test]
+    fn request_rate() {
+        let parse = |json: &str| serde_json::from_str::<Config>(json);
+        let config =
+            parse(r#"{"tile_downloads": {"requests_per_second": null}}"#).expect("in test");
+        assert_eq!(config.tile_downloads.requests_per_second, None);
+        assert_eq!(
+            Config::default().tile_downloads.requests_per_second,
+            Some(10.0)
+        );
+        assert!(parse(r#"{"tile_downloads": {"requests_per_second": 0}}"#).is_err());
+        assert!(parse(r#"{"tile_downloads": {"requests_per_second": -2}}"#).is_err());
+    }
+}
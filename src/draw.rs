@@ -1,46 +1,150 @@
 use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashSet},
     fmt, fs,
     io::Read,
     path::{self, PathBuf},
     process::Command,
-    sync::LazyLock,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
 };
 
 use anyhow::{Result, anyhow};
 use hsv::hsv_to_rgb;
-use log::{error, info};
-use rayon::iter::{ParallelBridge, ParallelIterator};
-use serde::Deserialize;
+use log::{error, info, warn};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use skia_safe::{
-    Bitmap, Canvas, Color4f, ColorType, FilterMode, Image, ImageInfo, OwnedCanvas, Paint,
-    PaintStyle, SamplingOptions,
+    Bitmap, BlendMode, BlurStyle, CachingHint, Canvas, Color4f, ColorChannel, ColorType,
+    CubicResampler, Image, ImageInfo, MaskFilter, Matrix, Paint, PaintCap, PaintJoin, PaintStyle,
+    Shader, TileMode,
     canvas::{SaveLayerRec, SrcRectConstraint},
+    color_filters, image_filters, shaders,
 };
 
 use crate::{
-    BASE_RES_PATH, FRAME_RATE, HEIGHT, OUT_PATH, OneOrTwo, PEOPLE, PINS_PATH, Transform, Vector,
-    WIDTH, WORLD, fade_in_function, fade_out_function,
-    map::{SORTERS, TILE_SIZE, TileDescr},
-    smoother_step,
+    CONFIG, FRAME_RATE, FRAMES_PATH, HEIGHT, OUT_PATH, OneOrTwo, PEOPLE, PINS_PATH,
+    RESOURCES_VERSION, Transform, Vector, WIDTH, World,
+    bounded::Rect,
+    config::{PinShadow, Quality, TwoPass, VideoEncoder, WaterRipple},
+    error::RenderError,
+    fade_in_function, fade_out_function,
+    map::{MapData, TILE_SIZE, TileDescr},
+    smoother_step, tiles_at, tiles_for, tiles_in, vec_to_lat_long,
 };
 
+pub mod api;
+#[cfg(feature = "bench")]
+pub mod bench;
+mod dither;
+#[cfg(feature = "gpu")]
+mod gpu;
+mod overlay;
 pub mod parse;
+pub mod progress;
+mod timeline;
+
+use progress::{Progress, ProgressEvent};
+use timeline::{Easing, Keyframe, Timeline};
 
 #[derive(Clone, Copy, Deserialize)]
-#[serde(from = "usize")]
+#[serde(try_from = "ColorDescr")]
 pub struct Color {
     r: u8,
     g: u8,
     b: u8,
+    /// multiplied with the opacity of the frame
+    a: f32,
+    /// index into the palette if the color was given as one, themes replace these colors
+    idx: Option<usize>,
 }
 
 impl fmt::Debug for Color {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Color {{ #{:>0X}{:>0X}{:>0X} }}", self.r, self.g, self.b)
+        write!(
+            f,
+            "Color {{ #{:>0X}{:>0X}{:>0X} a: {} }}",
+            self.r, self.g, self.b, self.a
+        )
+    }
+}
+
+/// Colors in the style are either an index into the palette, a `"#rrggbb"` hex string
+/// or `{r, g, b}` or `{h, s, v}` objects with an optional alpha `a`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColorDescr {
+    Index(usize),
+    Hex(String),
+    Rgba {
+        r: u8,
+        g: u8,
+        b: u8,
+        #[serde(default = "opaque")]
+        a: f32,
+    },
+    Hsva {
+        h: f64,
+        s: f64,
+        v: f64,
+        #[serde(default = "opaque")]
+        a: f32,
+    },
+}
+
+fn opaque() -> f32 {
+    1.0
+}
+
+impl TryFrom<ColorDescr> for Color {
+    type Error = String;
+
+    fn try_from(value: ColorDescr) -> std::result::Result<Self, Self::Error> {
+        match value {
+            ColorDescr::Index(idx) => PALETTE.with_borrow(|palette| {
+                palette.get(idx).map(|c| c.with_idx(idx)).ok_or(format!(
+                    "color index {idx} is out of range, there are {} colors",
+                    palette.len()
+                ))
+            }),
+            ColorDescr::Hex(s) => {
+                let bytes = s
+                    .strip_prefix('#')
+                    .and_then(|s| hex::decode(s).ok())
+                    .ok_or(format!("'{s}' is not a hex color like #rrggbb"))?;
+                match bytes[..] {
+                    [r, g, b] => Ok(Color::new(r, g, b)),
+                    [r, g, b, a] => Ok(Color::new(r, g, b).with_alpha(a as f32 / 255.0)),
+                    _ => Err(format!("'{s}' is not a hex color like #rrggbb")),
+                }
+            }
+            ColorDescr::Rgba { r, g, b, a } => {
+                Ok(Color::new(r, g, b).with_alpha(unit("a", a as f64)? as f32))
+            }
+            ColorDescr::Hsva { h, s, v, a } => {
+                // `hsv_to_rgb` panics outside of these ranges
+                if !(0.0..360.0).contains(&h) {
+                    return Err(format!("h has to be at least 0 and below 360, not {h}"));
+                }
+                Ok(Color::from_hsv(h, unit("s", s)?, unit("v", v)?)
+                    .with_alpha(unit("a", a as f64)? as f32))
+            }
+        }
+    }
+}
+
+/// `value` if it is between 0 and 1
+fn unit(name: &str, value: f64) -> std::result::Result<f64, String> {
+    if (0.0..=1.0).contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!("{name} has to be between 0 and 1, not {value}"))
     }
 }
 
-const COLORS: LazyLock<Vec<Color>> = LazyLock::new(|| {
+/// built-in palette, used if the style does not define its own
+pub fn default_palette() -> Vec<Color> {
     vec![
         // Siedlungsgebiet
         Color::from_hsv(235.0, 0.18, 0.02),
@@ -57,11 +161,27 @@ const COLORS: LazyLock<Vec<Color>> = LazyLock::new(|| {
         // Wald
         Color::from_hsv(131.0, 0.24, 0.09),
     ]
-});
+}
+
+/// index of the background color in the palette
+pub const BACKGROUND_IDX: usize = 1;
+
+thread_local! {
+    /// palette which color indices refer to while a style is deserialized
+    static PALETTE: RefCell<Vec<Color>> = RefCell::new(default_palette());
+}
+
+/// runs `f` with color indices referring to `palette`
+pub fn with_palette<T>(palette: &[Color], f: impl FnOnce() -> T) -> T {
+    let old = PALETTE.replace(palette.to_vec());
+    let result = f();
+    PALETTE.set(old);
+    result
+}
 
 impl From<usize> for Color {
     fn from(value: usize) -> Color {
-        COLORS[value]
+        PALETTE.with_borrow(|palette| palette[value].with_idx(value))
     }
 }
 
@@ -72,34 +192,170 @@ impl Color {
     }
 
     pub fn new(r: u8, g: u8, b: u8) -> Self {
-        Self { r, g, b }
+        Self {
+            r,
+            g,
+            b,
+            a: 1.0,
+            idx: None,
+        }
+    }
+
+    fn with_idx(mut self, idx: usize) -> Self {
+        self.idx = Some(idx);
+        self
+    }
+
+    /// the color of the palette this color refers to, if it refers to one
+    fn themed(self, palette: &[Color]) -> Self {
+        match self.idx.and_then(|idx| palette.get(idx)) {
+            Some(color) => *color,
+            None => self,
+        }
+    }
+
+    /// linear interpolation, `t = 0` gives `self` and `t = 1` gives `other`
+    pub fn lerp(self, other: Color, t: f32) -> Self {
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Self {
+            r: mix(self.r, other.r),
+            g: mix(self.g, other.g),
+            b: mix(self.b, other.b),
+            a: self.a + (other.a - self.a) * t,
+            idx: self.idx,
+        }
+    }
+
+    pub fn with_alpha(mut self, a: f32) -> Self {
+        self.a = a.clamp(0.0, 1.0);
+        self
     }
 
     fn to_skia(&self) -> Color4f {
-        Color4f::from(skia_safe::Color::from_rgb(self.r, self.g, self.b))
+        self.with_opacity(1.0)
     }
 
     fn with_opacity(&self, opacity: f32) -> Color4f {
         let mut col = Color4f::from(skia_safe::Color::from_rgb(self.r, self.g, self.b));
-        col.a = opacity;
+        col.a = self.a * opacity;
         col
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Copy, Deserialize)]
 pub struct LayerStyle {
     pub fill: Option<Color>,
     pub stroke: Option<(f32, Color)>,
+    /// areas whose bounding box is smaller than this many pixels on screen are not drawn
+    pub min_pixel_size: Option<f32>,
+    /// how the layer is blended with the layers drawn before it
+    #[serde(default)]
+    pub blend: LayerBlend,
+    /// ends of the stroke of lines
+    #[serde(default)]
+    pub cap: LayerCap,
+    /// corners of the stroke of lines
+    #[serde(default)]
+    pub join: LayerJoin,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayerBlend {
+    #[default]
+    Normal,
+    /// darkens, white leaves the map below unchanged
+    Multiply,
+    /// lightens, black leaves the map below unchanged
+    Screen,
+    /// adds the colors, for glowing lines
+    Plus,
+}
+
+impl LayerBlend {
+    fn to_skia(self) -> BlendMode {
+        match self {
+            LayerBlend::Normal => BlendMode::SrcOver,
+            LayerBlend::Multiply => BlendMode::Multiply,
+            LayerBlend::Screen => BlendMode::Screen,
+            LayerBlend::Plus => BlendMode::Plus,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayerCap {
+    #[default]
+    Round,
+    /// ends exactly at the end of the line
+    Butt,
+    /// extends past the end by half the width
+    Square,
+}
+
+impl LayerCap {
+    fn to_skia(self) -> PaintCap {
+        match self {
+            LayerCap::Round => PaintCap::Round,
+            LayerCap::Butt => PaintCap::Butt,
+            LayerCap::Square => PaintCap::Square,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayerJoin {
+    #[default]
+    Round,
+    /// sharp corners
+    Miter,
+    /// corners cut off straight
+    Bevel,
+}
+
+impl LayerJoin {
+    fn to_skia(self) -> PaintJoin {
+        match self {
+            LayerJoin::Round => PaintJoin::Round,
+            LayerJoin::Miter => PaintJoin::Miter,
+            LayerJoin::Bevel => PaintJoin::Bevel,
+        }
+    }
 }
 
 impl LayerStyle {
-    pub fn to_draw_instructions(&self, transform: Transform, opacity: f32) -> DrawInstructions {
-        let Self { fill, stroke } = self;
+    /// false if neither fill nor stroke are set
+    pub fn is_visible(&self) -> bool {
+        self.fill.is_some() || self.stroke.is_some()
+    }
+
+    /// colors referring to the palette are taken from `palette`
+    pub fn to_draw_instructions(
+        &self,
+        transform: Transform,
+        opacity: f32,
+        palette: &[Color],
+        quality: Quality,
+    ) -> DrawInstructions {
+        let Self {
+            fill,
+            stroke,
+            min_pixel_size: _,
+            blend,
+            cap,
+            join,
+        } = self;
         DrawInstructions {
-            fill: *fill,
-            stroke: *stroke,
+            fill: fill.map(|c| c.themed(palette)),
+            stroke: stroke.map(|(width, c)| (width, c.themed(palette))),
             transform,
             opacity,
+            quality,
+            blend: *blend,
+            cap: *cap,
+            join: *join,
         }
     }
 }
@@ -109,6 +365,10 @@ pub struct DrawInstructions {
     pub stroke: Option<(f32, Color)>,
     pub transform: Transform,
     pub opacity: f32,
+    pub quality: Quality,
+    pub blend: LayerBlend,
+    pub cap: LayerCap,
+    pub join: LayerJoin,
 }
 
 impl DrawInstructions {
@@ -119,9 +379,10 @@ impl DrawInstructions {
             paint.set_stroke(true);
             paint.set_style(PaintStyle::Stroke);
             paint.set_stroke_width(line_width);
-            paint.set_stroke_cap(skia_safe::PaintCap::Round);
-            paint.set_stroke_join(skia_safe::PaintJoin::Round);
-            paint.set_anti_alias(true);
+            paint.set_stroke_cap(self.cap.to_skia());
+            paint.set_stroke_join(self.join.to_skia());
+            paint.set_anti_alias(self.quality.anti_alias());
+            paint.set_blend_mode(self.blend.to_skia());
             Some(paint)
         } else {
             None
@@ -133,7 +394,8 @@ impl DrawInstructions {
             let color = color.with_opacity(self.opacity);
             let mut paint = Paint::new(&color, None);
             paint.set_style(PaintStyle::Fill);
-            paint.set_anti_alias(true);
+            paint.set_anti_alias(self.quality.anti_alias());
+            paint.set_blend_mode(self.blend.to_skia());
             Some(paint)
         } else if let Some((width, color)) = self.stroke {
             let color = color.with_opacity(self.opacity);
@@ -141,7 +403,8 @@ impl DrawInstructions {
             paint.set_stroke(true);
             paint.set_style(PaintStyle::Stroke);
             paint.set_stroke_width(width);
-            paint.set_anti_alias(true);
+            paint.set_anti_alias(self.quality.anti_alias());
+            paint.set_blend_mode(self.blend.to_skia());
             Some(paint)
         } else {
             None
@@ -149,12 +412,65 @@ impl DrawInstructions {
     }
 }
 
+/// filled circle of `radius_px` pixels around the world position `center`,
+/// `transform` maps world to screen coordinates and the alpha of `color` is respected
+#[allow(dead_code)] // for markers, no input key places them yet
+pub fn draw_disc(
+    center: Vector,
+    radius_px: f32,
+    color: Color,
+    transform: Transform,
+    quality: Quality,
+    canvas: &Canvas,
+) {
+    let instructions = DrawInstructions {
+        fill: Some(color),
+        stroke: None,
+        transform,
+        opacity: 1.0,
+        quality,
+        blend: LayerBlend::Normal,
+        cap: LayerCap::Round,
+        join: LayerJoin::Round,
+    };
+    let paint = instructions.area_style().expect("fill is set");
+    let center = instructions.transform * center;
+    canvas.draw_circle((center.x, center.y), radius_px, &paint);
+}
+
+/// the point of the pin image which is placed on the position it marks
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[allow(dead_code)] // for symbol markers, the pins of people and checkpoints use their tips
+pub enum Anchor {
+    /// the tip given when loading the pin
+    #[default]
+    Tip,
+    Center,
+    TopLeft,
+    /// fractions of the image width and height from the top left corner
+    Custom(f32, f32),
+}
+
+impl Anchor {
+    /// offset of the anchor from the top left corner of an image of `size` with its tip at `tip`
+    fn offset(self, tip: Vector, size: Vector) -> Vector {
+        match self {
+            Anchor::Tip => tip,
+            Anchor::Center => size * 0.5,
+            Anchor::TopLeft => Vector::zeros(),
+            Anchor::Custom(x, y) => Vector::new(size.x * x, size.y * y),
+        }
+    }
+}
+
 pub struct Pin {
     pin: Image,
     pin_tip_x: f32,
     pin_tip_y: f32,
     img_width: f32,
     img_height: f32,
+    anchor: Anchor,
+    shadow: bool,
 }
 
 impl Pin {
@@ -165,32 +481,60 @@ impl Pin {
             pin: image,
             pin_tip_x,
             pin_tip_y,
+            anchor: Anchor::default(),
+            shadow: true,
         }
     }
 
+    /// places the pin by `anchor` instead of its tip
+    pub fn with_anchor(mut self, anchor: Anchor) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    /// the png `name` in the pins directory
     pub fn load(name: &str, pin_tip_x: f32, pin_tip_y: f32) -> Result<Pin> {
-        let mut file = std::fs::File::open(PINS_PATH.join(format!("{}.png", name)))?;
+        Self::load_path(
+            &PINS_PATH.join(format!("{}.png", name)),
+            pin_tip_x,
+            pin_tip_y,
+        )
+    }
+
+    /// any image file skia can decode, like png, jpg or webp
+    pub fn load_path(path: &path::Path, pin_tip_x: f32, pin_tip_y: f32) -> Result<Pin> {
+        let mut file = std::fs::File::open(path)
+            .map_err(|err| anyhow!("could not open image {}: {err}", path.display()))?;
         let mut buffer = Vec::new();
         file.read_to_end(&mut buffer)?;
 
         let data = skia_safe::Data::new_copy(&buffer);
 
         Ok(Self::new(
-            Image::from_encoded(data).ok_or(anyhow!("Failed to decode image: {}", name))?,
+            Image::from_encoded(data)
+                .ok_or(anyhow!("Failed to decode image: {}", path.display()))?,
             pin_tip_x,
             pin_tip_y,
         ))
     }
 
-    fn draw(&self, target_location: Vector, pin_height: f32, canvas: &mut OwnedCanvas) {
+    /// draws the pin with its anchor at `target_location`, `tint` is blended over the pin
+    fn draw(
+        &self,
+        target_location: Vector,
+        pin_height: f32,
+        tint: Option<Color4f>,
+        quality: Quality,
+        canvas: &Canvas,
+    ) {
         let scale_factor = pin_height / self.img_height as f32;
 
         let scaled_size = Vector::new(self.img_width as f32 * scale_factor, pin_height);
 
-        let offset = Vector::new(
-            self.pin_tip_x as f32 * scale_factor,
-            self.pin_tip_y as f32 * scale_factor,
-        );
+        let offset = self.anchor.offset(
+            Vector::new(self.pin_tip_x, self.pin_tip_y),
+            Vector::new(self.img_width, self.img_height),
+        ) * scale_factor;
 
         let dest = target_location - offset;
         let dest_bottom_right = dest + scaled_size;
@@ -215,54 +559,198 @@ impl Pin {
                 * (off_screen_height - dest_rect.height())
                 / (dest_rect.width() * dest_rect.height());
 
-            let sampling = SamplingOptions::new(FilterMode::Linear, skia_safe::MipmapMode::Linear);
+            let alpha = shown_frac * shown_frac * shown_frac;
+            if let Some(shadow) = &CONFIG.pin_shadow
+                && self.shadow
+            {
+                draw_pin_shadow(shadow, target_location, pin_height, alpha, quality, canvas);
+            }
+
             let mut paint = Paint::default();
-            paint.set_anti_alias(true);
-            paint.set_alpha_f(shown_frac * shown_frac * shown_frac);
+            paint.set_anti_alias(quality.anti_alias());
+            paint.set_alpha_f(alpha);
+            if let Some(tint) = tint {
+                paint.set_color_filter(color_filters::blend(tint, None, BlendMode::SrcATop));
+            }
 
             canvas.draw_image_rect_with_sampling_options(
                 &self.pin,
                 Some((&src_rect, SrcRectConstraint::Fast)),
                 dest_rect,
-                sampling,
+                quality.sampling(),
                 &paint,
             );
         }
     }
 }
 
+/// The pin of a person or checkpoint with the images of `extra_pins` in the config,
+/// they are drawn over it in order, centered at their offset from its tip.
+pub struct PinGroup {
+    pin: Pin,
+    /// image, offset from the tip and height, both in pin heights
+    extras: Vec<(Pin, Vector, f32)>,
+}
+
+/// An image given by name is the png of that name in the pins directory, one with a file
+/// extension is a path, relative ones start in the pins directory.
+pub(crate) fn pin_image_path(image: &str) -> PathBuf {
+    let path = path::Path::new(image);
+    if path.extension().is_some() {
+        PINS_PATH.join(path)
+    } else {
+        PINS_PATH.join(format!("{image}.png"))
+    }
+}
+
+impl PinGroup {
+    /// the pin `name` with the extra images configured for `name`
+    pub fn load(name: &str, pin_tip_x: f32, pin_tip_y: f32) -> Result<Self> {
+        let extras = CONFIG
+            .extra_pins
+            .get(name)
+            .into_iter()
+            .flatten()
+            .map(|extra| {
+                let mut pin = Pin::load_path(&pin_image_path(&extra.image), 0.0, 0.0)?
+                    .with_anchor(Anchor::Center);
+                pin.shadow = false;
+                Ok((
+                    pin,
+                    Vector::new(extra.offset.0, extra.offset.1),
+                    extra.height,
+                ))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            pin: Pin::load(name, pin_tip_x, pin_tip_y)?,
+            extras,
+        })
+    }
+
+    /// `tint` only applies to the pin, not to the extra images
+    fn draw(
+        &self,
+        target_location: Vector,
+        pin_height: f32,
+        tint: Option<Color4f>,
+        quality: Quality,
+        canvas: &Canvas,
+    ) {
+        self.pin
+            .draw(target_location, pin_height, tint, quality, canvas);
+        for (extra, offset, height) in &self.extras {
+            extra.draw(
+                target_location + offset * pin_height,
+                pin_height * height,
+                None,
+                quality,
+                canvas,
+            );
+        }
+    }
+}
+
+/// soft ellipse below the tip of a pin
+fn draw_pin_shadow(
+    shadow: &PinShadow,
+    target_location: Vector,
+    pin_height: f32,
+    alpha: f32,
+    quality: Quality,
+    canvas: &Canvas,
+) {
+    let center = target_location + Vector::new(shadow.offset.0, shadow.offset.1) * pin_height;
+    let (half_width, half_height) = (
+        shadow.width * pin_height / 2.0,
+        shadow.width * pin_height / 6.0,
+    );
+    let oval = skia_safe::Rect::new(
+        center.x - half_width,
+        center.y - half_height,
+        center.x + half_width,
+        center.y + half_height,
+    );
+    let mut paint = Paint::default();
+    paint.set_anti_alias(quality.anti_alias());
+    paint.set_color4f(Color4f::new(0.0, 0.0, 0.0, shadow.opacity * alpha), None);
+    paint.set_mask_filter(MaskFilter::blur(
+        BlurStyle::Normal,
+        shadow.softness * pin_height,
+        false,
+    ));
+    canvas.draw_oval(oval, &paint);
+}
+
+/// length of the equator in meters, web mercator uses the equatorial radius
+const EARTH_CIRCUMFERENCE: f64 = 40_075_016.686;
+
 #[derive(Copy, Clone)]
 pub struct ScenePos {
-    pub center: Vector,
+    /// in `f64`, at high zoom the centers of a slow pan in `f32` jump by pixels between frames
+    pub center: (f64, f64),
     pub zoom: f32,
     pub time: u32,
 }
 
 impl ScenePos {
     pub fn new(center: Vector, zoom: f32, time: u32) -> Self {
+        Self::precise((center.x as f64, center.y as f64), zoom, time)
+    }
+
+    /// like `new` with the full precision of `f64`
+    pub fn precise(center: (f64, f64), zoom: f32, time: u32) -> Self {
         Self { center, zoom, time }
     }
 
+    /// the center rounded to `f32`, precise enough for everything but the camera transforms
+    pub fn rounded_center(&self) -> Vector {
+        Vector::new(self.center.0 as f32, self.center.1 as f32)
+    }
+
+    /// Center and the largest zoom at which all of `bounds` is on screen. If its aspect ratio
+    /// differs from the screen it is centered with map around it. `None` for a single point.
+    pub fn fit(bounds: &Rect) -> Option<(Vector, f32)> {
+        let size = Vector::new(bounds.x_max - bounds.x_min, bounds.y_max - bounds.y_min);
+        if size.x <= 0.0 && size.y <= 0.0 {
+            return None;
+        }
+        let scale = (WIDTH as f32 / size.x).min(HEIGHT as f32 / size.y);
+        let center = Vector::new(bounds.x_min, bounds.y_min) + size * 0.5;
+        Some((center, (scale / TILE_SIZE as f32).log2()))
+    }
+
+    /// in `f64`, in `f32` the scaled center is off by many pixels at high zoom
     pub fn world_to_screen(&self) -> Transform {
-        let scale = 2f32.powf(self.zoom) * TILE_SIZE as f32;
-        let scaled_center = self.center * scale;
-        let screen_center = Vector::new(WIDTH as f32 / 2.0, HEIGHT as f32 / 2.0);
-        let translation = screen_center - scaled_center;
-        Transform::new(scale, translation)
+        let scale = 2f64.powf(self.zoom as f64) * TILE_SIZE as f64;
+        Transform::precise(
+            scale,
+            (
+                WIDTH as f64 / 2.0 - self.center.0 * scale,
+                HEIGHT as f64 / 2.0 - self.center.1 * scale,
+            ),
+        )
     }
 
     pub fn screen_to_world(&self) -> Transform {
-        let scale = 2f32.powf(-self.zoom) / TILE_SIZE as f32;
-        let scaled_screen_center = scale * Vector::new(WIDTH as f32 / 2.0, HEIGHT as f32 / 2.0);
-        Transform::new(scale, -scaled_screen_center + self.center)
+        self.world_to_screen().invert()
     }
 
+    /// The corner of the tile is taken relative to the camera before scaling,
+    /// scaling tile indices first gives values too large for `f32` at high zoom
+    /// and the edges of neighboring tiles would not meet.
     pub fn tile_to_screen(&self, tile: TileDescr) -> Transform {
-        let scale = TILE_SIZE as f32 * 2f32.powf(self.zoom - tile.z as f32);
-        let translation = (scale * Vector::new(tile.x as f32, tile.y as f32))
-            - (TILE_SIZE as f32 * 2f32.powf(self.zoom) * self.center)
-            + Vector::new(WIDTH as f32 / 2.0, HEIGHT as f32 / 2.0);
-        Transform::new(scale, translation)
+        let tiles = 2f64.powi(tile.z as i32);
+        let corner = (
+            tile.x as f64 / tiles - self.center.0,
+            tile.y as f64 / tiles - self.center.1,
+        );
+        let world_scale = TILE_SIZE as f64 * 2f64.powf(self.zoom as f64);
+        let translation = Vector::new(
+            (corner.0 * world_scale) as f32,
+            (corner.1 * world_scale) as f32,
+        ) + Vector::new(WIDTH as f32 / 2.0, HEIGHT as f32 / 2.0);
+        Transform::new((world_scale / tiles) as f32, translation)
     }
 
     pub fn world_min(&self) -> Vector {
@@ -272,221 +760,1084 @@ impl ScenePos {
     pub fn world_max(&self) -> Vector {
         self.screen_to_world() * Vector::new(WIDTH as f32, HEIGHT as f32)
     }
+
+    /// real world meters covered by one pixel at the center of the screen,
+    /// mercator stretches the map by `1 / cos(latitude)`
+    #[allow(dead_code)] // for features measuring real world distances
+    pub fn meters_per_pixel(&self) -> f32 {
+        let (lat, _) = vec_to_lat_long(self.rounded_center());
+        let pixels = 2f64.powf(self.zoom as f64) * TILE_SIZE as f64;
+        (EARTH_CIRCUMFERENCE * (lat as f64).to_radians().cos() / pixels) as f32
+    }
 }
 
-pub struct Frame {
-    scene_pos: ScenePos,
-    people: Vec<String>,
-    pin_height: f32,
-    checkpoints: bool,
+/// optional post effects, all of them are off by default
+#[derive(Clone, Copy, Default)]
+pub struct Effects {
+    /// opacity of the black at the corners of the frame
+    pub vignette: Option<f32>,
+    /// sigma in pixels of the blur applied to the map below the pins
+    pub blur: Option<f32>,
 }
 
-impl Frame {
-    pub fn render_background(&self, canvas: &mut OwnedCanvas) {
-        canvas.clear(COLORS[1].to_skia());
-        let tiles = WORLD.get_tiles_at(self.scene_pos);
-        let map = WORLD.map.read().expect("RwLock not poisoned");
-        match tiles {
-            OneOrTwo::One(tiles) => {
-                let tiles: Option<Vec<_>> = tiles.iter().map(|tile| map.get_tile(*tile)).collect();
-                if tiles.is_none() {
-                    error!("some tiles needed were not loaded");
-                    return;
-                }
-                let tiles = tiles.expect("checked above");
-                for id in 0..=SORTERS.max_layer_idx() {
-                    for tile in &tiles {
-                        if let Some(layer) = tile.get_layer(id) {
-                            layer.draw(canvas, self.scene_pos.tile_to_screen(tile.descr), 1.0)
-                        }
-                    }
-                }
-            }
-            OneOrTwo::Two(less_detail, more_detail) => {
-                let less_detail: Option<Vec<_>> =
-                    less_detail.iter().map(|tile| map.get_tile(*tile)).collect();
-                if less_detail.is_none() {
-                    error!("some tiles needed were not loaded");
-                }
-                let less_detail = less_detail.expect("checked above");
-
-                canvas.save_layer(&SaveLayerRec::default());
-                for id in 0..=SORTERS.max_layer_idx() {
-                    for tile in &less_detail {
-                        if let Some(layer) = tile.get_layer(id) {
-                            let opacity = fade_out_function(self.scene_pos.zoom.fract());
-                            layer.draw(canvas, self.scene_pos.tile_to_screen(tile.descr), opacity)
-                        }
-                    }
-                }
-                canvas.restore();
+impl Effects {
+    fn draw_vignette(&self, canvas: &Canvas) {
+        let Some(intensity) = self.vignette else {
+            return;
+        };
+        let center = (WIDTH as f32 / 2.0, HEIGHT as f32 / 2.0);
+        let radius = Vector::new(center.0, center.1).norm();
+        let colors = [
+            skia_safe::Color::TRANSPARENT,
+            skia_safe::Color::from_argb((intensity.clamp(0.0, 1.0) * 255.0) as u8, 0, 0, 0),
+        ];
+        let mut paint = Paint::default();
+        paint.set_shader(Shader::radial_gradient(
+            center,
+            radius,
+            &colors,
+            Some(&[0.4, 1.0][..]),
+            TileMode::Clamp,
+            None,
+            None,
+        ));
+        canvas.draw_paint(&paint);
+    }
+}
 
-                let more_detail: Option<Vec<_>> =
-                    more_detail.iter().map(|tile| map.get_tile(*tile)).collect();
-                if more_detail.is_none() {
-                    error!("some tiles needed were not loaded");
-                }
-                let more_detail = more_detail.expect("checked above");
-                canvas.save_layer(&SaveLayerRec::default());
-                for id in 0..=SORTERS.max_layer_idx() {
-                    for tile in &more_detail {
-                        if let Some(layer) = tile.get_layer(id) {
-                            let opacity = fade_in_function(self.scene_pos.zoom.fract());
-                            layer.draw(canvas, self.scene_pos.tile_to_screen(tile.descr), opacity)
-                        }
-                    }
-                }
-                canvas.restore();
-            }
-        }
+/// optional information drawn on top of the frame, all of them are off by default
+#[derive(Clone, Copy, Default)]
+pub struct Overlays {
+    /// ranking of the shown people by distance traveled
+    pub leaderboard: bool,
+    /// lines at round latitudes and longitudes over the map
+    pub graticule: bool,
+    /// the whole track of every shown person as a faint line
+    pub routes: bool,
+    /// bar along the bottom showing how much of the event time has passed
+    pub progress: bool,
+    /// small map in a corner with a dot at the center of the camera
+    pub overview: Option<Overview>,
+}
+
+/// the part of the world shown by the overview inset
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Overview {
+    pub center: Vector,
+    pub zoom: f32,
+}
+
+/// title card drawn over the frame
+#[derive(Clone)]
+pub struct Title {
+    pub text: String,
+    /// from 0 to 1, at 1 the map is not drawn at all
+    pub opacity: f32,
+}
+
+/// seconds of video the title card is shown before the map
+const TITLE_DURATION: f32 = 2.0;
+/// seconds of video over which the title card fades into the map
+const TITLE_FADE: f32 = 0.5;
+
+/// prepends the title card to `frames` and fades it out over their start
+/// number of frames of the title card shown before the animation
+fn title_frames(title: Option<&str>) -> usize {
+    match title {
+        Some(_) => (TITLE_DURATION * FRAME_RATE).round() as usize,
+        None => 0,
     }
+}
 
-    pub fn render(self) -> Bitmap {
-        let info = ImageInfo::new(
-            (WIDTH as i32, HEIGHT as i32),
-            ColorType::N32,
-            skia_safe::AlphaType::Opaque,
-            None,
-        );
-        let mut bitmap = Bitmap::new();
-        if !bitmap.set_info(&info, None) {
-            panic!("could not set image info while rendering")
-        };
-        bitmap.alloc_pixels();
-        let mut canvas =
-            Canvas::from_bitmap(&bitmap, None).expect("Failed to create canvas from bitmap");
+fn with_title<'a>(
+    frames: impl Iterator<Item = Frame> + 'a,
+    title: Option<&'a str>,
+) -> impl Iterator<Item = Frame> + 'a {
+    let mut frames = frames.peekable();
+    let card = move |opacity| {
+        title.map(|text| Title {
+            text: text.to_string(),
+            opacity,
+        })
+    };
+    let intro = match (card(1.0), frames.peek()) {
+        (Some(card), Some(first)) => Some(Frame {
+            title: Some(card),
+            ..first.clone()
+        }),
+        _ => None,
+    };
+    let intro_frames = title_frames(title);
+    let fade_frames = (TITLE_FADE * FRAME_RATE).round() as usize;
+    intro
+        .into_iter()
+        .flat_map(move |intro| std::iter::repeat_n(intro, intro_frames))
+        .chain(frames.enumerate().map(move |(i, mut frame)| {
+            if i < fade_frames {
+                frame.title = card(1.0 - (i + 1) as f32 / (fade_frames + 1) as f32);
+            }
+            frame
+        }))
+}
 
-        self.render_background(&mut canvas);
+/// corner of the frame an overlay is placed in
+#[derive(Clone, Copy, PartialEq, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
 
-        let people = if self.people.is_empty() {
-            PEOPLE.iter().map(|s| s.to_string()).collect()
-        } else {
-            self.people
+impl Corner {
+    /// top left position of an overlay of `size` placed in this corner,
+    /// inset from the edges by the safe area of the config
+    pub fn anchor(&self, size: Vector) -> Vector {
+        let inset = CONFIG.safe_area * HEIGHT as f32;
+        let x = match self {
+            Corner::TopLeft | Corner::BottomLeft => inset,
+            Corner::TopRight | Corner::BottomRight => WIDTH as f32 - inset - size.x,
         };
+        let y = match self {
+            Corner::TopLeft | Corner::TopRight => inset,
+            Corner::BottomLeft | Corner::BottomRight => HEIGHT as f32 - inset - size.y,
+        };
+        Vector::new(x, y)
+    }
+}
 
-        if self.checkpoints {
-            for (_name, (position, pin)) in WORLD.checkpoints.iter() {
-                pin.draw(
-                    self.scene_pos.world_to_screen() * position,
-                    self.pin_height,
-                    &mut canvas,
-                );
-            }
+/// Animation of the pins of people, a pure function of the time in the video
+/// so frames can be rendered in any order.
+#[derive(Clone, Copy, Default)]
+pub struct PinAnimation {
+    /// pins drop in when their person first appears
+    pub drop: bool,
+    /// pins bob up and down while the person does not move
+    pub bob: bool,
+    /// seconds of event time per second of video, 0 if the event time stands still
+    time_rate: f32,
+}
+
+/// seconds of video a pin takes to drop in
+const DROP_DURATION: f32 = 0.6;
+/// seconds of video between the drops of two consecutive pins
+const DROP_STAGGER: f32 = 0.1;
+/// seconds of video for one bob
+const BOB_PERIOD: f32 = 2.0;
+/// bob amplitude as a fraction of the pin height
+const BOB_AMPLITUDE: f32 = 0.04;
+/// the movement of a person is measured over this many seconds of track time
+const STATIONARY_WINDOW: u32 = 10 * 60;
+/// pins bob fully while their person moved less than the first number of pixels
+/// in the window and not at all above the second, in between the bob fades
+const BOB_FADE_PIXELS: (f32, f32) = (1.0, 8.0);
+
+impl PinAnimation {
+    /// the animation in a video whose event time advances `time_rate` seconds per second
+    pub fn with_time_rate(self, time_rate: f32) -> Self {
+        Self { time_rate, ..self }
+    }
+
+    /// Seconds into the video at which the event time reaches `event_time`, seen from a frame
+    /// at event time `time` and `video_time` seconds into the video. Times before the start are
+    /// 0, times the video never reaches are infinite.
+    fn video_time_of(&self, event_time: u32, time: u32, video_time: f32) -> f32 {
+        let ahead = (event_time as i64 - time as i64) as f32;
+        if self.time_rate > 0.0 {
+            (video_time + ahead / self.time_rate).max(0.0)
+        } else if ahead > 0.0 {
+            f32::INFINITY
+        } else {
+            0.0
         }
+    }
 
-        for name in people {
-            let track = WORLD
-                .get_track(&name)
-                .expect("here the list of people is valid");
-            if let Some(position) = track.get_position(self.scene_pos.time) {
-                track.pin.draw(
-                    self.scene_pos.world_to_screen() * position,
-                    self.pin_height,
-                    &mut canvas,
-                );
-            }
+    /// how much a person bobs, 1 if they moved by at most the first of `BOB_FADE_PIXELS`
+    /// in the window and 0 beyond the second
+    fn stillness(moved_pixels: f32) -> f32 {
+        1.0 - smoother_step(moved_pixels, BOB_FADE_PIXELS.0, BOB_FADE_PIXELS.1)
+    }
+
+    /// Returns the factor for the pin height and the vertical offset in pixels
+    /// of the `idx`-th pin at `video_time` seconds into the video.
+    /// Its person appears at `appeared` seconds and bobs by `stillness` (0 to 1).
+    fn pose(
+        &self,
+        video_time: f32,
+        appeared: f32,
+        idx: usize,
+        stillness: f32,
+        pin_height: f32,
+    ) -> (f32, f32) {
+        let mut scale = 1.0;
+        let mut offset = 0.0;
+        if self.drop {
+            let progress = smoother_step(
+                video_time - appeared - idx as f32 * DROP_STAGGER,
+                0.0,
+                DROP_DURATION,
+            );
+            scale = progress;
+            offset -= (1.0 - progress) * pin_height * 0.5;
         }
-        bitmap
+        if self.bob {
+            let phase = std::f32::consts::TAU * video_time / BOB_PERIOD + idx as f32;
+            offset -= (phase.sin() * 0.5 + 0.5) * pin_height * BOB_AMPLITUDE * stillness;
+        }
+        (scale, offset)
     }
 }
 
-pub trait Renderable {
-    fn get_file_name(&self) -> PathBuf;
-    fn name(&self) -> &str;
-    fn make_file(self: Box<Self>) -> Result<()>;
+/// `crop` with its edges rounded to whole pixels, `None` if no pixel is left
+pub(crate) fn round_crop(crop: Rect) -> Option<Rect> {
+    let rounded = Rect::new(
+        crop.x_min.round(),
+        crop.x_max.round(),
+        crop.y_min.round(),
+        crop.y_max.round(),
+    );
+    (rounded.width() >= 1.0 && rounded.height() >= 1.0).then_some(rounded)
 }
 
-pub struct StillFrame {
-    name: String,
-    center: Vector,
-    zoom: f32,
-    time: u32,
+#[derive(Clone)]
+pub struct Frame {
+    scene_pos: ScenePos,
     people: Vec<String>,
-    checkpoints: bool,
     pin_height: f32,
+    checkpoints: bool,
+    effects: Effects,
+    overlays: Overlays,
+    /// seconds since the start of the video
+    video_time: f32,
+    pin_animation: PinAnimation,
+    quality: Quality,
+    title: Option<Title>,
+    /// part of the screen in pixels which is rendered, the whole screen if `None`
+    crop: Option<Rect>,
 }
 
-impl Renderable for StillFrame {
-    fn get_file_name(&self) -> PathBuf {
-        OUT_PATH.join(format!("{}.png", self.name)).to_path_buf()
+impl Frame {
+    /// Renders only the pixels of `crop` in screen coordinates, it may reach past the
+    /// `WIDTH` by `HEIGHT` screen. Its edges are rounded to whole pixels so neighboring crops
+    /// tile the screen without seams, an error if no pixel is left.
+    /// A blur is clamped at the edges of the crop instead of the screen.
+    pub fn with_crop(mut self, crop: Rect) -> Result<Self, RenderError> {
+        self.crop = Some(round_crop(crop).ok_or_else(|| {
+            RenderError::Other(anyhow!(
+                "crop from ({}, {}) to ({}, {}) contains no pixels",
+                crop.x_min,
+                crop.y_min,
+                crop.x_max,
+                crop.y_max
+            ))
+        })?);
+        Ok(self)
     }
 
-    fn name(&self) -> &str {
-        &self.name
+    /// the corners of the rendered part of the world, the crop or the whole screen
+    fn world_extent(&self) -> (Vector, Vector) {
+        match self.crop {
+            Some(crop) => {
+                let screen_to_world = self.scene_pos.screen_to_world();
+                (
+                    screen_to_world * Vector::new(crop.x_min, crop.y_min),
+                    screen_to_world * Vector::new(crop.x_max, crop.y_max),
+                )
+            }
+            None => (self.scene_pos.world_min(), self.scene_pos.world_max()),
+        }
     }
 
-    fn make_file(self: Box<Self>) -> Result<()> {
-        let frame = Frame {
-            scene_pos: ScenePos::new(self.center, self.zoom, self.time),
-            people: self.people.clone(),
-            checkpoints: self.checkpoints,
-            pin_height: self.pin_height,
-        };
-        info!("loading tiles for {}", self.name);
-        WORLD.load_tiles_at(frame.scene_pos)?;
-        info!("finished loading tiles for {}", self.name);
-        let image: skia_safe::Image = frame.render().as_image();
-        let mut file = std::fs::File::create(&self.get_file_name())?;
-        skia_safe::png_encoder::encode(
-            &image.peek_pixels().expect("failed to get pixels."),
-            &mut file,
-            &skia_safe::png_encoder::Options::default(),
-        );
-        return Ok(());
+    /// the frame whose map is drawn scaled down in the overview inset
+    fn overview(&self) -> Option<Frame> {
+        let overview = self.overlays.overview?;
+        Some(Frame {
+            scene_pos: ScenePos::new(overview.center, overview.zoom, self.scene_pos.time),
+            effects: Effects::default(),
+            overlays: Overlays::default(),
+            title: None,
+            crop: None,
+            ..self.clone()
+        })
     }
-}
-
-pub struct Fixed {
-    name: String,
-    center: Vector,
-    zoom: (f32, f32),
-    time: (u32, u32),
-    duration_s: f32,
-    people: Vec<String>,
-    checkpoints: bool,
-    pin_height: f32,
-}
 
-impl Fixed {
-    pub fn as_frames(&self) -> Vec<Frame> {
-        let Fixed {
-            name: _,
-            center,
-            zoom,
-            time,
-            duration_s,
-            people,
-            pin_height,
-            checkpoints,
-        } = self;
-        let frames_tot = (duration_s * FRAME_RATE).round() as u32;
-        let mut frames = Vec::new();
-        for i in 0..frames_tot {
-            let zoom = zoom.0 + (zoom.1 - zoom.0) * (i as f32 / frames_tot as f32);
-            let time = time.0
-                + (((time.1 - time.0) as f32) * (i as f32 / frames_tot as f32)).round() as u32;
-            frames.push(Frame {
-                scene_pos: ScenePos::new(*center, zoom, time),
-                people: people.clone(),
-                checkpoints: *checkpoints,
-                pin_height: *pin_height,
-            });
+    /// the tiles of the frame and of its overview inset
+    fn tiles(&self) -> Vec<TileDescr> {
+        let mut tiles = tiles_in(self.scene_pos.zoom, self.world_extent());
+        if let Some(overview) = self.overview() {
+            tiles.extend(tiles_for(overview.scene_pos));
         }
-        frames
+        tiles
     }
-}
 
-impl Renderable for Fixed {
-    fn name(&self) -> &str {
-        &self.name
+    fn draw_overview(&self, world: &World, canvas: &Canvas) {
+        let Some(overview) = self.overview() else {
+            return;
+        };
+        let config = &CONFIG.overview;
+        let rect = overlay::overview_rect(config);
+        canvas.save();
+        canvas.clip_rect(rect, None, Some(self.quality.anti_alias()));
+        canvas.translate((rect.left, rect.top));
+        canvas.scale((config.size, config.size));
+        overview.render_background(world, canvas);
+        canvas.restore();
+        let marker = overview.scene_pos.world_to_screen() * self.scene_pos.rounded_center();
+        overlay::draw_overview_frame(
+            overlay::overview_pos(marker, config),
+            config,
+            self.quality,
+            canvas,
+        );
     }
 
-    fn get_file_name(&self) -> PathBuf {
-        OUT_PATH.join(format!("{}.mp4", self.name)).to_path_buf()
+    /// the listed people, everyone if none are listed
+    fn shown_people(&self) -> Vec<&str> {
+        if self.people.is_empty() {
+            PEOPLE.to_vec()
+        } else {
+            self.people.iter().map(String::as_str).collect()
+        }
     }
 
-    fn make_file(self: Box<Self>) -> Result<()> {
-        make_video(self.as_frames(), &self.name, self.get_file_name())
+    /// shown people whose track has no position at the time of the frame, their pins are
+    /// not drawn. Empty if the title card covers the map.
+    fn people_without_position<'a>(&'a self, world: &World) -> Vec<&'a str> {
+        if self
+            .title
+            .as_ref()
+            .is_some_and(|title| title.opacity >= 1.0)
+        {
+            return Vec::new();
+        }
+        self.shown_people()
+            .into_iter()
+            .filter(|name| {
+                world
+                    .get_track(name)
+                    .is_none_or(|track| track.get_position(self.scene_pos.time).is_none())
+            })
+            .collect()
+    }
+
+    /// width and height of the rendered image in pixels
+    fn size(&self) -> (i32, i32) {
+        match self.crop {
+            Some(crop) => (crop.width() as i32, crop.height() as i32),
+            None => (WIDTH as i32, HEIGHT as i32),
+        }
+    }
+
+    /// identifies the rendered image, frames with the same key look the same
+    fn cache_key(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(RESOURCES_VERSION.as_bytes());
+        for x in [self.scene_pos.center.0, self.scene_pos.center.1] {
+            hasher.update(x.to_le_bytes());
+        }
+        for x in [
+            self.scene_pos.zoom,
+            self.pin_height,
+            self.video_time,
+            self.effects.vignette.unwrap_or(-1.0),
+            self.effects.blur.unwrap_or(-1.0),
+            self.pin_animation.time_rate,
+        ] {
+            hasher.update(x.to_le_bytes());
+        }
+        hasher.update(self.scene_pos.time.to_le_bytes());
+        hasher.update([
+            self.checkpoints as u8,
+            self.pin_animation.drop as u8,
+            self.pin_animation.bob as u8,
+            self.quality as u8,
+            self.overlays.leaderboard as u8,
+            self.overlays.graticule as u8,
+            self.overlays.routes as u8,
+            self.overlays.progress as u8,
+        ]);
+        if let Some(overview) = self.overlays.overview {
+            for x in [overview.center.x, overview.center.y, overview.zoom] {
+                hasher.update(x.to_le_bytes());
+            }
+        }
+        for person in &self.people {
+            hasher.update(person.as_bytes());
+            hasher.update([0]);
+        }
+        if let Some(title) = &self.title {
+            hasher.update(title.opacity.to_le_bytes());
+            hasher.update(title.text.as_bytes());
+        }
+        if let Some(crop) = self.crop {
+            for x in [crop.x_min, crop.x_max, crop.y_min, crop.y_max] {
+                hasher.update(x.to_le_bytes());
+            }
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    /// the palette of the style, shifted towards the night palette if configured
+    fn palette(&self, world: &World) -> Vec<Color> {
+        let day = world.style.palette();
+        let Some(day_night) = &CONFIG.day_night else {
+            return day.to_vec();
+        };
+        let day = match &day_night.day_palette {
+            Some(palette) if palette.len() > BACKGROUND_IDX => palette,
+            _ => day,
+        };
+        let night = 1.0 - day_night.daylight(self.scene_pos.time);
+        day.iter()
+            .enumerate()
+            .map(|(i, color)| match day_night.night_palette.get(i) {
+                Some(night_color) => color.lerp(*night_color, night),
+                None => *color,
+            })
+            .collect()
+    }
+
+    pub fn render_background(&self, world: &World, canvas: &Canvas) {
+        if !self.draw_tiles(world, canvas) {
+            MISSING_TILES.record();
+        }
+    }
+
+    /// Draws the map, returns false if some of the tiles were not loaded.
+    /// While cross-fading a level with missing tiles is left out and the other is drawn fully.
+    fn draw_tiles(&self, world: &World, canvas: &Canvas) -> bool {
+        let palette = self.palette(world);
+        canvas.clear(palette[BACKGROUND_IDX].to_skia());
+        let map = world.map.read().expect("RwLock not poisoned");
+        let loaded = |tiles: &[TileDescr]| -> Option<Vec<&MapData>> {
+            let tiles: Option<Vec<_>> = tiles.iter().map(|tile| map.get_tile(*tile)).collect();
+            Some(tiles?.into_iter().filter(|tile| !tile.is_empty()).collect())
+        };
+        match tiles_at(
+            self.scene_pos.zoom,
+            self.world_extent(),
+            CONFIG.zoom_cross_fade,
+        ) {
+            OneOrTwo::One(tiles) => {
+                let Some(tiles) = loaded(&tiles) else {
+                    return false;
+                };
+                self.draw_level(&tiles, 1.0, world, &palette, canvas);
+                true
+            }
+            OneOrTwo::Two(less_detail, more_detail) => {
+                let (less_detail, more_detail) = (loaded(&less_detail), loaded(&more_detail));
+                let fade = self.scene_pos.zoom.fract();
+                let (less_opacity, more_opacity) = match (&less_detail, &more_detail) {
+                    (Some(_), Some(_)) => (fade_out_function(fade), fade_in_function(fade)),
+                    _ => (1.0, 1.0),
+                };
+                for (tiles, opacity) in [(&less_detail, less_opacity), (&more_detail, more_opacity)]
+                {
+                    let Some(tiles) = tiles else {
+                        continue;
+                    };
+                    canvas.save_layer(&SaveLayerRec::default());
+                    self.draw_level(tiles, opacity, world, &palette, canvas);
+                    canvas.restore();
+                }
+                less_detail.is_some() && more_detail.is_some()
+            }
+        }
+    }
+
+    /// draws the layers of tiles of one zoom level in the order of the style
+    fn draw_level(
+        &self,
+        tiles: &[&MapData],
+        opacity: f32,
+        world: &World,
+        palette: &[Color],
+        canvas: &Canvas,
+    ) {
+        let ripple = CONFIG.water_ripple.as_ref().and_then(|ripple| {
+            let layers: Vec<u8> = ripple
+                .layers
+                .iter()
+                .filter_map(|name| world.style.get_layer_idx(name))
+                .collect();
+            Some((self.ripple_paint(ripple)?, layers))
+        });
+        for id in 0..=world.style.max_layer_idx() {
+            let ripple = ripple.as_ref().filter(|(_, layers)| layers.contains(&id));
+            if let Some((paint, _)) = ripple {
+                canvas.save_layer(&SaveLayerRec::default().paint(paint));
+            }
+            for tile in tiles {
+                if let Some(layer) = tile.get_layer(id) {
+                    layer.draw(
+                        canvas,
+                        self.scene_pos.tile_to_screen(tile.descr),
+                        opacity,
+                        palette,
+                        self.quality,
+                    )
+                }
+            }
+            if ripple.is_some() {
+                canvas.restore();
+            }
+        }
+    }
+
+    /// displaces a layer by noise which drifts with the video time, the same for every tile
+    fn ripple_paint(&self, ripple: &WaterRipple) -> Option<Paint> {
+        let frequency = 1.0 / ripple.wavelength;
+        let noise = shaders::turbulence((frequency, frequency), 2, 0.0, None)?
+            .with_local_matrix(&Matrix::translate((ripple.speed * self.video_time, 0.0)));
+        let mut paint = Paint::default();
+        // the displacement is `scale` times the channel minus one half
+        paint.set_image_filter(image_filters::displacement_map(
+            (ColorChannel::R, ColorChannel::G),
+            2.0 * ripple.amplitude,
+            image_filters::shader(noise, None),
+            None,
+            None,
+        )?);
+        Some(paint)
+    }
+
+    pub fn render(self, world: &World) -> Bitmap {
+        let bitmap = new_bitmap(self.size());
+        self.render_into(world, &bitmap);
+        bitmap
+    }
+
+    /// draws the frame onto `bitmap` which has to be `WIDTH` by `HEIGHT` or the size of the crop,
+    /// everything previously on it is cleared
+    pub fn render_into(&self, world: &World, bitmap: &Bitmap) {
+        if let Some(factor) = self.supersample() {
+            let (width, height) = self.size();
+            let large = new_bitmap(supersampled_size((width, height), factor));
+            let canvas =
+                Canvas::from_bitmap(&large, None).expect("Failed to create canvas from bitmap");
+            canvas.scale((factor, factor));
+            self.draw(world, &canvas);
+            downscale(&large.as_image(), bitmap);
+            return;
+        }
+        let canvas =
+            Canvas::from_bitmap(bitmap, None).expect("Failed to create canvas from bitmap");
+        self.draw(world, &canvas);
+    }
+
+    /// the factor of the config if it is larger than 1, drafts are never supersampled
+    fn supersample(&self) -> Option<f32> {
+        (self.quality == Quality::Final && CONFIG.supersample > 1.0).then_some(CONFIG.supersample)
+    }
+
+    /// draws the map and pins onto a `WIDTH` by `HEIGHT` canvas or one of the size of the crop
+    pub fn draw(&self, world: &World, canvas: &Canvas) {
+        if let Some(crop) = self.crop {
+            canvas.translate((-crop.x_min, -crop.y_min));
+        }
+        if let Some(title) = &self.title
+            && title.opacity >= 1.0
+        {
+            overlay::draw_title(&title.text, 1.0, self.quality, canvas);
+            return;
+        }
+
+        if let Some(sigma) = self.effects.blur {
+            let mut paint = Paint::default();
+            paint.set_image_filter(image_filters::blur(
+                (sigma, sigma),
+                TileMode::Clamp,
+                None,
+                None,
+            ));
+            canvas.save_layer(&SaveLayerRec::default().paint(&paint));
+            self.render_background(world, canvas);
+            canvas.restore();
+        } else {
+            self.render_background(world, canvas);
+        }
+
+        if self.overlays.graticule {
+            overlay::draw_graticule(self.scene_pos, &CONFIG.graticule, self.quality, canvas);
+        }
+
+        let people = self.shown_people();
+        let pin_height = CONFIG.pin_height_limits.clamp(self.pin_height);
+
+        if self.overlays.routes {
+            overlay::draw_routes(&people, world, self.scene_pos, self.quality, canvas);
+        }
+
+        if self.checkpoints {
+            // the rings burst out below the pins
+            if let Some(config) = &CONFIG.checkpoint_events {
+                for (position, time) in &world.checkpoint_events {
+                    overlay::draw_checkpoint_event(
+                        self.scene_pos.world_to_screen() * position,
+                        self.scene_pos.time.abs_diff(*time),
+                        pin_height,
+                        config,
+                        self.quality,
+                        canvas,
+                    );
+                }
+            }
+            for (_name, (position, pin)) in world.checkpoints.iter() {
+                pin.draw(
+                    self.scene_pos.world_to_screen() * position,
+                    pin_height,
+                    None,
+                    self.quality,
+                    canvas,
+                );
+            }
+        }
+
+        for (idx, name) in people.iter().copied().enumerate() {
+            let track = world
+                .get_track(name)
+                .expect("here the list of people is valid");
+            if let Some(position) = track.get_position(self.scene_pos.time) {
+                let screen_pos = self.scene_pos.world_to_screen() * position;
+                let stillness = track
+                    .get_position(self.scene_pos.time.saturating_sub(STATIONARY_WINDOW))
+                    .map_or(0.0, |before| {
+                        PinAnimation::stillness(
+                            (self.scene_pos.world_to_screen() * before - screen_pos).norm(),
+                        )
+                    });
+                // the person appears with the first point of their track
+                let appeared = track.points.first().map_or(0.0, |first| {
+                    self.pin_animation.video_time_of(
+                        first.time,
+                        self.scene_pos.time,
+                        self.video_time,
+                    )
+                });
+                let (scale, offset) =
+                    self.pin_animation
+                        .pose(self.video_time, appeared, idx, stillness, pin_height);
+                if scale <= 0.0 {
+                    continue;
+                }
+                let tint = match (&CONFIG.speed_tint, &CONFIG.elevation_tint) {
+                    (Some(tint), _) => track
+                        .get_velocity(self.scene_pos.time)
+                        .map(|speed| tint.color(speed)),
+                    (None, Some(tint)) => track
+                        .get_altitude(self.scene_pos.time)
+                        .map(|altitude| tint.color(altitude)),
+                    (None, None) => None,
+                };
+                track.pin.draw(
+                    screen_pos + Vector::new(0.0, offset),
+                    pin_height * scale,
+                    tint.map(|tint| tint.to_skia()),
+                    self.quality,
+                    canvas,
+                );
+            }
+        }
+
+        self.effects.draw_vignette(canvas);
+
+        if self.overlays.progress
+            && let Some(range) = world.time_range()
+        {
+            let fraction = overlay::progress_fraction(self.scene_pos.time, range);
+            overlay::draw_progress(fraction, self.quality, canvas);
+        }
+
+        self.draw_overview(world, canvas);
+
+        if self.overlays.leaderboard {
+            let mut entries: Vec<(&str, f32)> = people
+                .iter()
+                .filter_map(|name| {
+                    let track = world.get_track(name)?;
+                    track.get_position(self.scene_pos.time)?;
+                    Some((*name, track.distance_until(self.scene_pos.time)))
+                })
+                .collect();
+            entries.sort_by(|a, b| b.1.total_cmp(&a.1));
+            overlay::draw_leaderboard(&entries, &CONFIG.leaderboard, self.quality, canvas);
+        }
+
+        if let Some(title) = &self.title {
+            overlay::draw_title(&title.text, title.opacity, self.quality, canvas);
+        }
+    }
+}
+
+fn new_frame_bitmap() -> Bitmap {
+    new_bitmap((WIDTH as i32, HEIGHT as i32))
+}
+
+fn supersampled_size(size: (i32, i32), factor: f32) -> (i32, i32) {
+    (
+        (size.0 as f32 * factor).round() as i32,
+        (size.1 as f32 * factor).round() as i32,
+    )
+}
+
+/// scales `image` into `bitmap` with a filter that does not alias when shrinking
+fn downscale(image: &Image, bitmap: &Bitmap) {
+    if !image.scale_pixels(
+        bitmap.pixmap(),
+        CubicResampler::mitchell(),
+        CachingHint::Disallow,
+    ) {
+        error!("could not scale down the supersampled frame");
+    }
+}
+
+/// Frames which are dithered are drawn with 16 bit float channels, the dither then sees the
+/// values between the 8 bit steps while converting them. Other frames are drawn with 8 bits.
+fn frame_color_type() -> ColorType {
+    if CONFIG.dither {
+        ColorType::RGBAF16
+    } else {
+        ColorType::N32
+    }
+}
+
+fn new_bitmap(size: (i32, i32)) -> Bitmap {
+    let info = ImageInfo::new(size, frame_color_type(), skia_safe::AlphaType::Opaque, None);
+    let mut bitmap = Bitmap::new();
+    if !bitmap.set_info(&info, None) {
+        panic!("could not set image info while rendering")
+    };
+    bitmap.alloc_pixels();
+    bitmap
+}
+
+/// A warning of the rendering of single frames. It is counted for every frame and logged
+/// once per render with the number of frames, so long animations do not flood the log.
+struct FrameWarning {
+    message: &'static str,
+    frames: AtomicUsize,
+}
+
+impl FrameWarning {
+    const fn new(message: &'static str) -> Self {
+        Self {
+            message,
+            frames: AtomicUsize::new(0),
+        }
+    }
+
+    fn record(&self) {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// logs the warning if it occurred since the last report of any render
+    fn report(&self, name: &str) {
+        let frames = self.frames.swap(0, Ordering::Relaxed);
+        if frames > 0 {
+            error!("{} in {frames} frames of {name}", self.message);
+        }
+    }
+}
+
+static MISSING_TILES: FrameWarning = FrameWarning::new("some tiles needed were not loaded");
+
+thread_local! {
+    /// every rendering thread keeps one bitmap alive across all the frames it renders
+    static FRAME_BITMAP: Bitmap = new_frame_bitmap();
+}
+
+pub trait Renderable {
+    fn get_file_name(&self) -> PathBuf;
+    fn name(&self) -> &str;
+    fn make_file(self: Box<Self>, world: &World, progress: &Progress) -> Result<(), RenderError>;
+    fn info(&self) -> RenderInfo;
+    /// the frame `progress` (0 to 1) of the way through the video, stills have only one frame
+    fn preview_frame(&self, progress: f32) -> Result<Frame, RenderError>;
+}
+
+/// the frame of `frames` which is `progress` (0 to 1) of the way through
+fn frame_at_progress(
+    mut frames: impl Iterator<Item = Frame>,
+    frame_count: usize,
+    progress: f32,
+) -> Frame {
+    let index = (progress.clamp(0.0, 1.0) * frame_count.saturating_sub(1) as f32).round();
+    frames
+        .nth(index as usize)
+        .expect("index is smaller than the frame count")
+}
+
+/// renders the frame `progress` (0 to 1) of the way through `renderable`
+/// to `<name>_preview.png` in the out directory and returns its path
+pub fn render_preview(
+    renderable: &dyn Renderable,
+    progress: f32,
+    world: &World,
+) -> Result<PathBuf> {
+    let path = OUT_PATH
+        .join(&CONFIG.image_dir)
+        .join(format!("{}_preview.png", renderable.name()));
+    let image = render_frame(
+        &renderable.preview_frame(progress)?,
+        renderable.name(),
+        world,
+        &Progress::default(),
+    )?;
+    write_png(&image, &path)?;
+    Ok(path)
+}
+
+/// loads `tiles` one after the other, the ones already loaded are counted right away
+fn load_tiles(tiles: &[TileDescr], world: &World, progress: &Progress) -> Result<()> {
+    for (i, tile) in tiles.iter().enumerate() {
+        world.load_tiles(&[*tile])?;
+        progress.report(ProgressEvent::TilesLoaded {
+            loaded: i + 1,
+            total: tiles.len(),
+        });
+    }
+    Ok(())
+}
+
+/// renders a single frame on the gpu if possible
+fn render_frame(frame: &Frame, name: &str, world: &World, progress: &Progress) -> Result<Image> {
+    load_tiles(&frame.tiles(), world, progress)?;
+    #[cfg(feature = "gpu")]
+    if let Some(image) = gpu::render(frame, world) {
+        MISSING_TILES.report(name);
+        return Ok(dithered(image));
+    }
+    let image = frame.clone().render(world).as_image();
+    MISSING_TILES.report(name);
+    Ok(dithered(image))
+}
+
+/// `image` converted to 8 bits with ordered dithering if the config asks for it, it hides banding
+/// in gradients
+fn dithered(image: Image) -> Image {
+    if !CONFIG.dither {
+        return image;
+    }
+    dither::dither(&image).unwrap_or_else(|| {
+        error!("could not dither the frame");
+        image
+    })
+}
+
+fn write_png(image: &Image, path: &path::Path) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = std::fs::File::create(path)?;
+    skia_safe::png_encoder::encode(
+        &image.peek_pixels().expect("failed to get pixels."),
+        &mut file,
+        &skia_safe::png_encoder::Options::default(),
+    );
+    Ok(())
+}
+
+/// what a rendered file shows, listed in the render manifest
+#[derive(Debug, Clone, Serialize)]
+pub struct RenderInfo {
+    pub kind: &'static str,
+    pub resolution: (usize, usize),
+    pub duration_s: Option<f32>,
+    pub people: Vec<String>,
+    /// music laid under a video
+    pub audio: Option<PathBuf>,
+}
+
+pub struct StillFrame {
+    name: String,
+    center: Vector,
+    zoom: f32,
+    time: u32,
+    people: Vec<String>,
+    checkpoints: bool,
+    pin_height: f32,
+    effects: Effects,
+    overlays: Overlays,
+    quality: Quality,
+    /// part of the screen in pixels which is rendered, see [`Frame::with_crop`]
+    crop: Option<Rect>,
+}
+
+impl Renderable for StillFrame {
+    fn get_file_name(&self) -> PathBuf {
+        OUT_PATH
+            .join(&CONFIG.image_dir)
+            .join(format!("{}.png", self.name))
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn make_file(self: Box<Self>, world: &World, progress: &Progress) -> Result<(), RenderError> {
+        let image = self.render(world, progress)?;
+        progress.report(ProgressEvent::FramesRendered {
+            rendered: 1,
+            total: 1,
+        });
+        Ok(write_png(&image, &self.get_file_name())?)
+    }
+
+    fn info(&self) -> RenderInfo {
+        RenderInfo {
+            kind: "bild",
+            resolution: match self.crop.and_then(round_crop) {
+                Some(crop) => (crop.width() as usize, crop.height() as usize),
+                None => (WIDTH, HEIGHT),
+            },
+            duration_s: None,
+            people: self.people.clone(),
+            audio: None,
+        }
+    }
+
+    fn preview_frame(&self, _progress: f32) -> Result<Frame, RenderError> {
+        self.frame()
+    }
+}
+
+impl StillFrame {
+    fn frame(&self) -> Result<Frame, RenderError> {
+        let frame = Frame {
+            scene_pos: ScenePos::new(self.center, self.zoom, self.time),
+            people: self.people.clone(),
+            checkpoints: self.checkpoints,
+            pin_height: self.pin_height,
+            effects: self.effects,
+            overlays: self.overlays,
+            video_time: 0.0,
+            pin_animation: PinAnimation::default(),
+            quality: self.quality,
+            title: None,
+            crop: None,
+        };
+        match self.crop {
+            Some(crop) => frame.with_crop(crop),
+            None => Ok(frame),
+        }
+    }
+
+    pub fn render(&self, world: &World, progress: &Progress) -> Result<Image> {
+        info!("loading tiles for {}", self.name);
+        let frame = self.frame()?;
+        for person in frame.people_without_position(world) {
+            warn!(
+                "{person} has no position in {}, their pin is not drawn",
+                self.name
+            );
+        }
+        render_frame(&frame, &self.name, world, progress)
+    }
+}
+
+/// animation whose camera follows a keyframe timeline, a single center stands still
+pub struct Fixed {
+    name: String,
+    camera: Timeline,
+    time: (u32, u32),
+    duration_s: f32,
+    /// exact number of frames, overrides the count derived from the duration
+    frame_count: Option<u32>,
+    people: Vec<String>,
+    checkpoints: bool,
+    pin_height: f32,
+    effects: Effects,
+    overlays: Overlays,
+    pin_animation: PinAnimation,
+    quality: Quality,
+    /// shown on a title card before the animation
+    title: Option<String>,
+    /// music laid under the video
+    audio: Option<PathBuf>,
+}
+
+impl Fixed {
+    pub fn as_frames(&self) -> impl Iterator<Item = Frame> + '_ {
+        let Fixed {
+            name: _,
+            camera,
+            time,
+            duration_s,
+            frame_count,
+            people,
+            pin_height,
+            checkpoints,
+            effects,
+            overlays,
+            pin_animation,
+            quality,
+            title: _,
+            audio: _,
+        } = self;
+        let frames_tot = frames_total(*duration_s, *frame_count);
+        // the last frame shows the end of the camera and the time
+        let last = frames_tot.saturating_sub(1).max(1) as f32;
+        let pin_animation =
+            pin_animation.with_time_rate((time.1 - time.0) as f32 * FRAME_RATE / last);
+        (0..frames_tot).map(move |i| {
+            let (center, zoom) = camera.at(i as f32 / last);
+            let time = time.0 + (((time.1 - time.0) as f32) * (i as f32 / last)).round() as u32;
+            Frame {
+                scene_pos: ScenePos::new(center, zoom, time),
+                people: people.clone(),
+                checkpoints: *checkpoints,
+                pin_height: *pin_height,
+                effects: *effects,
+                overlays: *overlays,
+                video_time: i as f32 / FRAME_RATE,
+                pin_animation,
+                quality: *quality,
+                title: None,
+                crop: None,
+            }
+        })
+    }
+}
+
+impl Renderable for Fixed {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn get_file_name(&self) -> PathBuf {
+        OUT_PATH
+            .join(&CONFIG.video_dir)
+            .join(format!("{}.mp4", self.name))
+    }
+
+    fn make_file(self: Box<Self>, world: &World, progress: &Progress) -> Result<(), RenderError> {
+        Ok(make_video(
+            with_title(self.as_frames(), self.title.as_deref()),
+            frames_total(self.duration_s, self.frame_count) as usize
+                + title_frames(self.title.as_deref()),
+            &self.name,
+            self.get_file_name(),
+            self.audio.as_deref(),
+            world,
+            progress,
+        )?)
+    }
+
+    fn info(&self) -> RenderInfo {
+        RenderInfo {
+            kind: "animation",
+            resolution: (WIDTH, HEIGHT),
+            duration_s: Some(self.duration_s + self.title.as_ref().map_or(0.0, |_| TITLE_DURATION)),
+            people: self.people.clone(),
+            audio: self.audio.clone(),
+        }
+    }
+
+    fn preview_frame(&self, progress: f32) -> Result<Frame, RenderError> {
+        let frames = || with_title(self.as_frames(), self.title.as_deref());
+        Ok(frame_at_progress(frames(), frames().count(), progress))
     }
 }
 pub struct Sweep {
@@ -495,88 +1846,140 @@ pub struct Sweep {
     zoom: (f32, f32),
     time: (u32, u32),
     duration_s: f32,
+    /// exact number of frames, overrides the count derived from the duration
+    frame_count: Option<u32>,
     people: Vec<String>,
     checkpoints: bool,
     pin_height: f32,
+    effects: Effects,
+    overlays: Overlays,
+    pin_animation: PinAnimation,
+    /// pan at a constant speed on screen instead of slowing down when zoomed in
+    constant_speed: bool,
+    quality: Quality,
+    /// shown on a title card before the animation
+    title: Option<String>,
+    /// music laid under the video
+    audio: Option<PathBuf>,
 }
 
 impl Sweep {
-    pub fn as_frames(&self) -> Vec<Frame> {
+    /// Zooms out halfway if the endpoints are too far apart to see both, the centers of
+    /// the keyframes are not used, the camera pans by screen distance in `as_frames`.
+    fn zoom_path(&self) -> Timeline {
+        let keyframe = |fraction, center, zoom| Keyframe {
+            fraction,
+            center,
+            zoom,
+        };
+        let (start, end) = (
+            keyframe(0.0, self.center.0, self.zoom.0),
+            keyframe(1.0, self.center.1, self.zoom.1),
+        );
+        let max_zoom = -(self.center.0 - self.center.1).norm().log2();
+        let (keyframes, easing) = if max_zoom < self.zoom.0 || max_zoom < self.zoom.1 {
+            let middle = (self.center.0 + self.center.1) * 0.5;
+            (
+                vec![start, keyframe(0.5, middle, max_zoom), end],
+                Easing::Smooth,
+            )
+        } else {
+            (vec![start, end], Easing::Linear)
+        };
+        Timeline::new(keyframes, easing).expect("fractions are increasing")
+    }
+
+    pub fn as_frames(&self) -> impl Iterator<Item = Frame> + '_ {
         let Sweep {
             name: _,
             center,
             zoom,
             time,
             duration_s,
+            frame_count,
             people,
             pin_height,
             checkpoints,
+            effects,
+            overlays,
+            pin_animation,
+            constant_speed,
+            quality,
+            title: _,
+            audio: _,
         } = self;
-        let frames_tot = (duration_s * FRAME_RATE).round() as u32;
-        let mut frames = Vec::new();
-        let dist = (center.0 - center.1).norm();
-        let max_zoom = -dist.log2();
-        let zoomlevels: Vec<f32> = if max_zoom < zoom.0 || max_zoom < zoom.1 {
-            (0..frames_tot)
-                .map(|i| {
-                    if 2 * i < frames_tot - 1 {
-                        (max_zoom - zoom.0)
-                            * smoother_step((i as f32) / (frames_tot - 1) as f32, 0.0, 0.5)
-                            + zoom.0
-                    } else {
-                        (zoom.1 - max_zoom)
-                            * smoother_step((i as f32) / (frames_tot - 1) as f32, 0.5, 1.0)
-                            + max_zoom
-                    }
-                })
-                .collect()
-        } else {
-            (0..frames_tot)
-                .map(|i| zoom.0 + (zoom.1 - zoom.0) * (i as f32 / (frames_tot - 1) as f32))
-                .collect()
-        };
+        let frames_tot = frames_total(*duration_s, *frame_count);
+        let pin_animation = pin_animation
+            .with_time_rate((time.1 - time.0) as f32 * FRAME_RATE / (frames_tot - 1).max(1) as f32);
+        let zoom_path = self.zoom_path();
+        let zoomlevels: Vec<f32> = (0..frames_tot)
+            .map(|i| zoom_path.at(i as f32 / (frames_tot - 1) as f32).1)
+            .collect();
         let lin_zoom = (0..frames_tot)
             .map(|i| zoom.0 + (zoom.1 - zoom.0) * (i as f32) / (frames_tot - 1) as f32);
 
         let pin_heights: Vec<f32> = zoomlevels
             .iter()
             .zip(lin_zoom)
-            .map(|(zoom, lin_zoom)| pin_height * 2f32.powf((zoom - lin_zoom) * 0.2))
+            .map(|(zoom, lin_zoom)| {
+                pin_height * 2f32.powf((zoom - lin_zoom) * CONFIG.sweep_pin_exponent)
+            })
             .collect();
 
-        let vec_scales: Vec<f32> = zoomlevels
+        // The camera moves 2^-z world units per step for one unit of screen distance at
+        // zoom z. Summing that gives the screen space arc length, so the pan speed on
+        // screen is constant. This spends many frames on the zoomed out part of long hops
+        // and barely any close to the endpoints, where the viewer orients themselves.
+        // The default cubes the step instead, which slows the camera down a lot more
+        // near the endpoints and passes over the zoomed out middle quickly.
+        let exponent = if *constant_speed { 1.0 } else { 3.0 };
+        let vec_scales: Vec<f64> = zoomlevels
             .iter()
             .scan(0.0, |state, z| {
-                *state += 2f32.powf(-z).powf(3.0);
+                *state += 2f64.powf(-*z as f64).powf(exponent);
                 Some(*state)
             })
             .collect();
 
+        // in `f64`, the steps between frames at high zoom are below the precision of `f32`
         let last = *vec_scales.last().expect("len is allways > 1");
+        let (start, end) = (
+            (center.0.x as f64, center.0.y as f64),
+            (center.1.x as f64, center.1.y as f64),
+        );
         let centers: Vec<_> = vec_scales
             .iter()
             .map(|x| {
                 let scale = x / last;
-                center.0 + ((center.1 - center.0) * scale)
+                (
+                    start.0 + (end.0 - start.0) * scale,
+                    start.1 + (end.1 - start.1) * scale,
+                )
             })
             .collect();
 
-        for (((i, zoom), center), pin_height) in (0..frames_tot)
-            .zip(zoomlevels.iter())
-            .zip(centers.iter())
-            .zip(pin_heights.iter())
-        {
-            let time = time.0
-                + (((time.1 - time.0) as f32) * (i as f32 / (frames_tot - 1) as f32)).round()
-                    as u32;
-            frames.push(Frame {
-                scene_pos: ScenePos::new(*center, *zoom, time),
-                people: people.clone(),
-                checkpoints: *checkpoints,
-                pin_height: *pin_height,
-            });
-        }
-        frames
+        (0..frames_tot)
+            .zip(zoomlevels)
+            .zip(centers)
+            .zip(pin_heights)
+            .map(move |(((i, zoom), center), pin_height)| {
+                let time = time.0
+                    + (((time.1 - time.0) as f32) * (i as f32 / (frames_tot - 1) as f32)).round()
+                        as u32;
+                Frame {
+                    scene_pos: ScenePos::precise(center, zoom, time),
+                    people: people.clone(),
+                    checkpoints: *checkpoints,
+                    pin_height,
+                    effects: *effects,
+                    overlays: *overlays,
+                    video_time: i as f32 / FRAME_RATE,
+                    pin_animation,
+                    quality: *quality,
+                    title: None,
+                    crop: None,
+                }
+            })
     }
 }
 
@@ -586,60 +1989,223 @@ impl Renderable for Sweep {
     }
 
     fn get_file_name(&self) -> PathBuf {
-        OUT_PATH.join(format!("{}.mp4", self.name)).to_path_buf()
+        OUT_PATH
+            .join(&CONFIG.video_dir)
+            .join(format!("{}.mp4", self.name))
     }
 
-    fn make_file(self: Box<Self>) -> Result<()> {
-        make_video(self.as_frames(), &self.name, self.get_file_name())
+    fn make_file(self: Box<Self>, world: &World, progress: &Progress) -> Result<(), RenderError> {
+        Ok(make_video(
+            with_title(self.as_frames(), self.title.as_deref()),
+            frames_total(self.duration_s, self.frame_count) as usize
+                + title_frames(self.title.as_deref()),
+            &self.name,
+            self.get_file_name(),
+            self.audio.as_deref(),
+            world,
+            progress,
+        )?)
     }
-}
 
-fn make_video(frames: Vec<Frame>, name: &str, file_name: impl AsRef<path::Path>) -> Result<()> {
-    let tmp_path = BASE_RES_PATH.join("tmp");
-    if tmp_path.exists() {
-        fs::remove_dir_all(&tmp_path)?;
-        fs::create_dir_all(&tmp_path)?;
-    } else {
-        fs::create_dir_all(&tmp_path)?;
+    fn info(&self) -> RenderInfo {
+        RenderInfo {
+            kind: "sweep",
+            resolution: (WIDTH, HEIGHT),
+            duration_s: Some(self.duration_s + self.title.as_ref().map_or(0.0, |_| TITLE_DURATION)),
+            people: self.people.clone(),
+            audio: self.audio.clone(),
+        }
     }
 
-    info!("loading tiles for {name}");
-    for frame in &frames {
-        WORLD.load_tiles_at(frame.scene_pos)?;
+    fn preview_frame(&self, progress: f32) -> Result<Frame, RenderError> {
+        let frames = || with_title(self.as_frames(), self.title.as_deref());
+        Ok(frame_at_progress(frames(), frames().count(), progress))
     }
-    info!("finished loading tiles for {name}");
+}
+
+/// number of frames of an animation, `frame_count` takes precedence over the duration
+fn frames_total(duration_s: f32, frame_count: Option<u32>) -> u32 {
+    frame_count.unwrap_or_else(|| (duration_s * FRAME_RATE).round() as u32)
+}
+
+fn frame_path(frame_dir: &path::Path, i: usize) -> PathBuf {
+    frame_dir.join(format!("frame{i:0>8}.png"))
+}
+
+/// frames rendered at once, tiles are loaded and frames are built one chunk at a time
+/// so memory does not grow with the length of the video
+const FRAME_CHUNK: usize = 300;
+
+/// `total` is the number of `frames`, they are built chunk by chunk while rendering
+fn make_video(
+    frames: impl Iterator<Item = Frame>,
+    total: usize,
+    name: &str,
+    file_name: impl AsRef<path::Path>,
+    audio: Option<&path::Path>,
+    world: &World,
+    progress: &Progress,
+) -> Result<()> {
+    // frames are kept between runs, frames whose cache key did not change are not rendered again
+    let frame_dir = FRAMES_PATH.join(name);
+    fs::create_dir_all(&frame_dir)?;
+    let hashes_path = frame_dir.join("hashes.json");
+    let old_hashes: Vec<String> = match fs::File::open(&hashes_path) {
+        Ok(file) => serde_json::from_reader(file).unwrap_or_default(),
+        Err(_) => Vec::new(),
+    };
+    // removed until all frames are rendered, so an aborted run does not leave stale hashes
+    if hashes_path.exists() {
+        fs::remove_file(&hashes_path)?;
+    }
+    let mut hashes = Vec::new();
+    let mut skipped = 0;
+    // frames without a position by person, logged once instead of for every frame
+    let mut missing_positions: BTreeMap<String, usize> = BTreeMap::new();
+
     info!("start rendering {name}");
-    frames
-        .into_iter()
-        .enumerate()
-        .par_bridge()
-        .map(|(i, frame)| -> Result<()> {
-            let image = frame.render().as_image();
-            let mut file = std::fs::File::create(tmp_path.join(format!("frame{i:0>8}.png")))?;
-            skia_safe::png_encoder::encode(
-                &image.peek_pixels().ok_or(anyhow!("could not get pixels"))?,
-                &mut file,
-                &skia_safe::png_encoder::Options::default(),
-            );
-            Ok(())
-        })
-        .collect::<Result<()>>()?;
-    info!("finished rendering {name}");
-    info!("making video for {name}");
-    Command::new("ffmpeg")
-        .arg("-y")
-        .arg("-framerate")
-        .arg(FRAME_RATE.to_string())
-        .arg("-i")
-        .arg(tmp_path.join("frame%08d.png"))
-        .arg("-c:v")
-        .arg("libx264")
-        .arg("-pix_fmt")
-        .arg("yuv420p")
-        .arg(file_name.as_ref())
-        .output()?;
-
-    fs::remove_dir_all(tmp_path)?;
+    let start = Instant::now();
+    let mut frame_count = 0;
+    // every rendering thread holds its own frame bitmap, so the thread count bounds the memory
+    let threads = CONFIG.render_threads.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    });
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()?;
+    let rendered = AtomicUsize::new(0);
+    // seconds spent on each stage over all chunks
+    let (mut loading_s, mut rendering_s) = (0.0, 0.0);
+    let mut frames = frames.enumerate().peekable();
+    while frames.peek().is_some() {
+        let chunk: Vec<_> = frames.by_ref().take(FRAME_CHUNK).collect();
+        frame_count += chunk.len();
+        let mut to_render = Vec::new();
+        for (i, frame) in chunk {
+            for person in frame.people_without_position(world) {
+                *missing_positions.entry(person.to_string()).or_default() += 1;
+            }
+            let hash = frame.cache_key();
+            let cached = old_hashes.get(i) == Some(&hash) && frame_path(&frame_dir, i).exists();
+            hashes.push(hash);
+            if cached {
+                skipped += 1;
+                progress.report(ProgressEvent::FramesRendered {
+                    rendered: rendered.fetch_add(1, Ordering::Relaxed) + 1,
+                    total,
+                });
+            } else {
+                to_render.push((i, frame));
+            }
+        }
+        let mut seen = HashSet::new();
+        let tiles: Vec<_> = to_render
+            .iter()
+            .flat_map(|(_, frame)| frame.tiles())
+            .filter(|tile| seen.insert(*tile))
+            .collect();
+        let loading_start = Instant::now();
+        load_tiles(&tiles, world, progress)?;
+        let chunk_loading_s = loading_start.elapsed().as_secs_f32();
+        let rendering_start = Instant::now();
+        let chunk_frames = to_render.len();
+        pool.install(|| {
+            to_render
+                .into_par_iter()
+                .map(|(i, frame)| -> Result<()> {
+                    #[cfg(feature = "gpu")]
+                    let on_gpu = match gpu::render(&frame, world) {
+                        Some(image) => {
+                            write_png(&dithered(image), &frame_path(&frame_dir, i))?;
+                            true
+                        }
+                        None => false,
+                    };
+                    #[cfg(not(feature = "gpu"))]
+                    let on_gpu = false;
+                    if !on_gpu {
+                        FRAME_BITMAP.with(|bitmap| -> Result<()> {
+                            frame.render_into(world, bitmap);
+                            if CONFIG.dither {
+                                // the dither converts the 16 bit bitmap to 8 bits, without it the
+                                // bitmap is encoded directly
+                                write_png(
+                                    &dithered(bitmap.as_image()),
+                                    &frame_path(&frame_dir, i),
+                                )?;
+                            } else {
+                                let mut file = std::fs::File::create(frame_path(&frame_dir, i))?;
+                                skia_safe::png_encoder::encode(
+                                    &bitmap
+                                        .peek_pixels()
+                                        .ok_or(anyhow!("could not get pixels"))?,
+                                    &mut file,
+                                    &skia_safe::png_encoder::Options::default(),
+                                );
+                            }
+                            Ok(())
+                        })?;
+                    }
+                    progress.report(ProgressEvent::FramesRendered {
+                        rendered: rendered.fetch_add(1, Ordering::Relaxed) + 1,
+                        total,
+                    });
+                    Ok(())
+                })
+                .collect::<Result<()>>()
+        })?;
+        let chunk_rendering_s = rendering_start.elapsed().as_secs_f32();
+        progress.report(ProgressEvent::ChunkRendered {
+            frames: chunk_frames,
+            loading_s: chunk_loading_s,
+            rendering_s: chunk_rendering_s,
+        });
+        loading_s += chunk_loading_s;
+        rendering_s += chunk_rendering_s;
+    }
+    let elapsed = start.elapsed().as_secs_f32();
+    info!(
+        "finished rendering {name}, {:.2} frames/s, {loading_s:.1}s loading tiles and \
+        {rendering_s:.1}s rendering, {skipped} of {frame_count} frames were cached",
+        (frame_count - skipped) as f32 / elapsed
+    );
+    MISSING_TILES.report(name);
+    for (person, count) in &missing_positions {
+        warn!(
+            "{person} has no position in {count} of {frame_count} frames of {name}, \
+            their pin is not drawn in them"
+        );
+    }
+    // ffmpeg would append frames left over from a longer previous run
+    for i in frame_count.. {
+        let path = frame_path(&frame_dir, i);
+        if !path.exists() {
+            break;
+        }
+        fs::remove_file(path)?;
+    }
+    serde_json::to_writer(fs::File::create(&hashes_path)?, &hashes)?;
+
+    info!("making video for {name}, its frames are kept in {frame_dir:?}");
+    progress.report(ProgressEvent::Encoding);
+    if let Some(dir) = file_name.as_ref().parent() {
+        fs::create_dir_all(dir)?;
+    }
+    if let Some(two_pass) = &CONFIG.two_pass {
+        encode_video_two_pass(&frame_dir, file_name.as_ref(), audio, two_pass)?;
+    } else {
+        let encoder = CONFIG.video_encoder;
+        if let Err(err) = encode_video(&frame_dir, file_name.as_ref(), audio, encoder) {
+            if encoder == VideoEncoder::Libx264 {
+                return Err(err);
+            }
+            warn!("{} failed, falling back to libx264: {err}", encoder.codec());
+            encode_video(&frame_dir, file_name.as_ref(), audio, VideoEncoder::Libx264)?;
+        }
+    }
+
     info!(
         "finished {name} output_file: {:?}",
         file_name.as_ref().file_name().expect("is valid file name")
@@ -647,38 +2213,745 @@ fn make_video(frames: Vec<Frame>, name: &str, file_name: impl AsRef<path::Path>)
     Ok(())
 }
 
+/// ffmpeg reading the numbered frames in `frame_dir`,
+/// `audio` is encoded with aac and cut to the length of the video
+fn ffmpeg_command(frame_dir: &path::Path, audio: Option<&path::Path>) -> Command {
+    let mut command = Command::new("ffmpeg");
+    command
+        .arg("-y")
+        .arg("-framerate")
+        .arg(FRAME_RATE.to_string())
+        .arg("-i")
+        .arg(frame_dir.join("frame%08d.png"));
+    if let Some(audio) = audio {
+        command
+            .arg("-i")
+            .arg(audio)
+            .arg("-c:a")
+            .arg("aac")
+            .arg("-shortest");
+    }
+    command
+}
+
+fn run_ffmpeg(command: &mut Command) -> Result<(), RenderError> {
+    let output = command
+        .output()
+        .map_err(|err| RenderError::Ffmpeg(format!("could not start ffmpeg: {err}")))?;
+    if !output.status.success() {
+        return Err(RenderError::Ffmpeg(format!(
+            "exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+                .lines()
+                .last()
+                .unwrap_or_default()
+        )));
+    }
+    Ok(())
+}
+
+/// encodes the numbered frames in `frame_dir` with ffmpeg
+fn encode_video(
+    frame_dir: &path::Path,
+    file_name: &path::Path,
+    audio: Option<&path::Path>,
+    encoder: VideoEncoder,
+) -> Result<()> {
+    run_ffmpeg(
+        ffmpeg_command(frame_dir, audio)
+            .arg("-c:v")
+            .arg(encoder.codec())
+            .arg("-pix_fmt")
+            .arg("yuv420p")
+            .arg(file_name),
+    )?;
+    Ok(())
+}
+
+/// encodes the numbered frames in `frame_dir` with two libx264 passes at the target bitrate
+fn encode_video_two_pass(
+    frame_dir: &path::Path,
+    file_name: &path::Path,
+    audio: Option<&path::Path>,
+    two_pass: &TwoPass,
+) -> Result<()> {
+    let pass_log = frame_dir.join("ffmpeg2pass");
+    for pass in ["1", "2"] {
+        // the first pass only analyzes the video, the audio is read and encoded in the second
+        let audio = if pass == "1" { None } else { audio };
+        let mut command = ffmpeg_command(frame_dir, audio);
+        command
+            .arg("-c:v")
+            .arg(VideoEncoder::Libx264.codec())
+            .arg("-preset")
+            .arg(&two_pass.preset)
+            .arg("-b:v")
+            .arg(&two_pass.bitrate)
+            .arg("-pass")
+            .arg(pass)
+            .arg("-passlogfile")
+            .arg(&pass_log)
+            .arg("-pix_fmt")
+            .arg("yuv420p");
+        if pass == "1" {
+            command.arg("-an").arg("-f").arg("null").arg("-");
+        } else {
+            command.arg(file_name);
+        }
+        run_ffmpeg(&mut command)?;
+    }
+
+    for entry in fs::read_dir(frame_dir)? {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .is_some_and(|s| s.starts_with("ffmpeg2pass"))
+        {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// a frame at `scene_pos` with nothing but the map and everything else at its default
+#[cfg(any(test, feature = "bench"))]
+fn test_frame(scene_pos: ScenePos) -> Frame {
+    Frame {
+        scene_pos,
+        people: Vec::new(),
+        pin_height: 200.0,
+        checkpoints: false,
+        effects: Effects::default(),
+        overlays: Overlays::default(),
+        video_time: 0.0,
+        pin_animation: PinAnimation::default(),
+        quality: Quality::Final,
+        title: None,
+        crop: None,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
-    use std::sync::LazyLock;
+    use std::{env, sync::LazyLock};
+
+    use sha2::{Digest, Sha256};
 
-    use crate::{MAP_DATA, WORLD, lat_long_to_vec};
+    use crate::{
+        STYLE_PATH, lat_long_to_vec,
+        map::{MvtGetter, Style},
+    };
+
+    const SNAPSHOT_PATH: &str = "test_files/snapshots";
+
+    /// world which only uses the tiles in `test_files/tiles`
+    pub(super) static TEST_WORLD: LazyLock<World> =
+        LazyLock::new(|| World::with_map(MvtGetter::fixtures()));
+
+    /// Renders `still` and compares the hash of its pixels to the snapshot stored under `name`.
+    /// If `UPDATE_SNAPSHOTS` is set the snapshot is (re)written instead, a missing one is written
+    /// and the test fails.
+    fn snapshot(name: &str, still: StillFrame) {
+        let image = still
+            .render(&TEST_WORLD, &Progress::default())
+            .expect("in test");
+        let pixels = image.peek_pixels().expect("in test");
+        let hash = hex::encode(Sha256::digest(pixels.bytes().expect("in test")));
+
+        let path = path::Path::new(SNAPSHOT_PATH).join(format!("{name}.sha256"));
+        let write = || {
+            fs::create_dir_all(SNAPSHOT_PATH).expect("in test");
+            fs::write(&path, &hash).expect("in test");
+        };
+        if env::var("UPDATE_SNAPSHOTS").is_ok() {
+            write();
+            return;
+        }
+        // a new snapshot still fails once, so it is not taken as checked before it is committed
+        let Ok(expected) = fs::read_to_string(&path) else {
+            write();
+            panic!("new snapshot written to {path:?}, commit it and run the test again");
+        };
+        assert_eq!(
+            expected.trim(),
+            hash,
+            "rendered pixels of {name} changed, rerun with UPDATE_SNAPSHOTS=1 if this is intended"
+        );
+    }
 
     #[test]
-    fn load_test() {
-        let poss = [
-            lat_long_to_vec(47.55503577206553, 7.5869946379106254),
-            lat_long_to_vec(46.90307463711658, 6.786078097356981),
-        ];
+    fn constant_speed_sweep() {
+        let sweep = Sweep {
+            name: "sweep".to_string(),
+            center: (Vector::new(0.5, 0.3), Vector::new(0.52, 0.31)),
+            zoom: (10.0, 9.0),
+            time: (0, 0),
+            duration_s: 2.0,
+            frame_count: None,
+            people: Vec::new(),
+            checkpoints: false,
+            pin_height: 100.0,
+            effects: Effects::default(),
+            overlays: Overlays::default(),
+            pin_animation: PinAnimation::default(),
+            constant_speed: true,
+            quality: Quality::Final,
+            title: None,
+            audio: None,
+        };
+        let frames: Vec<_> = sweep.as_frames().collect();
+        let speeds: Vec<f32> = frames
+            .windows(2)
+            .map(|w| {
+                (w[1].scene_pos.rounded_center() - w[0].scene_pos.rounded_center()).norm()
+                    * 2f32.powf(w[1].scene_pos.zoom)
+            })
+            .collect();
+        let mean = speeds.iter().sum::<f32>() / speeds.len() as f32;
+        assert!(speeds.iter().all(|s| (s - mean).abs() < mean * 0.01));
+    }
+
+    #[test]
+    fn high_zoom_sweep() {
+        // a pan of about three pixels per frame, the step of `f32` is about a hundred pixels
+        let sweep = Sweep {
+            name: "sweep".to_string(),
+            center: (Vector::new(0.5, 0.3), Vector::new(0.5000001, 0.3)),
+            zoom: (18.0, 18.0),
+            time: (0, 0),
+            duration_s: 2.0,
+            frame_count: None,
+            people: Vec::new(),
+            checkpoints: false,
+            pin_height: 100.0,
+            effects: Effects::default(),
+            overlays: Overlays::default(),
+            pin_animation: PinAnimation::default(),
+            constant_speed: true,
+            quality: Quality::Final,
+            title: None,
+            audio: None,
+        };
+        let frames: Vec<_> = sweep.as_frames().collect();
+        let steps: Vec<f64> = frames
+            .windows(2)
+            .map(|w| {
+                (w[1].scene_pos.center.0 - w[0].scene_pos.center.0)
+                    * 2f64.powi(18)
+                    * TILE_SIZE as f64
+            })
+            .collect();
+        let mean = steps.iter().sum::<f64>() / steps.len() as f64;
+        assert!(mean > 1.0 && mean < 5.0, "{mean}");
+        assert!(
+            steps.iter().all(|s| (s - mean).abs() < mean * 0.01),
+            "{steps:?}"
+        );
+    }
 
-        LazyLock::force(&MAP_DATA);
-        LazyLock::force(&WORLD);
-        let mut data_lock = MAP_DATA.write().expect("in test");
-        for zoom_level in 4..=5 {
-            for pos in poss {
-                let scene_pos = ScenePos {
-                    center: pos,
-                    zoom: zoom_level as f32,
-                    time: 0,
+    #[test]
+    fn fixed_ends_on_last_keyframe() {
+        let keyframe = |fraction, x, zoom| Keyframe {
+            fraction,
+            center: Vector::new(x, 0.3),
+            zoom,
+        };
+        let fixed = Fixed {
+            name: "fixed".to_string(),
+            camera: Timeline::new(
+                vec![keyframe(0.0, 0.5, 10.0), keyframe(1.0, 0.52, 8.0)],
+                Easing::Linear,
+            )
+            .expect("in test"),
+            time: (100, 400),
+            duration_s: 2.0,
+            frame_count: None,
+            people: Vec::new(),
+            checkpoints: false,
+            pin_height: 100.0,
+            effects: Effects::default(),
+            overlays: Overlays::default(),
+            pin_animation: PinAnimation::default(),
+            quality: Quality::Final,
+            title: None,
+            audio: None,
+        };
+        let frames: Vec<_> = fixed.as_frames().collect();
+        let (first, last) = (&frames[0].scene_pos, &frames[frames.len() - 1].scene_pos);
+        assert_eq!(
+            (first.rounded_center(), first.zoom, first.time),
+            (Vector::new(0.5, 0.3), 10.0, 100)
+        );
+        assert_eq!(
+            (last.rounded_center(), last.zoom, last.time),
+            (Vector::new(0.52, 0.3), 8.0, 400)
+        );
+    }
+
+    #[test]
+    fn frame_cache_key() {
+        let frame = |pin_height| Frame {
+            people: vec!["Luca".to_string()],
+            pin_height,
+            ..test_frame(ScenePos::new(Vector::new(0.5, 0.3), 7.0, 100))
+        };
+        assert_eq!(frame(100.0).cache_key(), frame(100.0).cache_key());
+        assert_ne!(frame(100.0).cache_key(), frame(101.0).cache_key());
+    }
+
+    #[test]
+    fn title_card() {
+        let frames = || {
+            (0..60).map(|i| Frame {
+                video_time: i as f32 / FRAME_RATE,
+                ..test_frame(ScenePos::new(Vector::new(0.5, 0.3), 7.0, i))
+            })
+        };
+        assert_eq!(with_title(frames(), None).count(), 60 + title_frames(None));
+
+        let titled: Vec<_> = with_title(frames(), Some("Tag 1")).collect();
+        let intro_frames = (TITLE_DURATION * FRAME_RATE).round() as usize;
+        assert_eq!(titled.len(), 60 + intro_frames);
+        assert_eq!(title_frames(Some("Tag 1")), intro_frames);
+        let opacity = |frame: &Frame| frame.title.as_ref().map_or(0.0, |title| title.opacity);
+        assert!(titled[..intro_frames].iter().all(|f| opacity(f) == 1.0));
+        assert_eq!(titled[intro_frames].scene_pos.time, 0);
+        assert!(
+            titled[intro_frames..]
+                .windows(2)
+                .all(|w| opacity(&w[1]) < opacity(&w[0]) || opacity(&w[1]) == 0.0)
+        );
+        assert!(titled.last().expect("in test").title.is_none());
+    }
+
+    #[test]
+    fn preview_progress() {
+        let frames = || {
+            (0..31).map(|i| Frame {
+                video_time: i as f32 / FRAME_RATE,
+                ..test_frame(ScenePos::new(Vector::new(0.5, 0.3), 7.0, i))
+            })
+        };
+        let time_at = |progress| frame_at_progress(frames(), 31, progress).scene_pos.time;
+        assert_eq!(time_at(0.0), 0);
+        assert_eq!(time_at(0.5), 15);
+        assert_eq!(time_at(1.0), 30);
+    }
+
+    #[test]
+    fn crops_line_up() {
+        let frame = Frame {
+            checkpoints: true,
+            effects: Effects {
+                vignette: Some(0.5),
+                blur: None,
+            },
+            ..test_frame(ScenePos::new(
+                Vector::new(66.5 / 128.0, 44.5 / 128.0),
+                7.0,
+                0,
+            ))
+        };
+        TEST_WORLD.load_tiles_at(frame.scene_pos).expect("in test");
+        let (w, h) = (WIDTH as f32, HEIGHT as f32);
+        let full = frame.clone().render(&TEST_WORLD);
+        let left = frame
+            .clone()
+            .with_crop(Rect::new(0.0, w / 2.0, 0.0, h))
+            .expect("in test")
+            .render(&TEST_WORLD);
+        let right = frame
+            .with_crop(Rect::new(w / 2.0, w, 0.0, h))
+            .expect("in test")
+            .render(&TEST_WORLD);
+        assert_eq!(
+            (left.width(), left.height()),
+            (WIDTH as i32 / 2, HEIGHT as i32)
+        );
+        let half = WIDTH as i32 / 2;
+        for y in (0..HEIGHT as i32).step_by(7) {
+            for x in (0..WIDTH as i32).step_by(7) {
+                let cropped = if x < half {
+                    left.pixmap().get_color((x, y))
+                } else {
+                    right.pixmap().get_color((x - half, y))
                 };
-                let tiles = WORLD.get_tiles_fixed(scene_pos, zoom_level);
-                for tile in tiles {
-                    println!("{:?}", tile);
-                    data_lock.load_tile(tile).expect("in test");
-                    data_lock.get_tile(tile).expect("in test");
-                }
+                assert_eq!(full.pixmap().get_color((x, y)), cropped, "at {x} {y}");
+            }
+        }
+    }
+
+    #[test]
+    fn crop_extent() {
+        let frame = test_frame(ScenePos::new(
+            Vector::new(66.5 / 128.0, 44.5 / 128.0),
+            7.0,
+            0,
+        ));
+        for empty in [
+            Rect::new(10.0, 10.0, 0.0, 100.0),
+            Rect::new(0.0, 100.0, 49.8, 50.2),
+        ] {
+            assert!(frame.clone().with_crop(empty).is_err());
+        }
+
+        // a crop reaching a whole tile past the screen loads the tiles it shows
+        let (w, h, tile) = (WIDTH as f32, HEIGHT as f32, TILE_SIZE as f32);
+        let wide = frame
+            .clone()
+            .with_crop(Rect::new(-tile, w + tile, 0.0, h))
+            .expect("in test");
+        assert_eq!(
+            wide.size(),
+            (WIDTH as i32 + 2 * TILE_SIZE as i32, HEIGHT as i32)
+        );
+        let screen = frame.tiles();
+        let tiles = wide.tiles();
+        assert!(screen.iter().all(|tile| tiles.contains(tile)));
+        for x in [65, 67] {
+            assert!(tiles.contains(&TileDescr { z: 7, x, y: 44 }));
+            assert!(!screen.contains(&TileDescr { z: 7, x, y: 44 }));
+        }
+    }
+
+    #[test]
+    fn missing_positions() {
+        let world = World::from_parts(
+            MvtGetter::fixtures(),
+            Style::from_path(&*STYLE_PATH).expect("in test"),
+            Default::default(),
+            Vec::new(),
+        );
+        let mut frame = Frame {
+            people: vec!["Luca".to_string(), "Marc".to_string()],
+            ..test_frame(ScenePos::new(Vector::new(0.5, 0.3), 7.0, 0))
+        };
+        assert_eq!(frame.people_without_position(&world), vec!["Luca", "Marc"]);
+        frame.title = Some(Title {
+            text: "Tag 1".to_string(),
+            opacity: 1.0,
+        });
+        assert!(frame.people_without_position(&world).is_empty());
+    }
+
+    #[test]
+    fn cross_fade_missing_level() {
+        let world = World::from_parts(
+            MvtGetter::fixtures(),
+            Style::from_path(&*STYLE_PATH).expect("in test"),
+            Default::default(),
+            Vec::new(),
+        );
+        let frame = Frame {
+            quality: Quality::Draft,
+            ..test_frame(ScenePos::new(
+                Vector::new(66.5 / 128.0, 44.5 / 128.0),
+                7.5,
+                0,
+            ))
+        };
+        // only the tile of zoom 7 is there, the more detailed level is missing
+        let tile = TileDescr { z: 7, x: 66, y: 44 };
+        world
+            .map
+            .write()
+            .expect("in test")
+            .load_tile(tile, &world.style)
+            .expect("in test");
+        // a piece from the middle of the screen, which is covered by the tile
+        let draw = |f: &dyn Fn(&Canvas)| {
+            let bitmap = new_bitmap((64, 64));
+            let canvas = Canvas::from_bitmap(&bitmap, None).expect("in test");
+            canvas.translate((32.0 - WIDTH as f32 / 2.0, 32.0 - HEIGHT as f32 / 2.0));
+            f(&canvas);
+            bitmap
+        };
+        let drawn = draw(&|canvas| assert!(!frame.draw_tiles(&world, canvas)));
+        let palette = frame.palette(&world);
+        let background = palette[BACKGROUND_IDX].to_skia();
+        assert!(
+            (0..64).any(|i| drawn.pixmap().get_color((i, i)) != background.to_color()),
+            "nothing of the loaded level is drawn"
+        );
+        // the loaded level is not faded out
+        let expected = draw(&|canvas| {
+            canvas.clear(background);
+            let map = world.map.read().expect("in test");
+            let loaded = map.get_tile(tile).expect("in test");
+            frame.draw_level(&[loaded], 1.0, &world, &palette, canvas);
+        });
+        for y in 0..64 {
+            for x in 0..64 {
+                assert_eq!(
+                    drawn.pixmap().get_color((x, y)),
+                    expected.pixmap().get_color((x, y)),
+                    "at {x} {y}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn frame_warning() {
+        let warning = FrameWarning::new("test warning");
+        for _ in 0..3 {
+            warning.record();
+        }
+        assert_eq!(warning.frames.load(Ordering::Relaxed), 3);
+        warning.report("test");
+        assert_eq!(warning.frames.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn fit_bounds() {
+        let on_screen = |p: Vector| {
+            (-0.5..=WIDTH as f32 + 0.5).contains(&p.x)
+                && (-0.5..=HEIGHT as f32 + 0.5).contains(&p.y)
+        };
+        // too wide and too high boxes touch the screen on two sides and leave space on the others
+        for bounds in [
+            Rect::new(0.5, 0.51, 0.3, 0.301),
+            Rect::new(0.5, 0.501, 0.3, 0.31),
+        ] {
+            let (center, zoom) = ScenePos::fit(&bounds).expect("in test");
+            let transform = ScenePos::new(center, zoom, 0).world_to_screen();
+            let (min, max) = (
+                transform * Vector::new(bounds.x_min, bounds.y_min),
+                transform * Vector::new(bounds.x_max, bounds.y_max),
+            );
+            assert!(on_screen(min) && on_screen(max), "{min:?} {max:?}");
+            let touches_sides = min.x.abs() < 0.5 && (max.x - WIDTH as f32).abs() < 0.5;
+            let touches_top = min.y.abs() < 0.5 && (max.y - HEIGHT as f32).abs() < 0.5;
+            assert!(touches_sides != touches_top);
+        }
+        assert!(ScenePos::fit(&Rect::new(0.5, 0.5, 0.3, 0.3)).is_none());
+    }
+
+    #[test]
+    fn supersampling() {
+        assert_eq!(supersampled_size((3840, 2160), 1.5), (5760, 3240));
+        assert_eq!(supersampled_size((101, 51), 2.0), (202, 102));
+        let frame = Frame {
+            quality: Quality::Draft,
+            ..test_frame(ScenePos::new(Vector::new(0.5, 0.5), 5.0, 0))
+        };
+        assert_eq!(frame.supersample(), None);
+    }
+
+    #[test]
+    fn corner_anchor() {
+        let inset = CONFIG.safe_area * HEIGHT as f32;
+        let size = Vector::new(200.0, 100.0);
+        assert_eq!(Corner::TopLeft.anchor(size), Vector::new(inset, inset));
+        assert_eq!(
+            Corner::BottomRight.anchor(size) + size,
+            Vector::new(WIDTH as f32 - inset, HEIGHT as f32 - inset)
+        );
+    }
+
+    #[test]
+    fn transform_round_trip() {
+        let center = lat_long_to_vec(43.9, 4.8);
+        for zoom in 0..=18 {
+            let scene_pos = ScenePos::new(center, zoom as f32, 0);
+            let pixel = 1.0 / (2f32.powi(zoom) * TILE_SIZE as f32);
+            for offset in [Vector::zeros(), Vector::new(500.0, -300.0) * pixel] {
+                let world = center + offset;
+                let screen = scene_pos.world_to_screen() * world;
+                // the exact position on the screen of the point in f32 world coordinates
+                let exact = (
+                    (world.x as f64 - center.x as f64) / pixel as f64 + WIDTH as f64 / 2.0,
+                    (world.y as f64 - center.y as f64) / pixel as f64 + HEIGHT as f64 / 2.0,
+                );
+                let error = (screen.x as f64 - exact.0).hypot(screen.y as f64 - exact.1);
+                assert!(error < 0.5, "zoom {zoom}: {screen:?} {exact:?}");
+                let back = scene_pos.screen_to_world() * screen;
+                assert!(
+                    (back - world).norm() / pixel < 0.5,
+                    "zoom {zoom}: {world:?} {back:?}"
+                );
             }
         }
     }
+
+    #[test]
+    fn adjacent_tiles() {
+        let left = TileDescr {
+            z: 16,
+            x: 33_641,
+            y: 23_861,
+        };
+        let right = TileDescr {
+            x: left.x + 1,
+            ..left
+        };
+        let below = TileDescr {
+            y: left.y + 1,
+            ..left
+        };
+        let edge = Vector::new((left.x + 1) as f32, left.y as f32 + 0.5) / 2f32.powi(16);
+        for zoom in [16.0, 17.3, 18.0] {
+            let scene_pos = ScenePos::new(edge + Vector::new(1e-7, -2e-7), zoom, 0);
+            let a = scene_pos.tile_to_screen(left) * Vector::new(1.0, 0.5);
+            let b = scene_pos.tile_to_screen(right) * Vector::new(0.0, 0.5);
+            assert!((a - b).norm() < 0.1, "zoom {zoom}: {a:?} {b:?}");
+            let a = scene_pos.tile_to_screen(left) * Vector::new(0.3, 1.0);
+            let b = scene_pos.tile_to_screen(below) * Vector::new(0.3, 0.0);
+            assert!((a - b).norm() < 0.1, "zoom {zoom}: {a:?} {b:?}");
+        }
+    }
+
+    #[test]
+    fn meters_per_pixel() {
+        // the equator is 40075 km long and 6144 pixels wide at zoom 0
+        let equator = ScenePos::new(Vector::new(0.5, 0.5), 0.0, 0);
+        assert!((equator.meters_per_pixel() - 6522.6).abs() < 0.1);
+        let zoomed = ScenePos::new(Vector::new(0.5, 0.5), 10.0, 0);
+        assert!((zoomed.meters_per_pixel() * 1024.0 - 6522.6).abs() < 0.1);
+        let north = ScenePos::new(lat_long_to_vec(60.0, 10.0), 0.0, 0);
+        assert!((north.meters_per_pixel() - 3261.3).abs() < 0.1);
+    }
+
+    #[test]
+    fn pin_anchor() {
+        let (tip, size) = (Vector::new(30.0, 90.0), Vector::new(60.0, 100.0));
+        assert_eq!(Anchor::default().offset(tip, size), tip);
+        assert_eq!(Anchor::Center.offset(tip, size), Vector::new(30.0, 50.0));
+        assert_eq!(Anchor::TopLeft.offset(tip, size), Vector::zeros());
+        assert_eq!(
+            Anchor::Custom(0.5, 1.0).offset(tip, size),
+            Vector::new(30.0, 100.0)
+        );
+    }
+
+    #[test]
+    fn pin_animation_pose() {
+        let still = PinAnimation::default();
+        assert_eq!(still.pose(0.0, 0.0, 0, 1.0, 100.0), (1.0, 0.0));
+        let drop = PinAnimation {
+            drop: true,
+            ..PinAnimation::default()
+        };
+        assert_eq!(drop.pose(0.0, 0.0, 0, 0.0, 100.0), (0.0, -50.0));
+        assert_eq!(drop.pose(DROP_DURATION, 0.0, 0, 0.0, 100.0), (1.0, 0.0));
+        assert_eq!(
+            drop.pose(DROP_DURATION, 0.0, 1, 0.0, 100.0).0,
+            drop.pose(DROP_DURATION - DROP_STAGGER, 0.0, 0, 0.0, 100.0)
+                .0
+        );
+        // a person appearing later drops in then
+        assert_eq!(drop.pose(5.0, 5.0, 0, 0.0, 100.0), (0.0, -50.0));
+        assert_eq!(
+            drop.pose(5.0 + 2.0 * DROP_DURATION, 5.0, 0, 0.0, 100.0),
+            (1.0, 0.0)
+        );
+        let bob = PinAnimation {
+            bob: true,
+            ..PinAnimation::default()
+        };
+        assert_eq!(bob.pose(1.0, 0.0, 0, 0.0, 100.0), (1.0, 0.0));
+        let (_, offset) = bob.pose(1.0, 0.0, 0, 1.0, 100.0);
+        assert!((-100.0 * BOB_AMPLITUDE..=0.0).contains(&offset));
+        let (_, half) = bob.pose(1.0, 0.0, 0, 0.5, 100.0);
+        assert!((half - offset / 2.0).abs() < 1e-5);
+        assert_eq!(PinAnimation::stillness(0.5), 1.0);
+        assert_eq!(PinAnimation::stillness(10.0), 0.0);
+        assert!((0.0..1.0).contains(&PinAnimation::stillness(4.0)));
+
+        // 60 seconds of event time per second of video
+        let timed = drop.with_time_rate(60.0);
+        assert_eq!(timed.video_time_of(1200, 600, 10.0), 20.0);
+        assert_eq!(timed.video_time_of(0, 600, 10.0), 0.0);
+        assert_eq!(drop.video_time_of(1200, 600, 10.0), f32::INFINITY);
+        assert_eq!(drop.video_time_of(600, 600, 10.0), 0.0);
+    }
+
+    #[test]
+    fn color_deserialize() {
+        let indexed: Color = serde_json::from_str("5").expect("in test");
+        assert_eq!(indexed.a, 1.0);
+        let rgba: Color =
+            serde_json::from_str(r#"{"r": 10, "g": 20, "b": 30, "a": 0.5}"#).expect("in test");
+        assert_eq!((rgba.r, rgba.g, rgba.b, rgba.a), (10, 20, 30, 0.5));
+        let rgb: Color = serde_json::from_str(r#"{"r": 10, "g": 20, "b": 30}"#).expect("in test");
+        assert_eq!(rgb.a, 1.0);
+        let hex: Color = serde_json::from_str(r##""#0a141e""##).expect("in test");
+        assert_eq!((hex.r, hex.g, hex.b, hex.a), (10, 20, 30, 1.0));
+        let hsv: Color =
+            serde_json::from_str(r#"{"h": 235, "s": 0.18, "v": 0.12}"#).expect("in test");
+        let expected = Color::from_hsv(235.0, 0.18, 0.12);
+        assert_eq!((hsv.r, hsv.g, hsv.b), (expected.r, expected.g, expected.b));
+        assert!(serde_json::from_str::<Color>(r#""0a141e""#).is_err());
+        assert!(serde_json::from_str::<Color>("100").is_err());
+        for invalid in [
+            r#"{"h": 360, "s": 0.5, "v": 0.5}"#,
+            r#"{"h": -1, "s": 0.5, "v": 0.5}"#,
+            r#"{"h": 100, "s": 1.5, "v": 0.5}"#,
+            r#"{"h": 100, "s": 0.5, "v": -0.1}"#,
+            r#"{"h": 100, "s": 0.5, "v": 0.5, "a": 2}"#,
+            r#"{"r": 10, "g": 20, "b": 30, "a": -0.5}"#,
+        ] {
+            assert!(serde_json::from_str::<Color>(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn still_snapshot() {
+        // center of the fixture tile 7/66/44
+        snapshot(
+            "still",
+            StillFrame {
+                name: "still".to_string(),
+                center: Vector::new(66.5 / 128.0, 44.5 / 128.0),
+                zoom: 7.0,
+                time: 0,
+                people: Vec::new(),
+                checkpoints: false,
+                pin_height: 200.0,
+                effects: Effects::default(),
+                overlays: Overlays::default(),
+                quality: Quality::Final,
+                crop: None,
+            },
+        );
+    }
+
+    #[test]
+    fn load_test() {
+        let world = World::from_parts(
+            MvtGetter::fixtures(),
+            Style::from_path(&*STYLE_PATH).expect("in test"),
+            Default::default(),
+            Vec::new(),
+        );
+        let mut data_lock = world.map.write().expect("in test");
+        // the middle of the fixture tile
+        let scene_pos = ScenePos::new(Vector::new(66.5 / 128.0, 44.5 / 128.0), 7.0, 0);
+        for tile in crate::tiles_fixed((scene_pos.world_min(), scene_pos.world_max()), 7) {
+            data_lock.load_tile(tile, &world.style).expect("in test");
+            data_lock.get_tile(tile).expect("in test");
+        }
+        let elsewhere = ScenePos::new(Vector::new(0.5, 0.3), 7.0, 0);
+        for tile in crate::tiles_fixed((elsewhere.world_min(), elsewhere.world_max()), 7) {
+            assert!(data_lock.load_tile(tile, &world.style).is_err());
+        }
+    }
+
+    #[test]
+    fn pin_image_paths() {
+        assert_eq!(pin_image_path("flag"), PINS_PATH.join("flag.png"));
+        assert_eq!(
+            pin_image_path("flags/fr.webp"),
+            PINS_PATH.join("flags").join("fr.webp")
+        );
+        assert_eq!(
+            pin_image_path("/tmp/star.jpg"),
+            PathBuf::from("/tmp/star.jpg")
+        );
+        assert!(Pin::load_path(&PINS_PATH.join("missing.png"), 0.0, 0.0).is_err());
+    }
 }
@@ -0,0 +1,128 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, anyhow};
+
+use super::{
+    Effects, Fixed, Overlays, PinAnimation, Progress, Renderable, StillFrame, Sweep, Timeline,
+};
+use crate::{CONFIG, PEOPLE, WORLD, lat_long_to_vec, parse::DEFAULT_PIN_HEIGHT};
+
+/// a single image, what a `Bild` input file describes
+pub struct StillParams {
+    /// the output is written to `<name>.png` in the out directory
+    pub name: String,
+    /// latitude and longitude in degrees
+    pub center: (f32, f32),
+    pub zoom: f32,
+    /// seconds since the start of the trip
+    pub time: u32,
+    /// names of the shown pins, empty shows everyone
+    pub people: Vec<String>,
+    pub checkpoints: bool,
+}
+
+/// a video, what an `Animation` input file describes.
+/// The values are (start, end), a moving center pans the camera like a sweep.
+pub struct AnimationParams {
+    /// the output is written to `<name>.mp4` in the out directory
+    pub name: String,
+    /// latitude and longitude in degrees
+    pub center: ((f32, f32), (f32, f32)),
+    pub zoom: (f32, f32),
+    /// seconds since the start of the trip
+    pub time: (u32, u32),
+    pub duration_s: f32,
+    /// names of the shown pins, empty shows everyone
+    pub people: Vec<String>,
+    pub checkpoints: bool,
+}
+
+fn check_people(people: &[String]) -> Result<Vec<String>> {
+    match people.iter().find(|name| !PEOPLE.contains(&name.as_str())) {
+        Some(name) => Err(anyhow!("unknown person: {name}")),
+        None => Ok(people.to_vec()),
+    }
+}
+
+/// renders the image and returns the path of the written file
+pub fn render_still(params: StillParams) -> Result<PathBuf> {
+    render_still_with_progress(params, &Progress::default())
+}
+
+/// like [`render_still`], reporting the loaded tiles to `progress`
+pub fn render_still_with_progress(params: StillParams, progress: &Progress) -> Result<PathBuf> {
+    let still = Box::new(StillFrame {
+        name: params.name,
+        center: lat_long_to_vec(params.center.0, params.center.1),
+        zoom: params.zoom,
+        time: params.time,
+        people: check_people(&params.people)?,
+        checkpoints: params.checkpoints,
+        pin_height: DEFAULT_PIN_HEIGHT,
+        effects: Effects::default(),
+        overlays: Overlays::default(),
+        quality: CONFIG.quality,
+        crop: None,
+    });
+    let path = still.get_file_name();
+    still.make_file(&WORLD, progress)?;
+    Ok(path)
+}
+
+/// renders the video and returns the path of the written file
+pub fn render_animation(params: AnimationParams) -> Result<PathBuf> {
+    render_animation_with_progress(params, &Progress::default())
+}
+
+/// like [`render_animation`], reporting loaded tiles and rendered frames to `progress`
+pub fn render_animation_with_progress(
+    params: AnimationParams,
+    progress: &Progress,
+) -> Result<PathBuf> {
+    if params.duration_s <= 0.0 {
+        return Err(anyhow!("duration has to be positive"));
+    }
+    let people = check_people(&params.people)?;
+    let start = lat_long_to_vec(params.center.0.0, params.center.0.1);
+    let end = lat_long_to_vec(params.center.1.0, params.center.1.1);
+    let animation: Box<dyn Renderable> = if start == end {
+        Box::new(Fixed {
+            name: params.name,
+            camera: Timeline::fixed(start, params.zoom),
+            time: params.time,
+            duration_s: params.duration_s,
+            frame_count: None,
+            people,
+            checkpoints: params.checkpoints,
+            pin_height: DEFAULT_PIN_HEIGHT,
+            effects: Effects::default(),
+            overlays: Overlays::default(),
+            pin_animation: PinAnimation::default(),
+            quality: CONFIG.quality,
+            title: None,
+            audio: None,
+        })
+    } else {
+        Box::new(Sweep {
+            name: params.name,
+            center: (start, end),
+            zoom: params.zoom,
+            time: params.time,
+            duration_s: params.duration_s,
+            frame_count: None,
+            people,
+            checkpoints: params.checkpoints,
+            pin_height: DEFAULT_PIN_HEIGHT,
+            effects: Effects::default(),
+            overlays: Overlays::default(),
+            pin_animation: PinAnimation::default(),
+            constant_speed: false,
+            quality: CONFIG.quality,
+            title: None,
+            audio: None,
+        })
+    };
+    let path = animation.get_file_name();
+    animation.make_file(&WORLD, progress)?;
+    Ok(path)
+}
@@ -0,0 +1,75 @@
+//! Fixtures for the criterion benchmarks in `benches`,
+//! they only use the tile bundled in `test_files/tiles`.
+
+use std::fs;
+
+use anyhow::{Result, anyhow};
+use skia_safe::{Bitmap, Canvas};
+
+use super::{Frame, ScenePos, new_frame_bitmap, test_frame};
+use crate::{
+    STYLE_PATH, Vector, World,
+    map::{MapData, MvtGetter, Style, TileDescr},
+};
+
+const FIXTURE_DIR: &str = "test_files/tiles";
+const FIXTURE_TILE: TileDescr = TileDescr { z: 7, x: 66, y: 44 };
+const FIXTURE_FILE: &str = "test_files/tiles/7_66_44.mvt";
+
+/// the raw fixture tile, for measuring how long parsing takes
+pub struct TileFixture {
+    bytes: Vec<u8>,
+    style: Style,
+}
+
+impl TileFixture {
+    pub fn load() -> Result<Self> {
+        Ok(Self {
+            bytes: fs::read(FIXTURE_FILE)?,
+            style: Style::from_path(&*STYLE_PATH)?,
+        })
+    }
+
+    /// parses the tile into map data like a freshly downloaded one
+    pub fn parse(&self) -> Result<()> {
+        let reader = mvt_reader::Reader::new(self.bytes.clone())
+            .map_err(|_| anyhow!("could not create Mvt Reader"))?;
+        MapData::from_reader(FIXTURE_TILE, reader, &self.style)?;
+        Ok(())
+    }
+}
+
+/// a frame in the middle of the fixture tile with its tiles already loaded
+pub struct FrameFixture {
+    world: World,
+    frame: Frame,
+    bitmap: Bitmap,
+}
+
+impl FrameFixture {
+    pub fn load() -> Result<Self> {
+        let world = World::with_map(MvtGetter::with_cache_dir(FIXTURE_DIR.into())?);
+        let frame = test_frame(ScenePos::new(
+            Vector::new(66.5 / 128.0, 44.5 / 128.0),
+            7.0,
+            0,
+        ));
+        world.load_tiles_at(frame.scene_pos)?;
+        Ok(Self {
+            world,
+            frame,
+            bitmap: new_frame_bitmap(),
+        })
+    }
+
+    /// renders the whole frame with pins, looking up the loaded tiles
+    pub fn render(&self) {
+        self.frame.render_into(&self.world, &self.bitmap);
+    }
+
+    /// only the skia draw calls of the map layers
+    pub fn draw_map(&self) {
+        let canvas = Canvas::from_bitmap(&self.bitmap, None).expect("bitmap is valid");
+        self.frame.render_background(&self.world, &canvas);
+    }
+}
@@ -0,0 +1,123 @@
+use skia_safe::{AlphaType, CachingHint, ColorType, Data, Image, ImageInfo, images};
+
+/// 8 by 8 Bayer matrix, every threshold from 0 to 63 once
+const BAYER: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// `image` converted to 8 bits per channel with ordered dithering, `None` if its pixels could not
+/// be read. The image should be drawn with more precision than that, like `RGBAF16`, otherwise
+/// there is nothing between the steps left to dither.
+pub fn dither(image: &Image) -> Option<Image> {
+    let (width, height) = (image.width(), image.height());
+    let precise = ImageInfo::new((width, height), ColorType::RGBAF32, AlphaType::Premul, None);
+    let mut pixels = vec![0.0_f32; width as usize * height as usize * 4];
+    if !image.read_pixels(
+        &precise,
+        &mut pixels,
+        width as usize * 16,
+        (0, 0),
+        CachingHint::Disallow,
+    ) {
+        return None;
+    }
+    let info = ImageInfo::new(
+        (width, height),
+        ColorType::RGBA8888,
+        AlphaType::Premul,
+        None,
+    );
+    let quantized = dither_pixels(&pixels, width as usize);
+    images::raster_from_data(&info, Data::new_copy(&quantized), width as usize * 4)
+}
+
+/// Converts the RGBA pixels with channels from 0 to 1 of rows `width` long to 8 bits. A channel
+/// is rounded up when its fraction between two steps is above the threshold of the Bayer matrix
+/// at the pixel, so the average of a block keeps the value between the steps and the straight
+/// edges between bands turn into a fine pattern. All channels of a pixel share the threshold to
+/// keep its hue. The alpha is rounded.
+fn dither_pixels(pixels: &[f32], width: usize) -> Vec<u8> {
+    let mut quantized = Vec::with_capacity(pixels.len());
+    for (i, pixel) in pixels.chunks_exact(4).enumerate() {
+        let (x, y) = (i % width, i / width);
+        let threshold = (BAYER[y % 8][x % 8] as f32 + 0.5) / 64.0;
+        for channel in &pixel[..3] {
+            quantized.push((channel * 255.0 + threshold).floor().clamp(0.0, 255.0) as u8);
+        }
+        quantized.push((pixel[3] * 255.0).round().clamp(0.0, 255.0) as u8);
+    }
+    quantized
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// the width of the widest flat band, a run of columns of the 8 rows of `values` which are all
+    /// the same
+    fn widest_band(values: &[u8], width: usize) -> usize {
+        let columns: Vec<Vec<u8>> = (0..width)
+            .map(|x| values.iter().skip(x).step_by(width).copied().collect())
+            .collect();
+        columns
+            .chunk_by(|a, b| a == b)
+            .map(|band| band.len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn pattern() {
+        let flat = [0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0];
+        assert_eq!(dither_pixels(&flat, 2), [0, 0, 0, 255, 255, 255, 255, 255]);
+
+        // a value between two steps is stored as both of them, averaging out to it
+        let pixels = vec![100.25 / 255.0; 8 * 8 * 4];
+        let quantized = dither_pixels(&pixels, 8);
+        let channels: Vec<u8> = quantized.chunks_exact(4).map(|pixel| pixel[0]).collect();
+        assert!(channels.iter().all(|c| (100..=101).contains(c)));
+        let sum: u32 = channels.iter().map(|c| *c as u32).sum();
+        assert_eq!(sum, 100 * 64 + 16);
+        assert!(quantized.chunks_exact(4).all(|pixel| pixel[1] == pixel[0]));
+    }
+
+    #[test]
+    fn gradient_without_bands() {
+        // a smooth gradient over four steps, rounded straight to 8 bits it shows flat bands
+        let width = 256;
+        let pixels: Vec<f32> = (0..width * 8)
+            .flat_map(|i| {
+                let value = (100.0 + 4.0 * (i % width) as f32 / width as f32) / 255.0;
+                [value, value, value, 1.0]
+            })
+            .collect();
+        let rounded: Vec<u8> = pixels
+            .chunks_exact(4)
+            .map(|pixel| (pixel[0] * 255.0).round() as u8)
+            .collect();
+        let quantized = dither_pixels(&pixels, width);
+        let dithered: Vec<u8> = quantized.chunks_exact(4).map(|pixel| pixel[0]).collect();
+        assert!(widest_band(&rounded, width) >= 32);
+        assert!(
+            widest_band(&dithered, width) * 8 <= widest_band(&rounded, width),
+            "{} {}",
+            widest_band(&dithered, width),
+            widest_band(&rounded, width)
+        );
+        // the pattern keeps the brightness of the gradient
+        let mean = dithered.iter().map(|v| *v as f32).sum::<f32>() / dithered.len() as f32;
+        let exact = pixels
+            .chunks_exact(4)
+            .map(|pixel| pixel[0] * 255.0)
+            .sum::<f32>()
+            / dithered.len() as f32;
+        assert!((mean - exact).abs() < 0.1, "{mean} {exact}");
+    }
+}
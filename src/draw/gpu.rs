@@ -0,0 +1,173 @@
+//! Optional rendering through skia's OpenGL backend.
+//! Every rendering thread creates its own offscreen OpenGL context through EGL,
+//! without EGL or a driver `render` returns `None` and the caller falls back to the CPU.
+use std::{cell::RefCell, ffi::c_void, sync::Once};
+
+use khronos_egl as egl;
+use log::warn;
+use skia_safe::{
+    AlphaType, CachingHint, Image, ImageInfo,
+    gpu::{self, DirectContext, SurfaceOrigin},
+};
+
+use super::{Frame, downscale, frame_color_type, new_bitmap, supersampled_size};
+use crate::World;
+
+thread_local! {
+    static CONTEXT: RefCell<Option<GlContext>> = RefCell::new(GlContext::new());
+}
+
+static UNAVAILABLE: Once = Once::new();
+
+/// an OpenGL context without a window, current on the thread which created it
+struct GlContext {
+    egl: egl::DynamicInstance<egl::EGL1_4>,
+    display: egl::Display,
+    /// frames are drawn into render targets of skia, the surface only makes the context current
+    surface: egl::Surface,
+    context: egl::Context,
+    direct: DirectContext,
+}
+
+impl GlContext {
+    fn new() -> Option<Self> {
+        // SAFETY: libEGL is the system implementation of the EGL API
+        let egl = unsafe { egl::DynamicInstance::<egl::EGL1_4>::load_required() }.ok()?;
+        // SAFETY: the default display does not need a native display handle
+        let display = unsafe { egl.get_display(egl::DEFAULT_DISPLAY) }?;
+        egl.initialize(display).ok()?;
+        egl.bind_api(egl::OPENGL_API).ok()?;
+        let attributes = [
+            egl::SURFACE_TYPE,
+            egl::PBUFFER_BIT,
+            egl::RENDERABLE_TYPE,
+            egl::OPENGL_BIT,
+            egl::RED_SIZE,
+            8,
+            egl::GREEN_SIZE,
+            8,
+            egl::BLUE_SIZE,
+            8,
+            egl::ALPHA_SIZE,
+            8,
+            egl::NONE,
+        ];
+        let config = egl.choose_first_config(display, &attributes).ok()??;
+        let surface = egl
+            .create_pbuffer_surface(display, config, &[egl::WIDTH, 1, egl::HEIGHT, 1, egl::NONE])
+            .ok()?;
+        let Ok(context) = egl.create_context(display, config, None, &[egl::NONE]) else {
+            let _ = egl.destroy_surface(display, surface);
+            return None;
+        };
+        let direct = egl
+            .make_current(display, Some(surface), Some(surface), Some(context))
+            .ok()
+            .and_then(|()| {
+                gpu::gl::Interface::new_load_with(|name| {
+                    egl.get_proc_address(name)
+                        .map_or(std::ptr::null(), |f| f as *const c_void)
+                })
+            })
+            .and_then(|interface| gpu::direct_contexts::make_gl(interface, None));
+        let Some(direct) = direct else {
+            let _ = egl.make_current(display, None, None, None);
+            let _ = egl.destroy_context(display, context);
+            let _ = egl.destroy_surface(display, surface);
+            return None;
+        };
+        Some(Self {
+            egl,
+            display,
+            surface,
+            context,
+            direct,
+        })
+    }
+}
+
+impl Drop for GlContext {
+    fn drop(&mut self) {
+        // skia must not free its resources through the context after it is destroyed
+        self.direct.abandon();
+        let _ = self.egl.make_current(self.display, None, None, None);
+        let _ = self.egl.destroy_context(self.display, self.context);
+        let _ = self.egl.destroy_surface(self.display, self.surface);
+    }
+}
+
+/// renders the frame on the GPU and reads the pixels back into a raster image
+pub fn render(frame: &Frame, world: &World) -> Option<Image> {
+    CONTEXT.with(|context| {
+        let mut context = context.borrow_mut();
+        let Some(GlContext {
+            direct: context, ..
+        }) = context.as_mut()
+        else {
+            UNAVAILABLE.call_once(|| warn!("no OpenGL context available, rendering on the CPU"));
+            return None;
+        };
+        let factor = frame.supersample();
+        let size = match factor {
+            Some(factor) => supersampled_size(frame.size(), factor),
+            None => frame.size(),
+        };
+        let info = ImageInfo::new(size, frame_color_type(), AlphaType::Premul, None);
+        let mut surface = gpu::surfaces::render_target(
+            context,
+            gpu::Budgeted::Yes,
+            &info,
+            None,
+            SurfaceOrigin::TopLeft,
+            None,
+            false,
+            None,
+        )?;
+        if let Some(factor) = factor {
+            surface.canvas().scale((factor, factor));
+        }
+        frame.draw(world, surface.canvas());
+        context.flush_and_submit();
+        let image = surface
+            .image_snapshot()
+            .make_raster_image(context, CachingHint::Disallow)?;
+        if factor.is_none() {
+            return Some(image);
+        }
+        let bitmap = new_bitmap(frame.size());
+        downscale(&image, &bitmap);
+        Some(bitmap.as_image())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        draw::{ScenePos, test::TEST_WORLD, test_frame},
+        vec::Vector,
+    };
+
+    #[test]
+    fn renders_on_gpu() {
+        if CONTEXT.with(|context| context.borrow().is_none()) {
+            warn!("no EGL context on this machine, the GPU path is not tested");
+            return;
+        }
+        let frame = test_frame(ScenePos::new(
+            Vector::new(66.5 / 128.0, 44.5 / 128.0),
+            7.0,
+            0,
+        ));
+        TEST_WORLD.load_tiles_at(frame.scene_pos).expect("in test");
+        let image = render(&frame, &TEST_WORLD).expect("rendered on the GPU with a context");
+        let cpu = frame.clone().render(&TEST_WORLD);
+        assert_eq!((image.width(), image.height()), (cpu.width(), cpu.height()));
+        let gpu = image.peek_pixels().expect("in test");
+        let center = (cpu.width() / 2, cpu.height() / 2);
+        let (a, b) = (gpu.get_color(center), cpu.pixmap().get_color(center));
+        for (a, b) in [(a.r(), b.r()), (a.g(), b.g()), (a.b(), b.b())] {
+            assert!(a.abs_diff(b) <= 2, "{a} {b}");
+        }
+    }
+}
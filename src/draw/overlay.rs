@@ -0,0 +1,360 @@
+use skia_safe::{Canvas, Color4f, Font, FontMgr, FontStyle, Paint, PaintStyle, Rect, Typeface};
+
+use super::{Color, DrawInstructions, LayerBlend, LayerCap, LayerJoin, ScenePos};
+use crate::{
+    HEIGHT, PEOPLE, Vector, WIDTH, World,
+    config::{CheckpointEvents, GraticuleConfig, LeaderboardConfig, OverviewConfig, Quality},
+    lat_long_to_vec,
+    map::Path,
+    vec_to_lat_long,
+};
+
+/// spacings in degrees the graticule can use
+const GRATICULE_STEPS: &[f32] = &[
+    0.01, 0.02, 0.05, 0.1, 0.2, 0.5, 1.0, 2.0, 5.0, 10.0, 15.0, 30.0,
+];
+/// the graticule uses the finest spacing with at most this many longitude lines on screen
+const GRATICULE_MAX_LINES: f32 = 8.0;
+
+/// opacity of the whole routes, faint so overlapping routes stay readable
+const ROUTE_OPACITY: f32 = 0.35;
+/// line width of the whole routes in pixels
+const ROUTE_WIDTH: f32 = 4.0;
+
+/// height of the progress bar as a fraction of the frame height
+const PROGRESS_HEIGHT: f32 = 0.008;
+
+thread_local! {
+    /// the default system typeface, `None` if no font is installed
+    static TYPEFACE: Option<Typeface> =
+        FontMgr::new().legacy_make_typeface(None, FontStyle::normal());
+}
+
+/// font for overlay text, `None` if no font is installed
+pub fn overlay_font(size: f32, quality: Quality) -> Option<Font> {
+    let typeface = TYPEFACE.with(|typeface| typeface.clone())?;
+    let mut font = Font::from_typeface(typeface, size);
+    if !quality.anti_alias() {
+        font.set_edging(skia_safe::font::Edging::Alias);
+    }
+    Some(font)
+}
+
+/// draws the ranking of `entries` (name, meters) which have to be sorted
+pub fn draw_leaderboard(
+    entries: &[(&str, f32)],
+    config: &LeaderboardConfig,
+    quality: Quality,
+    canvas: &Canvas,
+) {
+    let entries = &entries[..entries.len().min(config.max_entries)];
+    if entries.is_empty() {
+        return;
+    }
+    let size = HEIGHT as f32 / 40.0;
+    let Some(font) = overlay_font(size, quality) else {
+        return;
+    };
+    let line_height = size * 1.4;
+    let padding = size * 0.6;
+
+    let lines: Vec<(String, String)> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, (name, meters))| {
+            (
+                format!("{}. {name}", i + 1),
+                format!("{:.1} km", meters / 1000.0),
+            )
+        })
+        .collect();
+    let width_of = |s: &str| font.measure_str(s, None).0;
+    let name_width = lines
+        .iter()
+        .map(|(name, _)| width_of(name))
+        .fold(0.0, f32::max);
+    let distance_width = lines
+        .iter()
+        .map(|(_, distance)| width_of(distance))
+        .fold(0.0, f32::max);
+
+    let box_size = Vector::new(
+        name_width + size + distance_width + 2.0 * padding,
+        line_height * lines.len() as f32 + 2.0 * padding,
+    );
+    let top_left = config.corner.anchor(box_size);
+
+    let mut background = Paint::new(Color4f::new(0.0, 0.0, 0.0, 0.5), None);
+    background.set_anti_alias(quality.anti_alias());
+    canvas.draw_round_rect(
+        Rect::from_xywh(top_left.x, top_left.y, box_size.x, box_size.y),
+        padding,
+        padding,
+        &background,
+    );
+
+    let mut text = Paint::new(Color4f::new(1.0, 1.0, 1.0, 1.0), None);
+    text.set_anti_alias(quality.anti_alias());
+    for (i, (name, distance)) in lines.iter().enumerate() {
+        let baseline = top_left.y + padding + line_height * (i as f32 + 0.75);
+        canvas.draw_str(name, (top_left.x + padding, baseline), &font, &text);
+        canvas.draw_str(
+            distance,
+            (
+                top_left.x + box_size.x - padding - width_of(distance),
+                baseline,
+            ),
+            &font,
+            &text,
+        );
+    }
+}
+
+/// black card with `text` centered on it covering the whole frame
+pub fn draw_title(text: &str, opacity: f32, quality: Quality, canvas: &Canvas) {
+    let mut background = Paint::new(Color4f::new(0.0, 0.0, 0.0, opacity), None);
+    background.set_anti_alias(quality.anti_alias());
+    canvas.draw_rect(Rect::from_wh(WIDTH as f32, HEIGHT as f32), &background);
+
+    let size = HEIGHT as f32 / 12.0;
+    let Some(font) = overlay_font(size, quality) else {
+        return;
+    };
+    let width = font.measure_str(text, None).0;
+    let mut paint = Paint::new(Color4f::new(1.0, 1.0, 1.0, opacity), None);
+    paint.set_anti_alias(quality.anti_alias());
+    canvas.draw_str(
+        text,
+        (
+            (WIDTH as f32 - width) / 2.0,
+            (HEIGHT as f32 + size * 0.7) / 2.0,
+        ),
+        &font,
+        &paint,
+    );
+}
+
+/// how much of the event time `range` has passed at `time`, from 0 to 1
+pub fn progress_fraction(time: u32, range: (u32, u32)) -> f32 {
+    if range.1 <= range.0 {
+        return 1.0;
+    }
+    (time.saturating_sub(range.0) as f32 / (range.1 - range.0) as f32).min(1.0)
+}
+
+/// draws a thin bar along the bottom of the frame filled to `fraction`
+pub fn draw_progress(fraction: f32, quality: Quality, canvas: &Canvas) {
+    let height = HEIGHT as f32 * PROGRESS_HEIGHT;
+    let top = HEIGHT as f32 - height;
+    let mut background = Paint::new(Color4f::new(0.0, 0.0, 0.0, 0.4), None);
+    background.set_anti_alias(quality.anti_alias());
+    canvas.draw_rect(Rect::from_xywh(0.0, top, WIDTH as f32, height), &background);
+    let mut fill = Paint::new(Color4f::new(1.0, 1.0, 1.0, 0.9), None);
+    fill.set_anti_alias(quality.anti_alias());
+    canvas.draw_rect(
+        Rect::from_xywh(0.0, top, WIDTH as f32 * fraction.clamp(0.0, 1.0), height),
+        &fill,
+    );
+}
+
+/// where the overview inset is on screen
+pub fn overview_rect(config: &OverviewConfig) -> Rect {
+    let size = Vector::new(WIDTH as f32, HEIGHT as f32) * config.size;
+    let top_left = config.corner.anchor(size);
+    Rect::from_xywh(top_left.x, top_left.y, size.x, size.y)
+}
+
+/// position in the inset of `screen_pos` on the screen of the overview
+pub fn overview_pos(screen_pos: Vector, config: &OverviewConfig) -> Vector {
+    let rect = overview_rect(config);
+    Vector::new(rect.left, rect.top) + screen_pos * config.size
+}
+
+/// border around the inset and a dot at `marker` if it is inside of it
+pub fn draw_overview_frame(
+    marker: Vector,
+    config: &OverviewConfig,
+    quality: Quality,
+    canvas: &Canvas,
+) {
+    let rect = overview_rect(config);
+    if (rect.left..=rect.right).contains(&marker.x) && (rect.top..=rect.bottom).contains(&marker.y)
+    {
+        let mut dot = Paint::new(config.marker_color.with_opacity(1.0), None);
+        dot.set_anti_alias(quality.anti_alias());
+        canvas.draw_circle((marker.x, marker.y), config.border_width * 2.5, &dot);
+    }
+    let mut border = Paint::new(config.border_color.with_opacity(1.0), None);
+    border.set_anti_alias(quality.anti_alias());
+    border.set_style(PaintStyle::Stroke);
+    border.set_stroke_width(config.border_width);
+    canvas.draw_rect(rect, &border);
+}
+
+/// radius and opacity of a checkpoint ring `time_diff` seconds away from its event,
+/// as fractions of the full radius and opacity, `None` if it is not visible
+fn burst(time_diff: u32, duration_s: u32) -> Option<(f32, f32)> {
+    if time_diff >= duration_s {
+        return None;
+    }
+    let t = time_diff as f32 / duration_s as f32;
+    Some((t, 1.0 - t))
+}
+
+/// draws a ring around the checkpoint at `screen_pos`, it grows and fades the further
+/// the frame is from the event so the burst only depends on the time of the frame
+pub fn draw_checkpoint_event(
+    screen_pos: Vector,
+    time_diff: u32,
+    pin_height: f32,
+    config: &CheckpointEvents,
+    quality: Quality,
+    canvas: &Canvas,
+) {
+    let Some((radius, opacity)) = burst(time_diff, config.duration_s) else {
+        return;
+    };
+    let mut paint = Paint::new(config.color.with_opacity(opacity), None);
+    paint.set_style(PaintStyle::Stroke);
+    paint.set_stroke_width(config.width * pin_height);
+    paint.set_anti_alias(quality.anti_alias());
+    canvas.draw_circle(
+        (screen_pos.x, screen_pos.y),
+        radius * config.radius * pin_height,
+        &paint,
+    );
+}
+
+/// spacing in degrees of the graticule for `lon_span` degrees of longitude on screen
+fn graticule_step(lon_span: f32) -> f32 {
+    GRATICULE_STEPS
+        .iter()
+        .copied()
+        .find(|step| lon_span / step <= GRATICULE_MAX_LINES)
+        .unwrap_or(*GRATICULE_STEPS.last().expect("is not empty"))
+}
+
+/// draws lines at round latitudes and longitudes, their spacing depends on the zoom
+pub fn draw_graticule(
+    scene_pos: ScenePos,
+    config: &GraticuleConfig,
+    quality: Quality,
+    canvas: &Canvas,
+) {
+    let (min, max) = (scene_pos.world_min(), scene_pos.world_max());
+    let (lon_min, lon_max) = ((min.x - 0.5) * 360.0, (max.x - 0.5) * 360.0);
+    // y grows towards the south
+    let (lat_min, lat_max) = (vec_to_lat_long(max).0, vec_to_lat_long(min).0);
+    let step = graticule_step(lon_max - lon_min);
+    let to_screen = scene_pos.world_to_screen();
+
+    let mut paint = Paint::new(config.color.with_opacity(config.opacity), None);
+    paint.set_style(PaintStyle::Stroke);
+    paint.set_stroke_width(config.width);
+    paint.set_anti_alias(quality.anti_alias());
+
+    let mut lon = (lon_min / step).ceil() * step;
+    while lon <= lon_max {
+        let x = (to_screen * lat_long_to_vec(0.0, lon)).x;
+        canvas.draw_line((x, 0.0), (x, HEIGHT as f32), &paint);
+        lon += step;
+    }
+    let mut lat = (lat_min / step).ceil() * step;
+    while lat <= lat_max {
+        let y = (to_screen * lat_long_to_vec(lat, 0.0)).y;
+        canvas.draw_line((0.0, y), (WIDTH as f32, y), &paint);
+        lat += step;
+    }
+}
+
+/// color of the route of `name`, the hues of all people are spread around the color wheel
+fn route_color(name: &str) -> Color {
+    let idx = PEOPLE
+        .iter()
+        .position(|person| *person == name)
+        .unwrap_or(0);
+    Color::from_hsv(360.0 * idx as f64 / PEOPLE.len() as f64, 0.8, 0.9)
+}
+
+/// draws the whole track of each of `people` as a faint line in their color
+pub fn draw_routes(
+    people: &[&str],
+    world: &World,
+    scene_pos: ScenePos,
+    quality: Quality,
+    canvas: &Canvas,
+) {
+    for name in people {
+        let Some(track) = world.get_track(name) else {
+            continue;
+        };
+        let path = Path(track.points.iter().map(|point| point.position).collect());
+        path.draw(
+            &DrawInstructions {
+                fill: None,
+                stroke: Some((ROUTE_WIDTH, route_color(name))),
+                transform: scene_pos.world_to_screen(),
+                opacity: ROUTE_OPACITY,
+                quality,
+                blend: LayerBlend::Normal,
+                cap: LayerCap::Round,
+                join: LayerJoin::Round,
+            },
+            canvas,
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn graticule() {
+        assert_eq!(graticule_step(360.0), 30.0);
+        assert_eq!(graticule_step(8.0), 1.0);
+        assert_eq!(graticule_step(8.5), 2.0);
+        assert_eq!(graticule_step(0.01), 0.01);
+    }
+
+    #[test]
+    fn progress() {
+        assert_eq!(progress_fraction(50, (100, 200)), 0.0);
+        assert_eq!(progress_fraction(150, (100, 200)), 0.5);
+        assert_eq!(progress_fraction(300, (100, 200)), 1.0);
+        assert_eq!(progress_fraction(100, (100, 100)), 1.0);
+    }
+
+    #[test]
+    fn overview_marker() {
+        let config = OverviewConfig::default();
+        let rect = overview_rect(&config);
+        assert_eq!(rect.width(), WIDTH as f32 * config.size);
+        // the center of the overview screen is the center of the inset
+        let center = overview_pos(Vector::new(WIDTH as f32, HEIGHT as f32) * 0.5, &config);
+        assert_eq!(center, Vector::new(rect.center_x(), rect.center_y()));
+        assert_eq!(
+            overview_pos(Vector::zeros(), &config),
+            Vector::new(rect.left, rect.top)
+        );
+    }
+
+    #[test]
+    fn checkpoint_burst() {
+        assert_eq!(burst(0, 100), Some((0.0, 1.0)));
+        assert_eq!(burst(25, 100), Some((0.25, 0.75)));
+        assert_eq!(burst(100, 100), None);
+        assert_eq!(burst(0, 0), None);
+    }
+
+    #[test]
+    fn route_colors() {
+        let colors: Vec<String> = PEOPLE
+            .iter()
+            .map(|name| format!("{:?}", route_color(name)))
+            .collect();
+        for (i, color) in colors.iter().enumerate() {
+            assert!(!colors[i + 1..].contains(color));
+        }
+    }
+}
@@ -1,15 +1,44 @@
-use std::{fs::read_to_string, path::Path};
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    fmt,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
 
-use anyhow::{Result, anyhow};
-use log::error;
+use anyhow::Result;
+use chrono::NaiveDateTime;
 
 use crate::{
-    OneOrTwo, PEOPLE, WORLD,
-    draw::{Fixed, Renderable, StillFrame, Sweep},
+    CONFIG, FRAME_RATE, HEIGHT, IN_PATH, OneOrTwo, PEOPLE, WORLD,
+    bounded::Rect,
+    config::Quality,
+    draw::{
+        Effects, Fixed, Overlays, Overview, PinAnimation, Renderable, ScenePos, StillFrame, Sweep,
+        frames_total, round_crop,
+        timeline::{CheckpointHold, Easing, Keyframe, Timeline, framing},
+    },
+    error::RenderError,
     lat_long_to_vec,
+    track::{TIME_FORMAT, TIME_ZERO},
     vec::Vector,
 };
 
+/// pin height in pixels used if `pingrösse` is not given, a tenth of the output height
+pub(crate) const DEFAULT_PIN_HEIGHT: f32 = HEIGHT as f32 / 10.0;
+
+thread_local! {
+    /// the problems found in the file being parsed, they are logged together at the end
+    static ERRORS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// collects a problem of the file being parsed, the parser continues to find the others
+macro_rules! error {
+    ($($arg:tt)+) => {
+        ERRORS.with_borrow_mut(|errors| errors.push(format!($($arg)+)))
+    };
+}
+
 macro_rules! error_on_none {
 ($val:expr, $($arg:tt)+) => {
     match $val {
@@ -19,8 +48,9 @@ macro_rules! error_on_none {
 }
 }
 
-/// panics if path has no file name or is not a txt
-pub fn from_path(path: impl AsRef<Path>) -> Result<Box<dyn Renderable>> {
+/// panics if path has no file name or is not a txt,
+/// a file with `Einzeln` gives one renderable per person
+pub fn from_path(path: impl AsRef<Path>) -> Result<Vec<Box<dyn Renderable>>, RenderError> {
     let name = path
         .as_ref()
         .iter()
@@ -28,39 +58,260 @@ pub fn from_path(path: impl AsRef<Path>) -> Result<Box<dyn Renderable>> {
         .expect("path in renderable from path is allways valid by caller")
         .to_string_lossy()
         .to_string();
-    let s = read_to_string(path)?;
+    let s = read_to_string(&path)?;
+    let path = path.as_ref().canonicalize()?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let Some(resolved) = resolve_includes(&s, dir, &mut vec![path.clone()]) else {
+        log_errors(&name, take_errors());
+        return Err(RenderError::Parse("could not resolve includes".to_string()));
+    };
+    let lines: Vec<_> = resolved
+        .iter()
+        .map(|line| (line.origin(), line.text.as_str()))
+        .collect();
 
-    let this = from_str(
+    let this = from_lines(
         name.strip_suffix(".txt")
             .expect("is always txt from caller"),
-        &s,
+        &lines,
     )
-    .ok_or(anyhow!("could not read file"))?;
+    .ok_or(RenderError::Parse("could not read file".to_string()))?;
     Ok(this)
 }
 
-fn from_str(name: &str, s: &str) -> Option<Box<dyn Renderable>> {
+/// The files `path` includes, directly or through other included files.
+/// Files which can not be read are left out, parsing the file reports them.
+pub fn included_files(path: impl AsRef<Path>) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![path.as_ref().to_path_buf()];
+    while let Some(path) = pending.pop() {
+        let Ok(s) = read_to_string(&path) else {
+            continue;
+        };
+        let dir = path.parent().unwrap_or(Path::new("."));
+        for file in s.lines().filter_map(include_target) {
+            if let Ok(included) = dir.join(file).canonicalize()
+                && !files.contains(&included)
+            {
+                files.push(included.clone());
+                pending.push(included);
+            }
+        }
+    }
+    files
+}
+
+/// where a line comes from, shown in problems as `3` or `3 in shared.txt`
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Line<'a> {
+    nr: usize,
+    /// the included file the line is from, `None` for lines of the parsed file itself
+    file: Option<&'a str>,
+}
+
+impl fmt::Display for Line<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.file {
+            Some(file) => write!(f, "{} in {}", self.nr, file),
+            None => write!(f, "{}", self.nr),
+        }
+    }
+}
+
+/// a line of the parsed file or of a file it includes
+#[derive(Debug)]
+struct SourceLine {
+    /// the include of the file the line is from as written, `None` for the file itself
+    file: Option<String>,
+    nr: usize,
+    text: String,
+}
+
+impl SourceLine {
+    fn origin(&self) -> Line<'_> {
+        Line {
+            nr: self.nr,
+            file: self.file.as_deref(),
+        }
+    }
+}
+
+/// lower case key of a line, `None` for empty lines
+fn key_of(line: &str) -> Option<String> {
+    line.split('#')
+        .next()
+        .expect("split has allways one element")
+        .split_whitespace()
+        .next()
+        .map(|key| key.to_lowercase())
+}
+
+/// the file of an `include other.txt` line, `None` for other lines
+fn include_target(line: &str) -> Option<&str> {
+    if key_of(line).as_deref() != Some("include") {
+        return None;
+    }
+    Some(
+        line.split('#')
+            .next()
+            .expect("split has allways one element")
+            .trim()["include".len()..]
+            .trim(),
+    )
+}
+
+/// Replaces `include other.txt` lines with the lines of the other file, relative to `dir`.
+/// The included lines come after the lines of `s` and remember the file they are from,
+/// keys which are already in `s` are not taken from the included file.
+/// `stack` holds the files currently being included to detect cycles.
+fn resolve_includes(s: &str, dir: &Path, stack: &mut Vec<PathBuf>) -> Option<Vec<SourceLine>> {
+    let own_keys: HashSet<String> = s.lines().filter_map(key_of).collect();
+    let mut resolved = Vec::new();
+    let mut included = Vec::new();
+    for (i, line) in s.lines().enumerate() {
+        let Some(file) = include_target(line) else {
+            resolved.push(SourceLine {
+                file: None,
+                nr: i + 1,
+                text: line.to_string(),
+            });
+            continue;
+        };
+        let path = error_on_none!(
+            dir.join(file).canonicalize().ok(),
+            "die Datei '{}' (Zeile {}) wurde nicht gefunden",
+            file,
+            i + 1
+        );
+        if stack.contains(&path) {
+            error!(
+                "'{}' (Zeile {}) bindet sich über {} selbst ein",
+                file,
+                i + 1,
+                stack
+                    .iter()
+                    .map(|p| p.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            );
+            return None;
+        }
+        let content = error_on_none!(
+            read_to_string(&path).ok(),
+            "die Datei '{}' (Zeile {}) konnte nicht gelesen werden",
+            file,
+            i + 1
+        );
+        stack.push(path.clone());
+        let content = resolve_includes(&content, path.parent().unwrap_or(dir), stack)?;
+        stack.pop();
+        for line in content {
+            if key_of(&line.text).is_some_and(|key| !own_keys.contains(&key)) {
+                included.push(SourceLine {
+                    file: Some(line.file.unwrap_or_else(|| file.to_string())),
+                    ..line
+                });
+            }
+        }
+    }
+    resolved.append(&mut included);
+    Some(resolved)
+}
+
+/// the collected problems without repetitions, files with `Einzeln` find them once per person
+fn take_errors() -> Vec<String> {
+    let mut unique = Vec::new();
+    for error in ERRORS.with_borrow_mut(std::mem::take) {
+        if !unique.contains(&error) {
+            unique.push(error);
+        }
+    }
+    unique
+}
+
+fn log_errors(name: &str, errors: Vec<String>) {
+    log::error!(
+        "{} hat {} Fehler:\n  {}",
+        name,
+        errors.len(),
+        errors.join("\n  ")
+    );
+}
+
+/// `None` if the file has any problem, all of them are logged together
+#[cfg(test)]
+fn from_str(name: &str, s: &str) -> Option<Vec<Box<dyn Renderable>>> {
+    let lines: Vec<_> = s
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            (
+                Line {
+                    nr: i + 1,
+                    file: None,
+                },
+                line,
+            )
+        })
+        .collect();
+    from_lines(name, &lines)
+}
+
+/// `None` if the lines have any problem, all of them are logged together
+fn from_lines(name: &str, lines: &[(Line<'_>, &str)]) -> Option<Vec<Box<dyn Renderable>>> {
+    take_errors();
+    let renderables = parse_renderables(name, lines);
+    let errors = take_errors();
+    if errors.is_empty() {
+        return renderables;
+    }
+    log_errors(name, errors);
+    None
+}
+
+fn parse_renderables(name: &str, lines: &[(Line<'_>, &str)]) -> Option<Vec<Box<dyn Renderable>>> {
     let valid_keys = &[
         "mitte",
         "zoom",
         "zeit",
         "dauer",
+        "bilder",
         "pins",
         "checkpoints",
         "pingrösse",
+        "vignette",
+        "unschärfe",
+        "pinanimation",
+        "gleichmässig",
+        "qualität",
+        "rangliste",
+        "titel",
+        "audio",
+        "gradnetz",
+        "routen",
+        "fortschritt",
+        "übersicht",
+        "kamera",
+        "übergang",
+        "rahmen",
+        "bereich",
+        "ausschnitt",
+        "einzeln",
+        "halt",
     ];
+    // the keyframes of the camera are a block of lines with the same key
+    let repeatable_keys = &["kamera"];
 
-    let lines: Vec<_> = s
-        .lines()
-        .enumerate()
-        .filter_map(|(i, s)| {
+    let lines: Vec<_> = lines
+        .iter()
+        .filter_map(|(line, s)| {
             let new = s
-                .trim()
                 .split('#')
                 .next()
-                .expect("split has allways one element");
+                .expect("split has allways one element")
+                .trim();
             if !new.is_empty() {
-                Some((i + 1, new))
+                Some((*line, new))
             } else {
                 None
             }
@@ -83,147 +334,631 @@ fn from_str(name: &str, s: &str) -> Option<Box<dyn Renderable>> {
     for p in &map {
         if !valid_keys.contains(&&*p.1) {
             error!(
-                "auf Zeile {} ist ein ungültiger Schlüssel:\n{} in Kleinbuchstaben gibt es nicht",
+                "auf Zeile {} ist ein ungültiger Schlüssel: {} in Kleinbuchstaben gibt es nicht",
                 p.0, p.1
             );
-            return None;
         }
     }
 
-    match &*lines[0].1.to_lowercase() {
-        "bild" => new_still_frame(name, &*map).map(|still| Box::new(still) as Box<dyn Renderable>),
-        "animation" => new_animation(name, &*map),
-        _ => {
-            error!("Modus '{}' wurde nicht verstanden", lines[0].1);
-            None
+    for (i, (line_nr, key, _)) in map.iter().enumerate() {
+        if repeatable_keys.contains(&key.as_str()) {
+            continue;
+        }
+        let duplicates: Vec<String> = map[i + 1..]
+            .iter()
+            .filter(|(_, other, _)| other == key)
+            .map(|(other_nr, _, _)| other_nr.to_string())
+            .collect();
+        if map[..i].iter().all(|(_, other, _)| other != key) && !duplicates.is_empty() {
+            error!(
+                "der Schlüssel '{}' kommt mehrmals vor: auf Zeile {} und {}",
+                key,
+                line_nr,
+                duplicates.join(", ")
+            );
         }
     }
-}
 
-fn new_animation(name: &str, map: &[(usize, String, &str)]) -> Option<Box<dyn Renderable>> {
-    let zoom_str = error_on_none!(find_key(map, "zoom"), "Zoom wurde nicht gefunden");
-    let zoom_tup = error_on_none!(
-        process_tuple(zoom_str.1),
-        "Konnte die Liste für Zoom (Zeile: {}) nicht verstehen",
-        zoom_str.0
-    );
-    let zoom = error_on_none!(
-        zoom_tup.map(|s| s.parse().ok()).as_opt(),
-        "Zoom (Zeile {}) wurde nicht verstanden",
-        zoom_str.0
-    )
-    .splat();
+    let mode = lines[0].1.to_lowercase();
+    if mode != "bild" && mode != "animation" {
+        error!("Modus '{}' wurde nicht verstanden", lines[0].1);
+        return None;
+    }
+    let new = |name: &str, map: &[(Line<'_>, String, &str)]| {
+        if mode == "bild" {
+            new_still_frame(name, map).map(|still| Box::new(still) as Box<dyn Renderable>)
+        } else {
+            new_animation(name, map)
+        }
+    };
 
-    let time_str = error_on_none!(find_key(map, "zeit"), "Zeit wurde nicht gefunded");
-    let time_tup = error_on_none!(
-        process_tuple(time_str.1),
-        "Konnte die Liste für Zeile (Zeile: {}) nicht verstehen",
-        time_str.0
+    let Some((solo_line, _)) = find_key(&map, "einzeln") else {
+        return Some(vec![new(name, &map)?]);
+    };
+    let people_str = error_on_none!(
+        find_key(&map, "pins"),
+        "Einzeln (Zeile {}) braucht Pins mit den Personen",
+        solo_line
     );
-    let time = error_on_none!(
-        time_tup.map(|s| process_time(s)).as_opt(),
-        "Zeit (Zeile {}) wurde nicht verstanden",
-        time_str.0
-    )
-    .splat();
-
-    let duration_str = error_on_none!(find_key(map, "dauer"), "duration wurde nicht gefunden");
-    let duration = error_on_none!(
-        duration_str.1.parse().ok(),
-        "duration (Zeile {}) wurde nicht verstanden",
-        duration_str.0
+    let people = error_on_none!(
+        process_people(people_str.1),
+        "Pins (Zeile: {}) benutzt Personen die nicht existieren: '{}'",
+        people_str.0,
+        people_str.1
     );
+    if people.is_empty() {
+        error!(
+            "Einzeln (Zeile {}) braucht mindestens eine Person",
+            solo_line
+        );
+        return None;
+    }
+    people
+        .iter()
+        .map(|person| {
+            let map: Vec<_> = map
+                .iter()
+                .map(|(line_nr, key, rest)| {
+                    if key == "pins" {
+                        (*line_nr, key.clone(), person.as_str())
+                    } else {
+                        (*line_nr, key.clone(), *rest)
+                    }
+                })
+                .collect();
+            new(&format!("{person}-{name}"), &map)
+        })
+        .collect()
+}
 
-    let people = match find_key(map, "pins") {
-        Some(people_str) => error_on_none!(
-            process_people(people_str.1),
-            "Pins (Zeile: {}) benutzt Personen die nicht existieren",
-            people_str.0
-        ),
-        None => Vec::new(),
+fn new_animation(name: &str, map: &[(Line<'_>, String, &str)]) -> Option<Box<dyn Renderable>> {
+    let time = process_sides(map, "zeit", "Zeit", process_time).map(OneOrTwo::splat);
+    let length = process_length(map);
+    let people = process_pins(map);
+    let pin_height = process_pin_height(map);
+    let effects = process_effects(map);
+    let overlays = process_overlays(map);
+    let quality = process_quality(map);
+
+    let pin_animation = match find_key(map, "pinanimation") {
+        Some(anim_str) => process_pin_animation(anim_str.1).or_else(|| {
+            error!(
+                "Pinanimation (Zeile {}) wurde nicht verstanden: '{}', erlaubt sind 'fallen' und 'wippen'",
+                anim_str.0, anim_str.1
+            );
+            None
+        }),
+        None => Some(PinAnimation::default()),
     };
 
-    let pin_h_str = error_on_none!(find_key(map, "pingrösse"), "Pingrösse wurde nicht gefunden");
-    let pin_height = error_on_none!(
-        pin_h_str.1.parse().ok(),
-        "Pingrösse (Zeile {}) wurde nicht verstanden",
-        pin_h_str.0
-    );
+    let title = match find_key(map, "titel") {
+        Some((line_nr, "")) => {
+            error!("Titel (Zeile {line_nr}) braucht einen Text");
+            None
+        }
+        Some((_, title)) => Some(Some(title.to_string())),
+        None => Some(None),
+    };
 
-    let center_str = error_on_none!(find_key(map, "mitte"), "Mitte wurde nicht gefunden");
-    let center_tup = error_on_none!(
-        process_tuple(center_str.1),
-        "Konnte die Liste für Mitte (Zeile: {}) nicht verstehen",
-        center_str.0
-    );
-    let center = error_on_none!(
-        center_tup.map(process_coord).as_opt(),
-        "Konnte die Liste für Mitte (Zeile: {}) nicht verstehen",
-        center_str.0
-    );
+    let audio = match find_key(map, "audio") {
+        Some((line_nr, audio)) => {
+            let path = IN_PATH.join(audio);
+            if path.is_file() {
+                Some(Some(path))
+            } else {
+                error!("die Audiodatei '{audio}' (Zeile {line_nr}) wurde nicht gefunden");
+                None
+            }
+        }
+        None => Some(None),
+    };
+
+    // without a time range Rahmen has nothing to follow, the error is already collected
+    let hold = process_hold(map);
+    let framing = match (time, length, hold) {
+        (Some(time), Some((duration, frame_count)), Some(hold)) => {
+            process_framing(map, time, frames_total(duration, frame_count), hold)
+        }
+        _ => find_key(map, "rahmen").is_none().then_some(None),
+    };
+    let area = process_area(map);
+    let keyframes = process_camera(map);
+    let camera = match (framing, area, keyframes) {
+        (Some(framing), Some(area), Some(keyframes)) => {
+            let area = area.map(|(center, zoom)| Timeline::fixed(center, (zoom, zoom)));
+            match framing.or(area).or(keyframes) {
+                Some(camera) => Some(AnimationCamera::Moving(camera)),
+                None => process_animation_center(map),
+            }
+        }
+        _ => None,
+    };
 
-    match center {
-        OneOrTwo::One(center) => Some(Box::new(Fixed {
+    if let Some((line_nr, _)) = find_key(map, "ausschnitt") {
+        error!("Ausschnitt (Zeile {line_nr}) gibt es nur in Bildern");
+        return None;
+    }
+    if let (Some(AnimationCamera::Moving(_)), Some((line_nr, _))) =
+        (&camera, find_key(map, "gleichmässig"))
+    {
+        error!(
+            "Gleichmässig (Zeile {line_nr}) gibt es nur in Animationen \
+            von einer Mitte zur anderen"
+        );
+        return None;
+    }
+
+    let (
+        Some(time),
+        Some((duration, frame_count)),
+        Some(people),
+        Some(pin_height),
+        Some(effects),
+        Some(overlays),
+        Some(quality),
+        Some(pin_animation),
+        Some(title),
+        Some(audio),
+        Some(camera),
+    ) = (
+        time,
+        length,
+        people,
+        pin_height,
+        effects,
+        overlays,
+        quality,
+        pin_animation,
+        title,
+        audio,
+        camera,
+    )
+    else {
+        return None;
+    };
+
+    match camera {
+        AnimationCamera::Sweep(center, zoom) => Some(Box::new(Sweep {
             name: name.to_string(),
             center,
             zoom,
             time,
             duration_s: duration,
+            frame_count,
             people,
             pin_height,
             checkpoints: find_key(map, "checkpoints").is_some(),
-        }) as Box<dyn Renderable>),
-
-        OneOrTwo::Two(center0, center1) => Some(Box::new(Sweep {
+            effects,
+            overlays,
+            pin_animation,
+            quality,
+            constant_speed: find_key(map, "gleichmässig").is_some(),
+            title,
+            audio,
+        })),
+        AnimationCamera::Moving(camera) => Some(Box::new(Fixed {
             name: name.to_string(),
-            center: (center0, center1),
-            zoom,
+            camera,
             time,
             duration_s: duration,
+            frame_count,
             people,
             pin_height,
             checkpoints: find_key(map, "checkpoints").is_some(),
-        }) as Box<dyn Renderable>),
+            effects,
+            overlays,
+            pin_animation,
+            quality,
+            title,
+            audio,
+        })),
     }
 }
 
-fn new_still_frame(name: &str, map: &[(usize, String, &str)]) -> Option<StillFrame> {
-    let center_str = error_on_none!(find_key(map, "mitte"), "Mitte wurde nicht gefunden");
-    let center = error_on_none!(
-        process_coord(center_str.1),
-        "Mitte (Zeile {}) wurde nicht verstanden!",
-        center_str.0
-    );
+/// how the camera of an animation moves
+enum AnimationCamera {
+    /// from one center to the other, the speed can follow the pins
+    Sweep((Vector, Vector), (f32, f32)),
+    Moving(Timeline),
+}
 
-    let zoom_str = error_on_none!(find_key(map, "zoom"), "Zoom wurde nicht gefunden");
-    let zoom = error_on_none!(
-        zoom_str.1.parse().ok(),
-        "Zoom (Zeile {}) wurde nicht verstanden",
-        zoom_str.0
+/// the camera from `Mitte` and `Zoom`, a sweep if `Mitte` has a start and an end
+fn process_animation_center(map: &[(Line<'_>, String, &str)]) -> Option<AnimationCamera> {
+    let zoom = process_sides(map, "zoom", "Zoom", |s| s.parse().ok()).map(OneOrTwo::splat);
+    let center = process_sides(map, "mitte", "Mitte", process_coord);
+    Some(match (center?, zoom?) {
+        (OneOrTwo::One(center), zoom) => AnimationCamera::Moving(Timeline::fixed(center, zoom)),
+        (OneOrTwo::Two(center0, center1), zoom) => AnimationCamera::Sweep((center0, center1), zoom),
+    })
+}
+
+/// a start and an optional end separated by `;`
+fn process_sides<'b, T>(
+    map: &[(Line<'b>, String, &'b str)],
+    key: &str,
+    display: &str,
+    parse: impl Fn(&'b str) -> Option<T>,
+) -> Option<OneOrTwo<T>> {
+    let (line_nr, value) = error_on_none!(find_key(map, key), "{} wurde nicht gefunden", display);
+    let tuple = error_on_none!(
+        process_tuple(value),
+        "Konnte die Liste für {} (Zeile: {}) nicht verstehen: '{}'",
+        display,
+        line_nr,
+        value
     );
+    parse_sides(tuple, display, line_nr, parse)
+}
+
+/// a single value which has to be there
+fn process_value<T>(
+    map: &[(Line<'_>, String, &str)],
+    key: &str,
+    display: &str,
+    parse: impl FnOnce(&str) -> Option<T>,
+) -> Option<T> {
+    let (line_nr, value) = error_on_none!(find_key(map, key), "{} wurde nicht gefunden", display);
+    Some(error_on_none!(
+        parse(value),
+        "{} (Zeile {}) wurde nicht verstanden: '{}'",
+        display,
+        line_nr,
+        value
+    ))
+}
 
-    let time_str = error_on_none!(find_key(map, "zeit"), "Zeit wurde nicht gefunded");
-    let time = error_on_none!(
-        process_time(time_str.1),
-        "Zeit (Zeile {}) wurde nicht verstanden",
-        time_str.0
+/// no pins if `pins` is not given
+fn process_pins(map: &[(Line<'_>, String, &str)]) -> Option<Vec<String>> {
+    let Some((line_nr, value)) = find_key(map, "pins") else {
+        return Some(Vec::new());
+    };
+    Some(error_on_none!(
+        process_people(value),
+        "Pins (Zeile: {}) benutzt Personen die nicht existieren: '{}'",
+        line_nr,
+        value
+    ))
+}
+
+fn process_pin_height(map: &[(Line<'_>, String, &str)]) -> Option<f32> {
+    let Some((line_nr, value)) = find_key(map, "pingrösse") else {
+        return Some(DEFAULT_PIN_HEIGHT);
+    };
+    Some(error_on_none!(
+        value.parse().ok(),
+        "Pingrösse (Zeile {}) wurde nicht verstanden: '{}'",
+        line_nr,
+        value
+    ))
+}
+
+/// Camera keyframes from a `Rahmen Luca 2; Marc` line keeping the people in each of the `frames`
+fn process_framing(
+    map: &[(Line<'_>, String, &str)],
+    time: (u32, u32),
+    frames: u32,
+    hold: Option<CheckpointHold>,
+) -> Option<Option<Timeline>> {
+    let Some((line_nr, value)) = find_key(map, "rahmen") else {
+        return Some(None);
+    };
+    for key in ["mitte", "zoom", "kamera"] {
+        if let Some((other_line, _)) = find_key(map, key) {
+            error!(
+                "Rahmen (Zeile {line_nr}) ersetzt Mitte, Zoom und Kamera, \
+                {key} (Zeile {other_line}) darf nicht auch vorkommen"
+            );
+            return None;
+        }
+    }
+    let people = error_on_none!(
+        process_weighted_people(value),
+        "Rahmen (Zeile {}) braucht 'name gewicht; name', nicht: '{}'",
+        line_nr,
+        value
     );
+    let (fractions, (mut cameras, positions)): (Vec<_>, (Vec<_>, Vec<_>)) = (0..frames)
+        .filter_map(|i| {
+            let fraction = i as f32 / frames.saturating_sub(1).max(1) as f32;
+            let time = (time.0 as f32 + (time.1 as f32 - time.0 as f32) * fraction).round() as u32;
+            let positions: Vec<_> = people
+                .iter()
+                .filter_map(|(name, weight)| {
+                    Some((WORLD.get_track(name)?.get_position(time)?, *weight))
+                })
+                .collect();
+            let (center, zoom) = framing(&positions)?;
+            let positions = positions
+                .into_iter()
+                .map(|(pos, _)| pos)
+                .collect::<Vec<_>>();
+            Some((fraction, ((time, center, zoom), positions)))
+        })
+        .unzip();
+    if let Some(hold) = hold {
+        let checkpoints: Vec<_> = WORLD.checkpoints.iter().map(|(_, (pos, _))| *pos).collect();
+        hold.apply(&mut cameras, &positions, &checkpoints);
+    }
+    let keyframes = fractions
+        .into_iter()
+        .zip(cameras)
+        .map(|(fraction, (_, center, zoom))| Keyframe {
+            fraction,
+            center,
+            zoom,
+        })
+        .collect();
+    Some(Some(error_on_none!(
+        Timeline::new(keyframes, Easing::Linear),
+        "Rahmen (Zeile {}): keine der Personen hat in dieser Zeit eine Position",
+        line_nr
+    )))
+}
+
+/// `Halt km; minuten; zoom` lets the camera of a `Rahmen` stop on each checkpoint the people
+/// come within the distance of, zoomed in by the zoom. Returns `Some(None)` if there is none.
+fn process_hold(map: &[(Line<'_>, String, &str)]) -> Option<Option<CheckpointHold>> {
+    let Some((line_nr, value)) = find_key(map, "halt") else {
+        return Some(None);
+    };
+    if find_key(map, "rahmen").is_none() {
+        error!("Halt (Zeile {line_nr}) gibt es nur mit Rahmen");
+        return None;
+    }
+    Some(Some(error_on_none!(
+        process_checkpoint_hold(value),
+        "Halt (Zeile {}) braucht 'km; minuten; zoom' mit positiven Zahlen, nicht: '{}'",
+        line_nr,
+        value
+    )))
+}
+
+fn process_checkpoint_hold(s: &str) -> Option<CheckpointHold> {
+    let mut split = s.split(';').map(str::trim);
+    let (Some(distance), Some(duration), Some(zoom_in), None) =
+        (split.next(), split.next(), split.next(), split.next())
+    else {
+        return None;
+    };
+    Some(CheckpointHold {
+        distance_m: distance.parse().ok().filter(|d: &f32| *d > 0.0)? * 1000.0,
+        duration_s: (duration.parse().ok().filter(|d: &f32| *d > 0.0)? * 60.0) as u32,
+        zoom_in: zoom_in.parse().ok().filter(|z: &f32| *z >= 0.0)?,
+    })
+}
+
+/// `Bereich (lat, lon); (lat, lon)` shows the rectangle between the two corners as large
+/// as it fits on screen. Returns `Some(None)` if there is none, `None` on errors.
+fn process_area(map: &[(Line<'_>, String, &str)]) -> Option<Option<(Vector, f32)>> {
+    let Some((line_nr, value)) = find_key(map, "bereich") else {
+        return Some(None);
+    };
+    for key in ["mitte", "zoom", "kamera", "rahmen"] {
+        if let Some((other_line, _)) = find_key(map, key) {
+            error!(
+                "Bereich (Zeile {line_nr}) ersetzt Mitte und Zoom, \
+                {key} (Zeile {other_line}) darf nicht auch vorkommen"
+            );
+            return None;
+        }
+    }
+    Some(Some(error_on_none!(
+        process_bounds(value),
+        "Bereich (Zeile {}) braucht zwei verschiedene Ecken '(breite, länge); (breite, länge)', \
+        nicht: '{}'",
+        line_nr,
+        value
+    )))
+}
+
+fn process_bounds(s: &str) -> Option<(Vector, f32)> {
+    let (a, b) = s.split_once(';')?;
+    let corners = [process_coord(a.trim())?, process_coord(b.trim())?];
+    ScenePos::fit(&Rect::from_points(&corners))
+}
 
-    let people = match find_key(map, "pins") {
-        Some(people_str) => error_on_none!(
-            process_people(people_str.1),
-            "Pins (Zeile: {}) benutzt Personen die nicht existieren",
-            people_str.0
+/// names with an optional positive weight, separated by `;`
+fn process_weighted_people(s: &str) -> Option<Vec<(String, f32)>> {
+    s.split(';')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|entry| {
+            let mut split = entry.split_whitespace();
+            let name = split.next()?;
+            if !PEOPLE.contains(&name) {
+                error!("Unbekannte Person: '{name}'");
+                return None;
+            }
+            let weight = match split.next() {
+                Some(weight) => weight.parse().ok().filter(|w: &f32| *w > 0.0)?,
+                None => 1.0,
+            };
+            split.next().is_none().then(|| (name.to_string(), weight))
+        })
+        .collect::<Option<Vec<_>>>()
+        .filter(|people| !people.is_empty())
+}
+
+/// Keyframes of the camera from `Kamera anteil; mitte; zoom` lines, `übergang` is
+/// `weich` or `linear`. Returns `Some(None)` if there are none, `None` on errors.
+fn process_camera(map: &[(Line<'_>, String, &str)]) -> Option<Option<Timeline>> {
+    let lines = find_keys(map, "kamera");
+    let easing = match find_key(map, "übergang") {
+        Some((line_nr, _)) if lines.is_empty() => {
+            error!("Übergang (Zeile {line_nr}) gibt es nur mit Kamera");
+            return None;
+        }
+        Some((line_nr, easing)) => match &*easing.to_lowercase() {
+            "weich" => Easing::Smooth,
+            "linear" => Easing::Linear,
+            _ => {
+                error!(
+                    "Übergang (Zeile {line_nr}) wurde nicht verstanden: '{easing}', \
+                    erlaubt sind 'weich' und 'linear'"
+                );
+                return None;
+            }
+        },
+        None => Easing::default(),
+    };
+    let Some(&(first_line, _)) = lines.first() else {
+        return Some(None);
+    };
+    for key in ["mitte", "zoom"] {
+        if let Some((line_nr, _)) = find_key(map, key) {
+            error!(
+                "Kamera (Zeile {first_line}) ersetzt Mitte und Zoom, \
+                {key} (Zeile {line_nr}) darf nicht auch vorkommen"
+            );
+            return None;
+        }
+    }
+
+    // every line is checked before giving up
+    let keyframes: Vec<_> = lines
+        .into_iter()
+        .map(|(line_nr, value)| process_keyframe(line_nr, value))
+        .collect();
+    let keyframes = keyframes.into_iter().collect::<Option<Vec<_>>>()?;
+    Some(Some(error_on_none!(
+        Timeline::new(keyframes, easing),
+        "die Anteile der Kamera (ab Zeile {}) müssen aufsteigen",
+        first_line
+    )))
+}
+
+/// `anteil; mitte; zoom` of a `Kamera` line
+fn process_keyframe(line_nr: Line<'_>, value: &str) -> Option<Keyframe> {
+    let mut split = value.split(';').map(str::trim);
+    let (Some(fraction), Some(center), Some(zoom), None) =
+        (split.next(), split.next(), split.next(), split.next())
+    else {
+        error!("Kamera (Zeile {line_nr}) braucht 'anteil; mitte; zoom', nicht: '{value}'");
+        return None;
+    };
+    Some(Keyframe {
+        fraction: error_on_none!(
+            fraction.parse().ok().filter(|f| (0.0..=1.0).contains(f)),
+            "der Anteil der Kamera (Zeile {}) muss zwischen 0 und 1 liegen: '{}'",
+            line_nr,
+            fraction
+        ),
+        center: error_on_none!(
+            process_coord(center),
+            "die Mitte der Kamera (Zeile {}) wurde nicht verstanden: '{}'",
+            line_nr,
+            center
+        ),
+        zoom: error_on_none!(
+            zoom.parse().ok(),
+            "der Zoom der Kamera (Zeile {}) wurde nicht verstanden: '{}'",
+            line_nr,
+            zoom
         ),
-        None => Vec::new(),
+    })
+}
+
+/// duration in seconds and the exact frame count if `bilder` is given instead of `dauer`
+fn process_length(map: &[(Line<'_>, String, &str)]) -> Option<(f32, Option<u32>)> {
+    match (find_key(map, "dauer"), find_key(map, "bilder")) {
+        (Some((duration_line, _)), Some((frames_line, _))) => {
+            error!(
+                "Dauer (Zeile {duration_line}) und Bilder (Zeile {frames_line}) \
+                schliessen sich aus, es darf nur eins angegeben werden"
+            );
+            None
+        }
+        (Some(duration_str), None) => {
+            let duration = error_on_none!(
+                duration_str.1.parse().ok(),
+                "Dauer (Zeile {}) wurde nicht verstanden: '{}'",
+                duration_str.0,
+                duration_str.1
+            );
+            Some((duration, None))
+        }
+        (None, Some(frames_str)) => {
+            let frames: u32 = error_on_none!(
+                frames_str.1.parse().ok().filter(|frames| *frames > 0),
+                "Bilder (Zeile {}) muss eine positive ganze Zahl sein: '{}'",
+                frames_str.0,
+                frames_str.1
+            );
+            Some((frames as f32 / FRAME_RATE, Some(frames)))
+        }
+        (None, None) => {
+            error!("duration wurde nicht gefunden");
+            None
+        }
+    }
+}
+
+fn new_still_frame(name: &str, map: &[(Line<'_>, String, &str)]) -> Option<StillFrame> {
+    let time = process_value(map, "zeit", "Zeit", process_time);
+
+    // without a time Rahmen has nothing to follow, the error is already collected
+    let framing = match time {
+        Some(time) => process_framing(map, (time, time), 1, None),
+        None => find_key(map, "rahmen").is_none().then_some(None),
+    };
+    let view = match (framing, process_area(map)) {
+        (Some(Some(camera)), Some(_)) => Some(camera.at(0.0)),
+        (Some(None), Some(Some(fit))) => Some(fit),
+        (Some(None), Some(None)) => {
+            let center = process_value(map, "mitte", "Mitte", process_coord);
+            let zoom = process_value(map, "zoom", "Zoom", |s| s.parse().ok());
+            center.zip(zoom)
+        }
+        _ => None,
     };
 
-    let pin_h_str = error_on_none!(find_key(map, "pingrösse"), "Pingrösse wurde nicht gefunden");
-    let pin_height = error_on_none!(
-        pin_h_str.1.parse().ok(),
-        "Pingrösse (Zeile {}) wurde nicht verstanden",
-        pin_h_str.0
-    );
+    let people = process_pins(map);
+    let pin_height = process_pin_height(map);
+    let effects = process_effects(map);
+    let overlays = process_overlays(map);
+    let quality = process_quality(map);
+    let crop = process_crop(map);
+
+    let mut animation_only = false;
+    for (key, display) in [
+        ("pinanimation", "Pinanimation"),
+        ("titel", "Titel"),
+        ("bilder", "Bilder"),
+        ("kamera", "Kamera"),
+        ("audio", "Audio"),
+        ("halt", "Halt"),
+        ("gleichmässig", "Gleichmässig"),
+    ] {
+        if let Some((line_nr, _)) = find_key(map, key) {
+            error!("{display} (Zeile {line_nr}) gibt es nur in Animationen");
+            animation_only = true;
+        }
+    }
+
+    let (
+        Some(time),
+        Some((center, zoom)),
+        Some(people),
+        Some(pin_height),
+        Some(effects),
+        Some(overlays),
+        Some(quality),
+        Some(crop),
+        false,
+    ) = (
+        time,
+        view,
+        people,
+        pin_height,
+        effects,
+        overlays,
+        quality,
+        crop,
+        animation_only,
+    )
+    else {
+        return None;
+    };
 
     Some(StillFrame {
         name: name.to_string(),
@@ -233,10 +968,142 @@ fn new_still_frame(name: &str, map: &[(usize, String, &str)]) -> Option<StillFra
         people,
         pin_height,
         checkpoints: find_key(map, "checkpoints").is_some(),
+        effects,
+        overlays,
+        quality,
+        crop,
+    })
+}
+
+/// the part of the screen between two corners `(x, y)` in pixels, it may reach past the screen
+fn process_crop(map: &[(Line<'_>, String, &str)]) -> Option<Option<Rect>> {
+    let Some((line_nr, value)) = find_key(map, "ausschnitt") else {
+        return Some(None);
+    };
+    let corners = value
+        .split_once(';')
+        .and_then(|(a, b)| Some([process_pixel(a.trim())?, process_pixel(b.trim())?]));
+    Some(Some(error_on_none!(
+        corners.and_then(|corners| round_crop(Rect::from_points(&corners))),
+        "Ausschnitt (Zeile {}) braucht zwei Ecken '(x, y); (x, y)' in Pixeln, \
+        die mindestens ein Pixel auseinander liegen, nicht: '{}'",
+        line_nr,
+        value
+    )))
+}
+
+/// a point `(x, y)` on the screen in pixels
+fn process_pixel(s: &str) -> Option<Vector> {
+    let (x, y) = s.strip_prefix('(')?.strip_suffix(')')?.split_once(',')?;
+    Some(Vector::new(x.trim().parse().ok()?, y.trim().parse().ok()?))
+}
+
+/// `entwurf` renders fast previews, `final` full quality, defaults to the quality in the config
+fn process_quality(map: &[(Line<'_>, String, &str)]) -> Option<Quality> {
+    let Some((line_nr, quality)) = find_key(map, "qualität") else {
+        return Some(CONFIG.quality);
+    };
+    match &*quality.to_lowercase() {
+        "entwurf" => Some(Quality::Draft),
+        "final" => Some(Quality::Final),
+        _ => {
+            error!("Qualität (Zeile {line_nr}) muss 'entwurf' oder 'final' sein");
+            None
+        }
+    }
+}
+
+/// `rangliste` ranks the shown people by the distance they traveled,
+/// `gradnetz` draws latitude and longitude lines, `routen` the whole tracks
+/// and `fortschritt` a bar with the elapsed event time
+/// `Übersicht (lat, lon); zoom` adds the overview inset
+fn process_overlays(map: &[(Line<'_>, String, &str)]) -> Option<Overlays> {
+    let overview = match find_key(map, "übersicht") {
+        Some((line_nr, value)) => Some(error_on_none!(
+            process_overview(value),
+            "Übersicht (Zeile {}) braucht '(breite, länge); zoom', nicht: '{}'",
+            line_nr,
+            value
+        )),
+        None => None,
+    };
+    Some(Overlays {
+        leaderboard: find_key(map, "rangliste").is_some(),
+        graticule: find_key(map, "gradnetz").is_some(),
+        routes: find_key(map, "routen").is_some(),
+        progress: find_key(map, "fortschritt").is_some(),
+        overview,
+    })
+}
+
+fn process_overview(s: &str) -> Option<Overview> {
+    let (center, zoom) = s.split_once(';')?;
+    Some(Overview {
+        center: process_coord(center.trim())?,
+        zoom: zoom.trim().parse().ok().filter(|z: &f32| *z >= 0.0)?,
+    })
+}
+
+/// `vignette` takes the darkness of the corners from 0 to 1, `unschärfe` the blur in pixels
+fn process_effects(map: &[(Line<'_>, String, &str)]) -> Option<Effects> {
+    let vignette = process_vignette(map);
+    let blur = process_blur(map);
+    Some(Effects {
+        vignette: vignette?,
+        blur: blur?,
+    })
+}
+
+fn process_vignette(map: &[(Line<'_>, String, &str)]) -> Option<Option<f32>> {
+    Some(match find_key(map, "vignette") {
+        Some(vignette_str) => Some(error_on_none!(
+            vignette_str
+                .1
+                .parse()
+                .ok()
+                .filter(|v| (0.0..=1.0).contains(v)),
+            "Vignette (Zeile {}) wurde nicht verstanden: '{}', erlaubt ist 0 bis 1",
+            vignette_str.0,
+            vignette_str.1
+        )),
+        None => None,
+    })
+}
+
+fn process_blur(map: &[(Line<'_>, String, &str)]) -> Option<Option<f32>> {
+    Some(match find_key(map, "unschärfe") {
+        Some(blur_str) => Some(error_on_none!(
+            blur_str.1.parse().ok().filter(|b: &f32| *b >= 0.0),
+            "Unschärfe (Zeile {}) wurde nicht verstanden: '{}'",
+            blur_str.0,
+            blur_str.1
+        )),
+        None => None,
     })
 }
 
-fn find_key<'a, 'b>(map: &'a [(usize, String, &'b str)], key: &str) -> Option<(usize, &'b str)>
+/// `fallen` lets the pins drop in, `wippen` lets them bob while standing still,
+/// both can be combined with a semicolon
+fn process_pin_animation(s: &str) -> Option<PinAnimation> {
+    let mut animation = PinAnimation::default();
+    for part in s
+        .split(';')
+        .map(|s| s.trim().to_lowercase())
+        .filter(|s| !s.is_empty())
+    {
+        match &*part {
+            "fallen" => animation.drop = true,
+            "wippen" => animation.bob = true,
+            _ => return None,
+        }
+    }
+    Some(animation)
+}
+
+fn find_key<'a, 'b>(
+    map: &'a [(Line<'b>, String, &'b str)],
+    key: &str,
+) -> Option<(Line<'b>, &'b str)>
 where
     'b: 'a,
 {
@@ -248,6 +1115,43 @@ where
     None
 }
 
+/// all lines with `key` in the order of the file
+fn find_keys<'b>(map: &[(Line<'b>, String, &'b str)], key: &str) -> Vec<(Line<'b>, &'b str)> {
+    map.iter()
+        .filter(|(_, this_key, _)| this_key == key)
+        .map(|(line, _, val)| (*line, *val))
+        .collect()
+}
+
+/// parses both sides of a start and end value, the error names the side which failed
+fn parse_sides<'a, T>(
+    tuple: OneOrTwo<&'a str>,
+    key: &str,
+    line_nr: Line<'_>,
+    parse: impl Fn(&'a str) -> Option<T>,
+) -> Option<OneOrTwo<T>> {
+    match tuple {
+        OneOrTwo::One(val) => Some(OneOrTwo::One(error_on_none!(
+            parse(val),
+            "{} (Zeile {}) wurde nicht verstanden: '{}'",
+            key,
+            line_nr,
+            val
+        ))),
+        OneOrTwo::Two(left_str, right_str) => {
+            let left = parse(left_str);
+            if left.is_none() {
+                error!("{key} (Zeile {line_nr}) wurde links nicht verstanden: '{left_str}'");
+            }
+            let right = parse(right_str);
+            if right.is_none() {
+                error!("{key} (Zeile {line_nr}) wurde rechts nicht verstanden: '{right_str}'");
+            }
+            Some(OneOrTwo::Two(left?, right?))
+        }
+    }
+}
+
 fn process_tuple(s: &str) -> Option<OneOrTwo<&str>> {
     let mut split = s.split(';');
     let a = split.next()?;
@@ -283,14 +1187,16 @@ fn process_coord(s: &str) -> Option<Vector> {
         }
     }
     let mut split = s.strip_prefix('(')?.strip_suffix(')')?.split(',');
+    let (lat_min, lat_max) = CONFIG.lat_bounds;
     let lat = split.next()?.trim().parse().ok()?;
-    if !(20.0..=50.0).contains(&lat) {
-        error!("Breitengrad ungültig");
+    if !(lat_min..=lat_max).contains(&lat) {
+        error!("Breitengrad {lat} ungültig, erlaubt ist {lat_min} bis {lat_max}");
         return None;
     }
+    let (lon_min, lon_max) = CONFIG.lon_bounds;
     let lon = split.next()?.trim().parse().ok()?;
-    if !(0.0..=10.0).contains(&lon) {
-        error!("Längengrad ungültig");
+    if !(lon_min..=lon_max).contains(&lon) {
+        error!("Längengrad {lon} ungültig, erlaubt ist {lon_min} bis {lon_max}");
         return None;
     }
     if split.next().is_some() {
@@ -299,7 +1205,13 @@ fn process_coord(s: &str) -> Option<Vector> {
     return Some(lat_long_to_vec(lat, lon));
 }
 
+/// `tagTstunde:minute` counted from the first day of the trip or an ISO 8601 timestamp
+/// like `2025-04-14T13:30:00`, returns the seconds since the start of the trip
 fn process_time(s: &str) -> Option<u32> {
+    if s.contains('-') {
+        let time = NaiveDateTime::parse_from_str(s.trim(), TIME_FORMAT).ok()?;
+        return u32::try_from((time - *TIME_ZERO).num_seconds()).ok();
+    }
     let mut split = s.trim().split('T');
     let day: u32 = split.next()?.trim().parse().ok()?;
     let mut time_split = split.next()?.split(':');
@@ -314,12 +1226,46 @@ fn process_time(s: &str) -> Option<u32> {
     return Some(day * 24 * 60 * 60 + hour * 60 * 60 + minute * 60);
 }
 
+/// Names separated by `;`, or `alle` (or `all`) followed by names with a `-` to leave out.
+/// `alle` selects every person and can not be combined with single names.
 fn process_people(s: &str) -> Option<Vec<String>> {
-    s.split(';')
-        .filter_map(|mut s| {
-            s = s.trim();
-            if !s.is_empty() { Some(s) } else { None }
-        })
+    let names: Vec<&str> = s
+        .split(';')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .collect();
+    let (excluded, names): (Vec<&str>, Vec<&str>) =
+        names.into_iter().partition(|s| s.starts_with('-'));
+    let excluded: Vec<&str> = excluded.iter().map(|s| s[1..].trim()).collect();
+    if let Some(name) = excluded.iter().find(|name| !PEOPLE.contains(name)) {
+        error!("Unbekannte Person kann nicht ausgeschlossen werden: '{name}'");
+        return None;
+    }
+    if names
+        .iter()
+        .any(|s| s.eq_ignore_ascii_case("alle") || s.eq_ignore_ascii_case("all"))
+    {
+        if names.len() > 1 {
+            error!("'alle' kann nicht mit einzelnen Personen kombiniert werden");
+            return None;
+        }
+        let people: Vec<String> = PEOPLE
+            .iter()
+            .filter(|name| !excluded.contains(name))
+            .map(|s| s.to_string())
+            .collect();
+        if people.is_empty() {
+            error!("Alle Personen wurden ausgeschlossen");
+            return None;
+        }
+        return Some(people);
+    }
+    if !excluded.is_empty() {
+        error!("Personen können nur mit 'alle' ausgeschlossen werden");
+        return None;
+    }
+    names
+        .into_iter()
         .map(|s| {
             if PEOPLE.contains(&s) {
                 Some(s.to_string())
@@ -354,4 +1300,285 @@ mod test {
         let s2 = include_str!("../../test_files/dumb.txt");
         assert!(from_str("example", s1).is_none() && from_str("example", s2).is_none());
     }
+
+    #[test]
+    fn people() {
+        init();
+        assert_eq!(process_people("Alle").expect("in test").len(), PEOPLE.len());
+        assert_eq!(
+            process_people("all; ").expect("in test").len(),
+            PEOPLE.len()
+        );
+        assert!(process_people("alle; Luca").is_none());
+        let without = process_people("alle; -Marc; - Ivo").expect("in test");
+        assert_eq!(without.len(), PEOPLE.len() - 2);
+        assert!(!without.iter().any(|name| name == "Marc" || name == "Ivo"));
+        assert!(process_people("alle; -Niemand").is_none());
+        assert!(process_people("Luca; -Marc").is_none());
+        assert_eq!(
+            process_people("Luca; Marc").expect("in test"),
+            vec!["Luca".to_string(), "Marc".to_string()]
+        );
+    }
+
+    #[test]
+    fn weighted_people() {
+        init();
+        assert_eq!(
+            process_weighted_people("Luca 2; Marc").expect("in test"),
+            vec![("Luca".to_string(), 2.0), ("Marc".to_string(), 1.0)]
+        );
+        assert!(process_weighted_people("Luca 0").is_none());
+        assert!(process_weighted_people("Luca zwei").is_none());
+        assert!(process_weighted_people("Niemand 2").is_none());
+        assert!(process_weighted_people(" ; ").is_none());
+    }
+
+    #[test]
+    fn include() {
+        init();
+        let dir = Path::new("test_files");
+        let s = include_str!("../../test_files/include.txt");
+        let resolved =
+            resolve_includes(s, dir, &mut vec![dir.join("include.txt")]).expect("in test");
+        let origin = |text: &str| {
+            resolved
+                .iter()
+                .find(|line| line.text == text)
+                .map(|line| line.origin().to_string())
+        };
+        assert_eq!(
+            origin("Pins Luca; Louis").as_deref(),
+            Some("2 in shared_settings.txt")
+        );
+        assert_eq!(origin("Pingrösse 30").as_deref(), Some("6"));
+        assert_eq!(origin("Pingrösse 20"), None);
+        assert_eq!(origin("Zoom 7").as_deref(), Some("4"));
+        let lines: Vec<_> = resolved
+            .iter()
+            .map(|line| (line.origin(), line.text.as_str()))
+            .collect();
+        from_lines("example", &lines).expect("in test");
+        assert_eq!(
+            included_files(dir.join("include.txt")),
+            [dir.join("shared_settings.txt")
+                .canonicalize()
+                .expect("in test")]
+        );
+
+        let cycle = include_str!("../../test_files/include_cycle.txt");
+        let cycle_path = dir
+            .join("include_cycle.txt")
+            .canonicalize()
+            .expect("in test");
+        assert!(resolve_includes(cycle, dir, &mut vec![cycle_path]).is_none());
+        assert!(resolve_includes("include missing.txt", dir, &mut Vec::new()).is_none());
+    }
+
+    #[test]
+    fn mode_line_comment() {
+        init();
+        let s = include_str!("../../test_files/mode_comment.txt");
+        from_str("example", s).expect("in test");
+    }
+
+    #[test]
+    fn length() {
+        init();
+        let line = |key: &str, value| (Line { nr: 1, file: None }, key.to_string(), value);
+        assert_eq!(process_length(&[line("dauer", "2.5")]), Some((2.5, None)));
+        assert_eq!(
+            process_length(&[line("bilder", "90")]),
+            Some((90.0 / FRAME_RATE, Some(90)))
+        );
+        assert!(process_length(&[line("bilder", "0")]).is_none());
+        assert!(process_length(&[line("bilder", "2.5")]).is_none());
+        assert!(process_length(&[line("dauer", "2.5"), line("bilder", "90")]).is_none());
+        assert!(process_length(&[]).is_none());
+    }
+
+    #[test]
+    fn camera() {
+        init();
+        let line = |key: &str, value| (Line { nr: 1, file: None }, key.to_string(), value);
+        let camera = process_camera(&[
+            line("kamera", "0; (42.0, 3.0); 8"),
+            line("kamera", "0.5; (42.0, 3.0); 6"),
+            line("kamera", "1; (43.0, 3.0); 10"),
+            line("übergang", "Linear"),
+        ])
+        .expect("in test")
+        .expect("in test");
+        assert_eq!(camera.at(0.25).1, 7.0);
+        assert_eq!(camera.at(1.0).0, lat_long_to_vec(43.0, 3.0));
+
+        assert!(process_camera(&[]).expect("in test").is_none());
+        assert!(process_camera(&[line("übergang", "weich")]).is_none());
+        assert!(process_camera(&[line("kamera", "0; (42.0, 3.0)")]).is_none());
+        assert!(process_camera(&[line("kamera", "2; (42.0, 3.0); 8")]).is_none());
+        assert!(
+            process_camera(&[
+                line("kamera", "0.5; (42.0, 3.0); 8"),
+                line("kamera", "0.2; (42.0, 3.0); 8"),
+            ])
+            .is_none()
+        );
+        assert!(
+            process_camera(&[line("kamera", "0; (42.0, 3.0); 8"), line("zoom", "8")]).is_none()
+        );
+    }
+
+    #[test]
+    fn time() {
+        assert_eq!(
+            process_time("2T7:30"),
+            Some(2 * 24 * 3600 + 7 * 3600 + 30 * 60)
+        );
+        assert_eq!(process_time("2025-04-16T07:30:00"), process_time("2T7:30"));
+        assert_eq!(process_time(" 2025-04-14T00:00:00 "), Some(0));
+        assert!(process_time("2025-04-13T23:59:00").is_none());
+        assert!(process_time("2025-04-16 07:30").is_none());
+        assert!(process_time("2T7").is_none());
+    }
+
+    #[test]
+    fn sides() {
+        init();
+        let parse = |s: &str| s.parse::<f32>().ok();
+        let line = Line { nr: 3, file: None };
+        let zoom = parse_sides(OneOrTwo::Two("7", "8.5"), "Zoom", line, parse).expect("in test");
+        assert_eq!(zoom.splat(), (7.0, 8.5));
+        assert!(parse_sides(OneOrTwo::Two("7", "x"), "Zoom", line, parse).is_none());
+        assert!(parse_sides(OneOrTwo::One("x"), "Zoom", line, parse).is_none());
+    }
+
+    #[test]
+    fn overview() {
+        init();
+        let overview = process_overview("(43.5, 3.5); 6").expect("in test");
+        assert_eq!(overview.zoom, 6.0);
+        assert_eq!(overview.center, lat_long_to_vec(43.5, 3.5));
+        assert!(process_overview("(43.5, 3.5)").is_none());
+        assert!(process_overview("(43.5, 3.5); -1").is_none());
+    }
+
+    #[test]
+    fn crop() {
+        init();
+        let crop =
+            |value| process_crop(&[(Line { nr: 1, file: None }, "ausschnitt".to_string(), value)]);
+        assert_eq!(
+            crop("(100, 50.4); (-20, 300)"),
+            Some(Some(Rect::new(-20.0, 100.0, 50.0, 300.0)))
+        );
+        assert_eq!(process_crop(&[]), Some(None));
+        for value in [
+            "(0, 0); (0, 100)",
+            "(0, 0); (100, 0.2)",
+            "(0, 0)",
+            "0, 0; 100, 100",
+        ] {
+            assert!(crop(value).is_none(), "{value}");
+        }
+    }
+
+    #[test]
+    fn duplicate_key() {
+        init();
+        let s = include_str!("../../test_files/duplicate_key.txt");
+        assert!(from_str("example", s).is_none());
+    }
+
+    #[test]
+    fn effects() {
+        init();
+        let s = include_str!("../../test_files/effects.txt");
+        from_str("example", s).expect("in test");
+        assert!(from_str("example", &s.replace("0.6", "1.5")).is_none());
+        assert!(from_str("example", &s.replace("2.5", "-1")).is_none());
+        assert!(from_str("example", &s.replace("Entwurf", "Skizze")).is_none());
+    }
+
+    #[test]
+    fn constant_speed_only_in_sweeps() {
+        init();
+        let still = include_str!("../../test_files/effects.txt");
+        assert!(from_str("example", &format!("{still}\nGleichmässig")).is_none());
+        let fixed = still
+            .replace("Bild", "Animation")
+            .replace("Zeit 1T12:00", "Zeit 1T12:00; 1T13:00\nDauer 2");
+        from_str("example", &fixed).expect("in test");
+        assert!(from_str("example", &format!("{fixed}\nGleichmässig")).is_none());
+        let sweep = fixed.replace("Mitte (40, 8)", "Mitte (40, 8); (41, 8)");
+        from_str("example", &format!("{sweep}\nGleichmässig")).expect("in test");
+    }
+
+    #[test]
+    fn pin_animation() {
+        let both = process_pin_animation("fallen; wippen;").expect("in test");
+        assert!(both.drop && both.bob);
+        let bob = process_pin_animation(" Wippen ").expect("in test");
+        assert!(!bob.drop && bob.bob);
+        assert!(process_pin_animation("fallen; hüpfen").is_none());
+    }
+
+    #[test]
+    fn checkpoint_hold() {
+        assert_eq!(
+            process_checkpoint_hold("2; 30; 1.5"),
+            Some(CheckpointHold {
+                distance_m: 2000.0,
+                duration_s: 1800,
+                zoom_in: 1.5,
+            })
+        );
+        assert!(process_checkpoint_hold("2; 30").is_none());
+        assert!(process_checkpoint_hold("0; 30; 1").is_none());
+        assert!(process_checkpoint_hold("2; 30; -1").is_none());
+    }
+
+    #[test]
+    fn all_errors() {
+        init();
+        let s = include_str!("../../test_files/effects.txt")
+            .replace("0.6", "1.5")
+            .replace("2.5", "-1")
+            .replace("Zoom 7", "Zoom sieben");
+        // the zoom is taken from an included file
+        let lines: Vec<_> = s
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                let file = line.starts_with("Zoom").then_some("shared.txt");
+                (Line { nr: i + 1, file }, line)
+            })
+            .collect();
+        take_errors();
+        assert!(parse_renderables("example", &lines).is_none());
+        let errors = take_errors();
+        assert_eq!(errors.len(), 3, "{errors:?}");
+        assert!(errors[0].starts_with("Zoom"));
+        assert!(errors[0].contains("in shared.txt"), "{}", errors[0]);
+        assert!(from_str("example", &s).is_none());
+        assert!(take_errors().is_empty());
+    }
+
+    #[test]
+    fn solo() {
+        init();
+        let s = include_str!("../../test_files/effects.txt");
+        let people = format!("{}; {}", PEOPLE[0], PEOPLE[1]);
+        let solo = format!("{s}\nPins {people}\nEinzeln");
+        let renderables = from_str("example", &solo).expect("in test");
+        let names: Vec<&str> = renderables.iter().map(|r| r.name()).collect();
+        assert_eq!(
+            names,
+            [
+                format!("{}-example", PEOPLE[0]),
+                format!("{}-example", PEOPLE[1])
+            ]
+        );
+        assert_eq!(from_str("example", s).expect("in test").len(), 1);
+        assert!(from_str("example", &format!("{s}\nEinzeln")).is_none());
+    }
 }
@@ -0,0 +1,64 @@
+use std::sync::mpsc::Sender;
+
+use log::debug;
+
+/// a step of a render, front-ends receive them while the render runs
+#[derive(Clone, Debug, PartialEq)]
+pub enum ProgressEvent {
+    /// tiles of the frames about to be rendered, videos load them in chunks of frames
+    TilesLoaded { loaded: usize, total: usize },
+    /// frames rendered or taken from the frame cache
+    FramesRendered { rendered: usize, total: usize },
+    /// a chunk of a video is done, `frames` of it were not cached and had to be rendered,
+    /// the seconds tell the throughput of loading its tiles and of rendering its frames
+    ChunkRendered {
+        frames: usize,
+        loading_s: f32,
+        rendering_s: f32,
+    },
+    /// all frames are rendered and ffmpeg makes the video
+    Encoding,
+}
+
+/// Where the progress of a render goes. Without a sender it is only logged at debug level,
+/// the usual start and end of a render are logged either way.
+#[derive(Clone, Default)]
+pub struct Progress {
+    sender: Option<Sender<ProgressEvent>>,
+}
+
+impl Progress {
+    pub fn new(sender: Sender<ProgressEvent>) -> Self {
+        Self {
+            sender: Some(sender),
+        }
+    }
+
+    /// a receiver which hung up does not stop the render
+    pub fn report(&self, event: ProgressEvent) {
+        match &self.sender {
+            Some(sender) => {
+                let _ = sender.send(event);
+            }
+            None => debug!("{event:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn channel() {
+        let (sender, receiver) = mpsc::channel();
+        let progress = Progress::new(sender);
+        progress.report(ProgressEvent::Encoding);
+        assert_eq!(receiver.try_recv(), Ok(ProgressEvent::Encoding));
+        drop(receiver);
+        progress.report(ProgressEvent::Encoding);
+        Progress::default().report(ProgressEvent::Encoding);
+    }
+}
@@ -0,0 +1,282 @@
+use crate::{HEIGHT, Vector, WIDTH, great_circle_distance, map::TILE_SIZE, smoother_step};
+
+/// people within this many weighted standard deviations of their centroid are framed
+const FRAMING_DEVIATIONS: f32 = 2.0;
+/// zoom of a framing of people who are all at the same place
+const FRAMING_MAX_ZOOM: f32 = 14.0;
+/// share of a checkpoint hold spent moving onto the checkpoint and the same back to the people
+const HOLD_TRANSITION: f32 = 0.25;
+
+/// camera position at `fraction` (0 to 1) of the way through an animation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+    pub fraction: f32,
+    pub center: Vector,
+    pub zoom: f32,
+}
+
+/// how the camera moves between two keyframes
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Easing {
+    Linear,
+    /// starts and stops without a jerk
+    #[default]
+    Smooth,
+}
+
+impl Easing {
+    /// progress from 0 to 1 between keyframes at `start` and `end`
+    fn progress(self, fraction: f32, start: f32, end: f32) -> f32 {
+        match self {
+            Easing::Linear => (fraction - start) / (end - start),
+            Easing::Smooth => smoother_step(fraction, start, end),
+        }
+    }
+}
+
+/// Camera path through keyframes sorted by their fraction.
+/// Before the first and after the last keyframe the camera stands still.
+#[derive(Clone, Debug)]
+pub struct Timeline {
+    keyframes: Vec<Keyframe>,
+    easing: Easing,
+}
+
+impl Timeline {
+    /// `None` if there are no keyframes or their fractions are not increasing
+    pub fn new(keyframes: Vec<Keyframe>, easing: Easing) -> Option<Self> {
+        if keyframes.is_empty() || keyframes.windows(2).any(|w| w[0].fraction >= w[1].fraction) {
+            return None;
+        }
+        Some(Self { keyframes, easing })
+    }
+
+    /// the camera stays at `center` and zooms linearly from `zoom.0` to `zoom.1`
+    pub fn fixed(center: Vector, zoom: (f32, f32)) -> Self {
+        Self {
+            keyframes: vec![
+                Keyframe {
+                    fraction: 0.0,
+                    center,
+                    zoom: zoom.0,
+                },
+                Keyframe {
+                    fraction: 1.0,
+                    center,
+                    zoom: zoom.1,
+                },
+            ],
+            easing: Easing::Linear,
+        }
+    }
+
+    /// center and zoom at `fraction` of the way through the animation
+    pub fn at(&self, fraction: f32) -> (Vector, f32) {
+        let first = self.keyframes[0];
+        if fraction <= first.fraction {
+            return (first.center, first.zoom);
+        }
+        // the segment starting at a keyframe includes it so a keyframe is hit exactly
+        let Some(end) = self.keyframes.iter().position(|k| fraction < k.fraction) else {
+            let last = self.keyframes[self.keyframes.len() - 1];
+            return (last.center, last.zoom);
+        };
+        let (a, b) = (self.keyframes[end - 1], self.keyframes[end]);
+        let t = self.easing.progress(fraction, a.fraction, b.fraction);
+        (
+            a.center + (b.center - a.center) * t,
+            a.zoom + (b.zoom - a.zoom) * t,
+        )
+    }
+}
+
+/// Camera centered on the weighted centroid of `positions` (position, weight) which zooms
+/// so their weighted spread fits the screen. Weights are relative, `None` if none is positive.
+pub fn framing(positions: &[(Vector, f32)]) -> Option<(Vector, f32)> {
+    let positions: Vec<_> = positions.iter().filter(|(_, w)| *w > 0.0).collect();
+    if positions.is_empty() {
+        return None;
+    }
+    let total: f32 = positions.iter().map(|(_, w)| w).sum();
+    let center = positions
+        .iter()
+        .fold(Vector::zeros(), |sum, (p, w)| sum + *p * *w)
+        * (1.0 / total);
+    let variance = |axis: fn(Vector) -> f32| {
+        positions
+            .iter()
+            .map(|(p, w)| w * axis(*p - center).powi(2))
+            .sum::<f32>()
+            / total
+    };
+    let fits = |half_screen: f32, variance: f32| {
+        (half_screen / (FRAMING_DEVIATIONS * variance.sqrt() * TILE_SIZE as f32)).log2()
+    };
+    let zoom = fits(WIDTH as f32 / 2.0, variance(|v| v.x))
+        .min(fits(HEIGHT as f32 / 2.0, variance(|v| v.y)))
+        .clamp(0.0, FRAMING_MAX_ZOOM);
+    Some((center, zoom))
+}
+
+/// A framing stops on a checkpoint once the people come within `distance_m` of it,
+/// for `duration_s` of event time zoomed in by `zoom_in`, and then follows them again.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CheckpointHold {
+    pub distance_m: f32,
+    pub duration_s: u32,
+    pub zoom_in: f32,
+}
+
+impl CheckpointHold {
+    /// Moves the camera `(time, center, zoom)` of the frames of a framing, in order of time,
+    /// onto the `checkpoints`. `people` are the positions of the framed people in each frame.
+    /// A checkpoint holds the camera again only after the people left it.
+    pub fn apply(
+        &self,
+        frames: &mut [(u32, Vector, f32)],
+        people: &[Vec<Vector>],
+        checkpoints: &[Vector],
+    ) {
+        // the held checkpoint, the time the hold started and the zoom it holds
+        let mut hold: Option<(Vector, u32, f32)> = None;
+        let mut last_near = None;
+        for ((time, center, zoom), positions) in frames.iter_mut().zip(people) {
+            if let Some((_, start, _)) = hold
+                && time.saturating_sub(start) > self.duration_s
+            {
+                hold = None;
+            }
+            if hold.is_none() {
+                let near = checkpoints.iter().position(|checkpoint| {
+                    positions.iter().any(|position| {
+                        great_circle_distance(*checkpoint, *position) <= self.distance_m
+                    })
+                });
+                if let Some(i) = near
+                    && last_near != near
+                {
+                    hold = Some((checkpoints[i], *time, *zoom + self.zoom_in));
+                }
+                last_near = near;
+            }
+            let Some((checkpoint, start, hold_zoom)) = hold else {
+                continue;
+            };
+            let t = time.saturating_sub(start) as f32 / self.duration_s.max(1) as f32;
+            let weight = smoother_step(t, 0.0, HOLD_TRANSITION)
+                .min(1.0 - smoother_step(t, 1.0 - HOLD_TRANSITION, 1.0));
+            *center = *center + (checkpoint - *center) * weight;
+            *zoom += (hold_zoom - *zoom) * weight;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lat_long_to_vec;
+
+    fn keyframe(fraction: f32, x: f32, zoom: f32) -> Keyframe {
+        Keyframe {
+            fraction,
+            center: Vector::new(x, 0.5),
+            zoom,
+        }
+    }
+
+    #[test]
+    fn interpolation() {
+        let timeline = Timeline::new(
+            vec![
+                keyframe(0.0, 0.2, 8.0),
+                keyframe(0.5, 0.4, 6.0),
+                keyframe(1.0, 0.4, 10.0),
+            ],
+            Easing::Linear,
+        )
+        .expect("in test");
+        assert_eq!(timeline.at(-1.0), (Vector::new(0.2, 0.5), 8.0));
+        assert_eq!(timeline.at(0.25), (Vector::new(0.3, 0.5), 7.0));
+        assert_eq!(timeline.at(0.5), (Vector::new(0.4, 0.5), 6.0));
+        assert_eq!(timeline.at(0.75), (Vector::new(0.4, 0.5), 8.0));
+        assert_eq!(timeline.at(2.0), (Vector::new(0.4, 0.5), 10.0));
+
+        let smooth = Timeline::new(
+            vec![keyframe(0.0, 0.2, 8.0), keyframe(1.0, 0.4, 10.0)],
+            Easing::Smooth,
+        )
+        .expect("in test");
+        assert_eq!(smooth.at(0.5).1, 9.0);
+        assert!(smooth.at(0.1).1 < 8.2);
+
+        let center = Vector::new(0.3, 0.3);
+        assert_eq!(Timeline::fixed(center, (5.0, 7.0)).at(0.5), (center, 6.0));
+        let single = Timeline::new(vec![keyframe(0.5, 0.2, 8.0)], Easing::Smooth);
+        assert_eq!(single.expect("in test").at(0.7).1, 8.0);
+    }
+
+    #[test]
+    fn weighted_framing() {
+        assert!(framing(&[]).is_none());
+        assert!(framing(&[(Vector::new(0.5, 0.5), 0.0)]).is_none());
+        let alone = framing(&[(Vector::new(0.5, 0.5), 1.0)]).expect("in test");
+        assert_eq!(alone, (Vector::new(0.5, 0.5), FRAMING_MAX_ZOOM));
+
+        let (a, b) = (Vector::new(0.5, 0.5), Vector::new(0.501, 0.5));
+        let (center, zoom) = framing(&[(a, 1.0), (b, 1.0)]).expect("in test");
+        assert!((center - Vector::new(0.5005, 0.5)).norm() < 1e-6);
+        // both are on screen
+        let half_width = WIDTH as f32 / 2.0 / (TILE_SIZE as f32 * 2f32.powf(zoom));
+        assert!((0.0005..0.01).contains(&half_width));
+
+        // a heavier weight pulls the center and narrows the spread
+        let (leader, leader_zoom) = framing(&[(a, 3.0), (b, 1.0)]).expect("in test");
+        assert!((leader - Vector::new(0.50025, 0.5)).norm() < 1e-6);
+        assert!(leader_zoom > zoom);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Timeline::new(Vec::new(), Easing::Linear).is_none());
+        assert!(
+            Timeline::new(
+                vec![keyframe(0.5, 0.2, 8.0), keyframe(0.5, 0.4, 6.0)],
+                Easing::Linear
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn checkpoint_hold() {
+        let checkpoint = lat_long_to_vec(45.0, 7.0);
+        let far = lat_long_to_vec(46.0, 7.0);
+        let hold = CheckpointHold {
+            distance_m: 1000.0,
+            duration_s: 60,
+            zoom_in: 1.0,
+        };
+        // one of the people is at the checkpoint from 50 to 150, the camera always frames both
+        let mut frames: Vec<_> = (0..=20).map(|i| (i * 10, far, 8.0)).collect();
+        let people: Vec<_> = frames
+            .iter()
+            .map(|(time, _, _)| {
+                let first = if (50..=150).contains(time) {
+                    checkpoint
+                } else {
+                    far
+                };
+                vec![first, far]
+            })
+            .collect();
+        hold.apply(&mut frames, &people, &[checkpoint]);
+        let at = |time| frames[time as usize / 10];
+        assert_eq!(at(40), (40, far, 8.0));
+        assert_eq!(at(50), (50, far, 8.0));
+        assert_eq!(at(80), (80, checkpoint, 9.0));
+        // still at the checkpoint after the hold, it does not start again
+        assert_eq!(at(120), (120, far, 8.0));
+        assert_eq!(at(140), (140, far, 8.0));
+        assert_eq!(at(160), (160, far, 8.0));
+    }
+}
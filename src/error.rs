@@ -0,0 +1,59 @@
+use std::io;
+
+use thiserror::Error;
+
+/// error of the public render functions, the variants tell network problems
+/// which are worth a retry apart from broken input files
+#[derive(Debug, Error)]
+pub enum RenderError {
+    #[error("could not download tile: {0}")]
+    TileDownload(#[from] reqwest::Error),
+    #[error("could not parse map data: {0}")]
+    MvtParse(String),
+    #[error("ffmpeg failed: {0}")]
+    Ffmpeg(String),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("could not parse input file: {0}")]
+    Parse(String),
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl From<anyhow::Error> for RenderError {
+    /// recovers the variant of errors which were passed on through `anyhow`
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<RenderError>() {
+            Ok(err) => return err,
+            Err(err) => err,
+        };
+        let err = match err.downcast::<reqwest::Error>() {
+            Ok(err) => return Self::TileDownload(err),
+            Err(err) => err,
+        };
+        match err.downcast::<io::Error>() {
+            Ok(err) => Self::Io(err),
+            Err(err) => Self::Other(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_anyhow() {
+        let ffmpeg: anyhow::Error = RenderError::Ffmpeg("exit status 1".to_string()).into();
+        assert!(matches!(
+            RenderError::from(ffmpeg.context("encoding video")),
+            RenderError::Ffmpeg(_)
+        ));
+        let io: anyhow::Error = io::Error::from(io::ErrorKind::NotFound).into();
+        assert!(matches!(RenderError::from(io), RenderError::Io(_)));
+        assert!(matches!(
+            RenderError::from(anyhow::anyhow!("something else")),
+            RenderError::Other(_)
+        ));
+    }
+}
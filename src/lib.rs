@@ -0,0 +1,557 @@
+//! Renders the animations of Ride or Die.
+//! The input files are read by the binary, other programs can render through
+//! [`render_still`] and [`render_animation`].
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    f32::consts::{FRAC_PI_4, PI, TAU},
+    fs::{self, File, read_dir},
+    path::PathBuf,
+    sync::{
+        LazyLock, OnceLock, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use anyhow::Result;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+
+pub mod bounded;
+mod config;
+mod draw;
+mod error;
+mod map;
+mod track;
+mod vec;
+
+use sha2::{Digest, Sha256};
+use track::Track;
+use vec::{Transform, Vector};
+
+#[cfg(feature = "bench")]
+pub use crate::draw::bench;
+use crate::{config::Config, draw::PinGroup, map::Style};
+pub use crate::{
+    draw::{
+        RenderInfo, Renderable, ScenePos,
+        api::{
+            AnimationParams, StillParams, render_animation, render_animation_with_progress,
+            render_still, render_still_with_progress,
+        },
+        parse,
+        progress::{Progress, ProgressEvent},
+        render_preview,
+    },
+    error::RenderError,
+    map::{MvtGetter, TileDescr, TileSource},
+};
+
+const WIDTH: usize = 1920 * 2;
+const HEIGHT: usize = 1080 * 2;
+const FRAME_RATE: f32 = 30.0;
+
+#[cfg(not(feature = "luca_build"))]
+pub const BASE_RES_PATH: LazyLock<PathBuf> = LazyLock::new(|| "./res".into());
+
+#[cfg(feature = "luca_build")]
+pub const BASE_RES_PATH: LazyLock<PathBuf> = LazyLock::new(|| "/Users/luca/rod".into());
+
+/// directories given on the command line, they take precedence over the `in` and `out` files
+static IN_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+static OUT_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+pub static IN_PATH: LazyLock<PathBuf> = LazyLock::new(|| match IN_OVERRIDE.get() {
+    Some(path) => path.clone(),
+    None => std::fs::read_to_string(BASE_RES_PATH.join("in"))
+        .expect("could not read in config file")
+        .trim()
+        .into(),
+});
+pub static OUT_PATH: LazyLock<PathBuf> = LazyLock::new(|| match OUT_OVERRIDE.get() {
+    Some(path) => path.clone(),
+    None => std::fs::read_to_string(BASE_RES_PATH.join("out"))
+        .expect("could not read out config file")
+        .trim()
+        .into(),
+});
+
+/// Replaces the directories of the `in` and `out` files,
+/// it has to be called before `IN_PATH` and `OUT_PATH` are first used.
+pub fn set_io_paths(in_path: Option<PathBuf>, out_path: Option<PathBuf>) {
+    if let Some(path) = in_path {
+        IN_OVERRIDE.set(path).expect("in path is only set once");
+    }
+    if let Some(path) = out_path {
+        OUT_OVERRIDE.set(path).expect("out path is only set once");
+    }
+}
+
+const PINS_PATH: LazyLock<PathBuf> = LazyLock::new(|| BASE_RES_PATH.join("pins"));
+const TRACK_PATH: LazyLock<PathBuf> = LazyLock::new(|| BASE_RES_PATH.join("tracks"));
+const CACHE_PATH: LazyLock<PathBuf> = LazyLock::new(|| BASE_RES_PATH.join("cache"));
+const STYLE_PATH: LazyLock<PathBuf> = LazyLock::new(|| BASE_RES_PATH.join("style.json"));
+const CONFIG_PATH: LazyLock<PathBuf> = LazyLock::new(|| BASE_RES_PATH.join("config.json"));
+const FRAMES_PATH: LazyLock<PathBuf> = LazyLock::new(|| BASE_RES_PATH.join("frames"));
+
+const PEOPLE: &'static [&'static str] = &[
+    "Clarissa", "Luca", "Flavio", "Louis", "Takashi", "Marc", "Ivo",
+];
+
+const FADE_MIN: f32 = 0.25;
+const FADE_MID: f32 = 0.5;
+const FADE_MAX: f32 = 0.75;
+
+pub enum OneOrTwo<T> {
+    One(T),
+    Two(T, T),
+}
+
+impl<T> OneOrTwo<T> {
+    pub fn map<S, F: Fn(T) -> S>(self, func: F) -> OneOrTwo<S> {
+        match self {
+            OneOrTwo::One(val) => OneOrTwo::One(func(val)),
+            OneOrTwo::Two(a, b) => OneOrTwo::Two(func(a), func(b)),
+        }
+    }
+
+    pub fn one(self) -> Option<T> {
+        match self {
+            OneOrTwo::One(val) => Some(val),
+            OneOrTwo::Two(_, _) => None,
+        }
+    }
+
+    pub fn two(self) -> Option<(T, T)> {
+        match self {
+            OneOrTwo::One(_) => None,
+            OneOrTwo::Two(a, b) => Some((a, b)),
+        }
+    }
+}
+
+impl<T> OneOrTwo<Option<T>> {
+    pub fn as_opt(self) -> Option<OneOrTwo<T>> {
+        match self {
+            OneOrTwo::One(Some(val)) => Some(OneOrTwo::One(val)),
+            OneOrTwo::Two(Some(a), Some(b)) => Some(OneOrTwo::Two(a, b)),
+            _ => None,
+        }
+    }
+}
+
+impl<T: Clone> OneOrTwo<T> {
+    pub fn splat(self) -> (T, T) {
+        match self {
+            OneOrTwo::One(val) => (val.clone(), val),
+            OneOrTwo::Two(a, b) => (a, b),
+        }
+    }
+}
+
+fn smooth_step(x: f32, edge0: f32, edge1: f32) -> f32 {
+    // edges which meet would divide 0 by 0
+    if edge1 <= edge0 {
+        return if x < edge0 { 0.0 } else { 1.0 };
+    }
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - t * 2.0)
+}
+
+fn smoother_step(x: f32, edge0: f32, edge1: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    return t * t * t * (t * (6.0 * t - 15.0) + 10.0);
+}
+
+pub fn fade_in_function(x: f32) -> f32 {
+    assert!(
+        FADE_MIN <= x && x <= FADE_MAX,
+        "fade in function used outside of its designed interval\n x was {x}"
+    );
+    if x > FADE_MID {
+        return 1.0;
+    }
+    smooth_step(x, FADE_MIN, FADE_MID)
+}
+
+pub fn fade_out_function(x: f32) -> f32 {
+    assert!(
+        FADE_MIN <= x && x <= FADE_MAX,
+        "fade in function used outside of its designed interval\n x was {x}"
+    );
+    if x < FADE_MID {
+        return 1.0;
+    }
+    1.0 - smooth_step(x, FADE_MID, FADE_MAX)
+}
+
+/// latitude in degrees where web mercator ends, the map is square up to here
+const MAX_MERCATOR_LAT: f32 = 85.051_13;
+
+/// set once a clamped latitude or a NaN coordinate was logged, the graticule hits them every frame
+static LOGGED_CLAMP: AtomicBool = AtomicBool::new(false);
+static LOGGED_NAN: AtomicBool = AtomicBool::new(false);
+
+/// Takes latiude and longitude in degrees and returns world coordinates,
+/// latitudes beyond the range of web mercator are clamped to it and NaN is replaced by 0
+pub fn lat_long_to_vec(lat: f32, lon: f32) -> Vector {
+    if (lat.is_nan() || lon.is_nan()) && !LOGGED_NAN.swap(true, Ordering::Relaxed) {
+        warn!("coordinates {lat}, {lon} are not a number, using 0 instead");
+    }
+    let not_nan = |x: f32| if x.is_nan() { 0.0 } else { x };
+    let (lat, lon) = (not_nan(lat), not_nan(lon));
+    let clamped = lat.clamp(-MAX_MERCATOR_LAT, MAX_MERCATOR_LAT);
+    if clamped != lat && !LOGGED_CLAMP.swap(true, Ordering::Relaxed) {
+        warn!(
+            "latitude {lat} is outside of the mercator projection, using {clamped}, \
+            further latitudes outside of it are clamped without a warning"
+        );
+    }
+    let lat = clamped;
+    Vector::new(
+        0.5 + lon / 360.0,
+        (PI - (FRAC_PI_4 + lat.to_radians() / 2.0).tan().ln()) / TAU,
+    )
+}
+
+/// Takes world coordinates and returns latitude and longitude in degrees,
+/// inverse of `lat_long_to_vec`
+pub fn vec_to_lat_long(vec: Vector) -> (f32, f32) {
+    let lat = (PI - TAU * vec.y).sinh().atan().to_degrees();
+    let lon = (vec.x - 0.5) * 360.0;
+    (lat, lon)
+}
+
+/// Great circle distance in meters between two points in world coordinates,
+/// unlike distances on the map it is not stretched towards the poles
+pub fn great_circle_distance(a: Vector, b: Vector) -> f32 {
+    track::haversine(vec_to_lat_long(a), vec_to_lat_long(b))
+}
+
+/// problems of the style file, which would otherwise only show up as a panic when loading it
+pub fn validate_style() -> Vec<String> {
+    Style::validate(&*STYLE_PATH)
+}
+
+/// problems of the config which depend on the style of `world`
+pub fn validate_config(world: &World) -> Vec<String> {
+    match &CONFIG.day_night {
+        Some(day_night) => day_night.problems(world.style.palette().len()),
+        None => Vec::new(),
+    }
+}
+
+/// writes `<name>.kml` for every track and `checkpoints.kml` with the route into `dir`
+pub fn export_kml(dir: &std::path::Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    for (name, track) in &WORLD.tracks {
+        fs::write(dir.join(format!("{name}.kml")), track.to_kml(name))?;
+    }
+    fs::write(
+        dir.join("checkpoints.kml"),
+        track::checkpoints_to_kml(&WORLD.checkpoints),
+    )?;
+    Ok(())
+}
+
+/// Everything needed to render a frame: the map data, the style and the people.
+/// `main` uses the global `WORLD`, tests can build their own from fixtures.
+pub struct World {
+    map: RwLock<MvtGetter>,
+    style: Style,
+    tracks: HashMap<String, Track>,
+    /// in the order of the route
+    checkpoints: Vec<(String, (Vector, PinGroup))>,
+    /// departures and arrivals at the checkpoints
+    checkpoint_events: Vec<(Vector, u32)>,
+    /// first and last time of any track, see [`World::time_range`]
+    time_range: Option<(u32, u32)>,
+}
+
+impl World {
+    pub fn new() -> Self {
+        Self::with_map(MvtGetter::new().expect("failed to initialize MvtGetter"))
+    }
+
+    pub fn with_map(map: MvtGetter) -> Self {
+        Self::from_parts(
+            map,
+            Style::from_path(&*STYLE_PATH).expect("could not decode style"),
+            track::get_tracks().expect("could not load tracks"),
+            track::get_checkpoints().expect("could not load checkpoints"),
+        )
+    }
+
+    pub fn from_parts(
+        map: MvtGetter,
+        style: Style,
+        tracks: HashMap<String, Track>,
+        checkpoints: Vec<(String, (Vector, PinGroup))>,
+    ) -> Self {
+        let points = tracks.values().flat_map(|track| &track.points);
+        let time_range = points
+            .clone()
+            .map(|point| point.time)
+            .min()
+            .zip(points.map(|point| point.time).max());
+        World {
+            map: RwLock::new(map),
+            style,
+            time_range,
+            tracks,
+            checkpoint_events: track::get_checkpoint_events(&checkpoints),
+            checkpoints,
+        }
+    }
+}
+
+/// Every tile drawn at `scene`, of both zoom levels while they cross-fade.
+/// Nothing is loaded, so it can tell which tiles to download ahead of a render.
+pub fn tiles_for(scene: ScenePos) -> Vec<TileDescr> {
+    tiles_in(scene.zoom, (scene.world_min(), scene.world_max()))
+}
+
+/// like [`tiles_for`] for the part of the world between the corners of `extent`
+pub fn tiles_in(zoom: f32, extent: (Vector, Vector)) -> Vec<TileDescr> {
+    match tiles_at(zoom, extent, CONFIG.zoom_cross_fade) {
+        OneOrTwo::One(tiles) => tiles,
+        OneOrTwo::Two(mut a, b) => {
+            a.extend(b);
+            a
+        }
+    }
+}
+
+/// without `cross_fade` only the level nearest to the zoom is used
+fn tiles_at(zoom: f32, extent: (Vector, Vector), cross_fade: bool) -> OneOrTwo<Vec<TileDescr>> {
+    let floor_zoom = zoom.floor();
+    let frac_zoom = zoom - floor_zoom;
+    if floor_zoom as u32 >= 14 {
+        return OneOrTwo::One(tiles_fixed(extent, 14));
+    }
+    if !cross_fade {
+        return OneOrTwo::One(tiles_fixed(extent, zoom.round() as u32));
+    }
+    match frac_zoom {
+        0.0..=FADE_MIN => OneOrTwo::One(tiles_fixed(extent, floor_zoom as u32)),
+        FADE_MIN..=FADE_MAX => OneOrTwo::Two(
+            tiles_fixed(extent, floor_zoom as u32),
+            tiles_fixed(extent, floor_zoom as u32 + 1),
+        ),
+        FADE_MAX..=1.0 => OneOrTwo::One(tiles_fixed(extent, floor_zoom as u32 + 1)),
+        _ => unreachable!("all values of the fractionals are covered"),
+    }
+}
+
+/// the valid tiles of level `zoom` between the world corners `extent`
+fn tiles_fixed((min, max): (Vector, Vector), zoom: u32) -> Vec<TileDescr> {
+    let min_x = (min.x * 2f32.powi(zoom as i32).floor()) as u32;
+    let min_y = (min.y * 2f32.powi(zoom as i32).floor()) as u32;
+    let max_x = (max.x * 2f32.powi(zoom as i32).floor()) as u32;
+    let max_y = (max.y * 2f32.powi(zoom as i32).floor()) as u32;
+    let mut tiles = Vec::new();
+    for x in min_x..=max_x {
+        for y in min_y..=max_y {
+            let tile = TileDescr { z: zoom, x, y };
+            if !tile.valid() {
+                error!("encountered invalid tile: {:?}", tile);
+                continue;
+            }
+            tiles.push(tile)
+        }
+    }
+    tiles
+}
+
+impl World {
+    pub fn load_tiles_at(&self, scene: ScenePos) -> Result<()> {
+        self.load_tiles(&tiles_for(scene))
+    }
+
+    pub fn load_tiles(&self, tiles: &[TileDescr]) -> Result<()> {
+        let mut lock = self.map.write().expect("RwLock not poisoned");
+        lock.load_tiles(tiles, &self.style)?;
+        drop(lock);
+        Ok(())
+    }
+}
+
+impl World {
+    pub fn get_track(&self, name: &str) -> Option<&Track> {
+        self.tracks.get(name)
+    }
+
+    /// first and last time of any track, `None` if there are no tracking points
+    pub fn time_range(&self) -> Option<(u32, u32)> {
+        self.time_range
+    }
+}
+
+pub static WORLD: LazyLock<World> = LazyLock::new(World::new);
+
+pub static CONFIG: LazyLock<Config> = LazyLock::new(|| match File::open(&*CONFIG_PATH) {
+    Ok(file) => serde_json::from_reader(file).expect("could not load config"),
+    Err(_) => Config::default(),
+});
+
+pub fn hash_file(path: impl AsRef<std::path::Path>) -> String {
+    let buf = fs::read(path).expect("path is always valid");
+    let hash = Sha256::digest(&buf);
+    hex::encode(hash)
+}
+
+/// Hash of everything besides the frame itself that changes how frames look:
+/// the build of this program, the style, the config, the tracks and the pins.
+static RESOURCES_VERSION: LazyLock<String> = LazyLock::new(|| {
+    let mut hasher = Sha256::new();
+    hasher.update(env!("CARGO_PKG_VERSION"));
+    // every change to the drawing code changes the executable
+    match std::env::current_exe().and_then(fs::read) {
+        Ok(executable) => hasher.update(Sha256::digest(executable)),
+        Err(e) => warn!("could not read the executable, cached frames may be stale: {e}"),
+    }
+    for path in [&*STYLE_PATH, &*CONFIG_PATH] {
+        if path.exists() {
+            hasher.update(hash_file(path));
+        }
+    }
+    for dir in [&*TRACK_PATH, &*PINS_PATH] {
+        let mut paths: Vec<_> = read_dir(dir)
+            .expect("resource directories exist")
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file())
+            .collect();
+        paths.sort();
+        for path in paths {
+            hasher.update(path.to_string_lossy().as_bytes());
+            hasher.update(hash_file(path));
+        }
+    }
+    // extra pin images can be in subdirectories or anywhere else
+    let mut extra_images: Vec<_> = CONFIG
+        .extra_pins
+        .values()
+        .flatten()
+        .map(|extra| draw::pin_image_path(&extra.image))
+        .filter(|path| path.is_file())
+        .collect();
+    extra_images.sort();
+    extra_images.dedup();
+    for path in extra_images {
+        hasher.update(path.to_string_lossy().as_bytes());
+        hasher.update(hash_file(path));
+    }
+    hex::encode(hasher.finalize())
+});
+
+/// hashes of the style and of every track file, like `WORLD` they are only read once
+static LOADED_HASHES: LazyLock<(String, HashMap<String, String>)> = LazyLock::new(|| {
+    let style = hash_file(&*STYLE_PATH);
+    let tracks = PEOPLE
+        .iter()
+        .filter_map(|name| {
+            let path = TRACK_PATH.join(format!("{name}.txt"));
+            path.exists().then(|| (name.to_string(), hash_file(path)))
+        })
+        .collect();
+    (style, tracks)
+});
+
+/// The resource files an output depends on besides its input file,
+/// if one of them changes the output has to be rendered again.
+#[derive(Serialize, Deserialize, PartialEq)]
+pub struct ResourceHashes {
+    pub style: String,
+    /// hashes of the tracks of the shown people by name
+    pub tracks: BTreeMap<String, String>,
+}
+
+impl ResourceHashes {
+    /// hashes of the style and the tracks of `people`, everyone if it is empty
+    pub fn for_people(people: &[String]) -> Self {
+        let (style, tracks) = &*LOADED_HASHES;
+        Self {
+            style: style.clone(),
+            tracks: tracks
+                .iter()
+                .filter(|(name, _)| people.is_empty() || people.contains(name))
+                .map(|(name, hash)| (name.clone(), hash.clone()))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn extreme_latitudes() {
+        for lat in [90.0, -90.0, 89.0, f32::INFINITY] {
+            let v = lat_long_to_vec(lat, 0.0);
+            assert!(
+                v.y.is_finite() && (-1e-4..=1.0 + 1e-4).contains(&v.y),
+                "{lat}: {v:?}"
+            );
+        }
+        assert_eq!(
+            lat_long_to_vec(90.0, 0.0),
+            lat_long_to_vec(MAX_MERCATOR_LAT, 0.0)
+        );
+        assert!(lat_long_to_vec(MAX_MERCATOR_LAT, 0.0).y.abs() < 1e-4);
+        assert_eq!(lat_long_to_vec(0.0, 0.0), Vector::new(0.5, 0.5));
+        assert_eq!(lat_long_to_vec(f32::NAN, f32::NAN), Vector::new(0.5, 0.5));
+    }
+
+    #[test]
+    fn tiles_for_scene() {
+        // the screen is smaller than a tile, at the corner of four tiles it needs all of them
+        let center = Vector::new(66.0 / 128.0, 44.0 / 128.0);
+        let tiles = tiles_for(ScenePos::new(center, 7.0, 0));
+        let expected: Vec<_> = [(65, 43), (65, 44), (66, 43), (66, 44)]
+            .map(|(x, y)| TileDescr { z: 7, x, y })
+            .to_vec();
+        assert_eq!(tiles, expected);
+
+        // while cross-fading both levels are needed
+        let fading = tiles_for(ScenePos::new(center, 7.5, 0));
+        assert!(fading.iter().any(|tile| tile.z == 7) && fading.iter().any(|tile| tile.z == 8));
+        for (zoom, level) in [(7.4, 7), (7.5, 8), (13.9, 14)] {
+            let scene = ScenePos::new(center, zoom, 0);
+            let OneOrTwo::One(tiles) =
+                tiles_at(zoom, (scene.world_min(), scene.world_max()), false)
+            else {
+                panic!("only one level without cross-fade");
+            };
+            assert!(tiles.iter().all(|tile| tile.z == level));
+        }
+
+        let middle = tiles_for(ScenePos::new(
+            Vector::new(66.5 / 128.0, 44.5 / 128.0),
+            7.0,
+            0,
+        ));
+        assert_eq!(middle, vec![TileDescr { z: 7, x: 66, y: 44 }]);
+
+        // tiles left of the map are not requested
+        let edge = tiles_for(ScenePos::new(Vector::new(0.0, 0.5), 3.0, 0));
+        assert!(edge.iter().all(|tile| tile.valid() && tile.x < 4));
+    }
+
+    #[test]
+    fn lat_long_round_trip() {
+        for (lat, lon) in [(0.0, 0.0), (47.3, 7.6), (-33.9, 151.2), (60.0, -120.0)] {
+            let (lat_back, lon_back) = vec_to_lat_long(lat_long_to_vec(lat, lon));
+            assert!((lat_back - lat).abs() < 1e-3 && (lon_back - lon).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn city_distance() {
+        let paris = lat_long_to_vec(48.8566, 2.3522);
+        let london = lat_long_to_vec(51.5074, -0.1278);
+        let d = great_circle_distance(paris, london);
+        assert!((d - 343_500.0).abs() < 1_000.0, "{d}");
+        assert_eq!(great_circle_distance(paris, paris), 0.0);
+    }
+}
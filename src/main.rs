@@ -1,275 +1,176 @@
 use std::{
     collections::HashMap,
-    f32::consts::{FRAC_PI_4, PI, TAU},
-    fs::{self, File, read_dir},
-    io::{self},
+    fs::{File, read_dir},
+    io,
     path::PathBuf,
-    sync::{LazyLock, Mutex, RwLock},
-    time::Instant,
+    sync::{LazyLock, Mutex},
+    time::{Duration, Instant},
 };
 
-use anyhow::Result;
-use log::{error, info};
-
-mod draw;
-mod map;
-mod track;
-mod vec;
-
-use draw::ScenePos;
-use map::MvtGetter;
+use anyhow::{Result, anyhow};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use track::Track;
-use vec::{Transform, Vector};
 
-use crate::{
-    draw::{Pin, Renderable, parse},
-    map::TileDescr,
+use rod_animations::{
+    BASE_RES_PATH, CONFIG, IN_PATH, MvtGetter, OUT_PATH, Progress, RenderInfo, Renderable,
+    ResourceHashes, WORLD, export_kml, hash_file, parse, render_preview, set_io_paths,
+    validate_config, validate_style,
 };
 
-const WIDTH: usize = 1920 * 2;
-const HEIGHT: usize = 1080 * 2;
-const FRAME_RATE: f32 = 30.0;
-
-#[cfg(not(feature = "luca_build"))]
-const BASE_RES_PATH: LazyLock<PathBuf> = LazyLock::new(|| "./res".into());
-
-#[cfg(feature = "luca_build")]
-const BASE_RES_PATH: LazyLock<PathBuf> = LazyLock::new(|| "/Users/luca/rod".into());
-
-const IN_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
-    std::fs::read_to_string(BASE_RES_PATH.join("in"))
-        .expect("could not read in config file")
-        .trim()
-        .into()
-});
-const OUT_PATH: LazyLock<PathBuf> = LazyLock::new(|| {
-    std::fs::read_to_string(BASE_RES_PATH.join("out"))
-        .expect("could not read out config file")
-        .trim()
-        .into()
-});
-
-const PINS_PATH: LazyLock<PathBuf> = LazyLock::new(|| BASE_RES_PATH.join("pins"));
-const TRACK_PATH: LazyLock<PathBuf> = LazyLock::new(|| BASE_RES_PATH.join("tracks"));
-const CACHE_PATH: LazyLock<PathBuf> = LazyLock::new(|| BASE_RES_PATH.join("cache"));
-const STYLE_PATH: LazyLock<PathBuf> = LazyLock::new(|| BASE_RES_PATH.join("style.json"));
 const HASHES_PATH: LazyLock<PathBuf> = LazyLock::new(|| BASE_RES_PATH.join("hashes.json"));
-
-const PEOPLE: &'static [&'static str] = &[
-    "Clarissa", "Luca", "Flavio", "Louis", "Takashi", "Marc", "Ivo",
-];
-
-const FADE_MIN: f32 = 0.25;
-const FADE_MID: f32 = 0.5;
-const FADE_MAX: f32 = 0.75;
-
-pub enum OneOrTwo<T> {
-    One(T),
-    Two(T, T),
-}
-
-impl<T> OneOrTwo<T> {
-    pub fn map<S, F: Fn(T) -> S>(self, func: F) -> OneOrTwo<S> {
-        match self {
-            OneOrTwo::One(val) => OneOrTwo::One(func(val)),
-            OneOrTwo::Two(a, b) => OneOrTwo::Two(func(a), func(b)),
-        }
-    }
-
-    pub fn one(self) -> Option<T> {
-        match self {
-            OneOrTwo::One(val) => Some(val),
-            OneOrTwo::Two(_, _) => None,
-        }
-    }
-
-    pub fn two(self) -> Option<(T, T)> {
-        match self {
-            OneOrTwo::One(_) => None,
-            OneOrTwo::Two(a, b) => Some((a, b)),
-        }
-    }
-}
-
-impl<T> OneOrTwo<Option<T>> {
-    pub fn as_opt(self) -> Option<OneOrTwo<T>> {
-        match self {
-            OneOrTwo::One(Some(val)) => Some(OneOrTwo::One(val)),
-            OneOrTwo::Two(Some(a), Some(b)) => Some(OneOrTwo::Two(a, b)),
-            _ => None,
-        }
-    }
-}
-
-impl<T: Clone> OneOrTwo<T> {
-    pub fn splat(self) -> (T, T) {
-        match self {
-            OneOrTwo::One(val) => (val.clone(), val),
-            OneOrTwo::Two(a, b) => (a, b),
-        }
-    }
-}
-
-fn smooth_step(x: f32, edge0: f32, edge1: f32) -> f32 {
-    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
-    t * t * (3.0 - t * 2.0)
+const MANIFEST_PATH: LazyLock<PathBuf> = LazyLock::new(|| BASE_RES_PATH.join("manifest.json"));
+
+/// what an output was rendered from, it is skipped while none of it changes
+#[derive(Serialize, Deserialize, PartialEq)]
+struct FileHashes {
+    input: String,
+    #[serde(flatten)]
+    resources: ResourceHashes,
+    /// hash of the music of a video, if it has any
+    audio: Option<String>,
 }
 
-fn smoother_step(x: f32, edge0: f32, edge1: f32) -> f32 {
-    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
-    return t * t * t * (t * (6.0 * t - 15.0) + 10.0);
-}
-
-pub fn fade_in_function(x: f32) -> f32 {
-    assert!(
-        FADE_MIN <= x && x <= FADE_MAX,
-        "fade in function used outside of its designed interval\n x was {x}"
-    );
-    if x > FADE_MID {
-        return 1.0;
-    }
-    smooth_step(x, FADE_MIN, FADE_MID)
-}
-
-pub fn fade_out_function(x: f32) -> f32 {
-    assert!(
-        FADE_MIN <= x && x <= FADE_MAX,
-        "fade in function used outside of its designed interval\n x was {x}"
-    );
-    if x < FADE_MID {
-        return 1.0;
-    }
-    1.0 - smooth_step(x, FADE_MID, FADE_MAX)
-}
-
-/// Takes latiude and longitude in degrees and returns world coordinates
-pub fn lat_long_to_vec(lat: f32, lon: f32) -> Vector {
-    Vector::new(
-        0.5 + lon / 360.0,
-        (PI - (FRAC_PI_4 + lat.to_radians() / 2.0).tan().ln()) / TAU,
-    )
-}
-
-struct World {
-    map: &'static RwLock<MvtGetter>,
-    tracks: HashMap<String, Track>,
-    checkpoints: HashMap<String, (Vector, Pin)>,
-}
-
-impl World {
-    pub fn new() -> Self {
-        World {
-            map: &MAP_DATA,
-            tracks: track::get_tracks().expect("could not load tracks"),
-            checkpoints: track::get_checkpoints().expect("could not load checkpoints"),
+impl FileHashes {
+    fn new(path: &std::path::Path, renderable: &dyn Renderable) -> Self {
+        let info = renderable.info();
+        Self {
+            input: hash_input(path),
+            resources: ResourceHashes::for_people(&info.people),
+            audio: info.audio.map(hash_file),
         }
     }
 }
 
-impl World {
-    pub fn get_tiles_at(&self, scene: ScenePos) -> OneOrTwo<Vec<TileDescr>> {
-        let floor_zoom = scene.zoom.floor();
-        let frac_zoom = scene.zoom - floor_zoom;
-        if floor_zoom as u32 >= 14 {
-            return OneOrTwo::One(self.get_tiles_fixed(scene, 14));
-        }
-        match frac_zoom {
-            0.0..=FADE_MIN => OneOrTwo::One(self.get_tiles_fixed(scene, floor_zoom as u32)),
-            FADE_MIN..=FADE_MAX => OneOrTwo::Two(
-                self.get_tiles_fixed(scene, floor_zoom as u32),
-                self.get_tiles_fixed(scene, floor_zoom as u32 + 1),
-            ),
-            FADE_MAX..=1.0 => OneOrTwo::One(self.get_tiles_fixed(scene, floor_zoom as u32 + 1)),
-            _ => unreachable!("all values of the fractionals are covered"),
-        }
-    }
-
-    pub fn get_tiles_fixed(&self, scene: ScenePos, zoom: u32) -> Vec<TileDescr> {
-        let min_x = (scene.world_min().x * 2f32.powi(zoom as i32).floor()) as u32;
-        let min_y = (scene.world_min().y * 2f32.powi(zoom as i32).floor()) as u32;
-        let max_x = (scene.world_max().x * 2f32.powi(zoom as i32).floor()) as u32;
-        let max_y = (scene.world_max().y * 2f32.powi(zoom as i32).floor()) as u32;
-        let mut tiles = Vec::new();
-        for x in min_x..=max_x {
-            for y in min_y..=max_y {
-                let tile = TileDescr { z: zoom, x, y };
-                if !tile.valid() {
-                    error!("encountered invalid tile: {:?}", tile);
-                    continue;
-                }
-                tiles.push(tile)
-            }
-        }
-        tiles
+/// hash of the input file and the files it includes, only of the file if it includes none
+fn hash_input(path: &std::path::Path) -> String {
+    let included = parse::included_files(path);
+    if included.is_empty() {
+        return hash_file(path);
     }
-
-    pub fn load_tiles_at(&self, scene: ScenePos) -> Result<()> {
-        let mut lock = self.map.write().expect("RwLock not poisoned");
-
-        match self.get_tiles_at(scene) {
-            OneOrTwo::One(tiles) => lock.load_tiles(&tiles)?,
-            OneOrTwo::Two(a, b) => {
-                lock.load_tiles(&a)?;
-                lock.load_tiles(&b)?;
-            }
-        }
-        drop(lock);
-        Ok(())
+    let mut hasher = Sha256::new();
+    hasher.update(hash_file(path));
+    for file in included {
+        hasher.update(file.to_string_lossy().as_bytes());
+        hasher.update(hash_file(file));
     }
+    hex::encode(hasher.finalize())
 }
 
-impl World {
-    pub fn get_track(&self, name: &str) -> Option<&Track> {
-        self.tracks.get(name)
+/// key of an output in the hash file, a file with `Einzeln` has one entry per person
+fn hashes_key(path: &std::path::Path, renderable: &dyn Renderable) -> String {
+    let name = renderable.name();
+    if path.file_stem().and_then(|s| s.to_str()) == Some(name) {
+        path.to_string_lossy().into_owned()
+    } else {
+        format!("{}:{}", path.to_string_lossy(), name)
     }
 }
 
-static WORLD: LazyLock<World> = LazyLock::new(World::new);
-
-static MAP_DATA: LazyLock<RwLock<MvtGetter>> =
-    LazyLock::new(|| RwLock::new(MvtGetter::new().expect("failed to initialize MvtGetter")));
-
-static FILE_HASHES: LazyLock<Mutex<HashMap<String, String>>> =
+static FILE_HASHES: LazyLock<Mutex<HashMap<String, FileHashes>>> =
     LazyLock::new(|| match File::open(&*HASHES_PATH) {
-        Ok(file) => Mutex::new(serde_json::from_reader(file).expect("could not load file hashes")),
+        Ok(file) => Mutex::new(serde_json::from_reader(file).unwrap_or_else(|err| {
+            warn!("could not load file hashes, every input file is rendered: {err}");
+            HashMap::new()
+        })),
         Err(_) => Mutex::new(HashMap::new()),
     });
 
-fn hash_file(path: impl AsRef<std::path::Path>) -> String {
-    let buf = fs::read(path).expect("path is always valid");
-    let hash = Sha256::digest(&buf);
-    hex::encode(hash)
+/// one entry per file rendered in this run
+#[derive(Serialize)]
+struct ManifestEntry {
+    file: PathBuf,
+    #[serde(flatten)]
+    info: RenderInfo,
+    render_time_s: f32,
 }
 
-fn process_renderable(path: std::path::PathBuf, renderable: Box<dyn Renderable>) {
+static MANIFEST: Mutex<Vec<ManifestEntry>> = Mutex::new(Vec::new());
+
+/// renders `renderable` read from `path`, returns false if rendering failed
+fn process_renderable(path: std::path::PathBuf, renderable: Box<dyn Renderable>) -> bool {
     let name = renderable.name().to_string();
+    let hashes = FileHashes::new(&path, &*renderable);
+    let key = hashes_key(&path, &*renderable);
+    let file = renderable.get_file_name();
+    let info = renderable.info();
     let start = Instant::now();
     info!("rendering {}", name);
-    match renderable.make_file() {
+    match renderable.make_file(&WORLD, &Progress::default()) {
         Ok(_) => {
             info!(
                 "took {}s to render: {}",
                 start.elapsed().as_secs_f32(),
                 name
             );
-            (*FILE_HASHES.lock().expect("not poisoned"))
-                .insert(path.to_string_lossy().into_owned(), hash_file(&path));
+            (*FILE_HASHES.lock().expect("not poisoned")).insert(key, hashes);
+            MANIFEST.lock().expect("not poisoned").push(ManifestEntry {
+                file,
+                info,
+                render_time_s: start.elapsed().as_secs_f32(),
+            });
+            true
         }
-        Err(err) => error!("could not render: {} reason: {}", name, err),
-    };
+        Err(err) => {
+            error!("could not render: {} reason: {}", name, err);
+            false
+        }
+    }
 }
 
-fn init() {
+/// renders the outputs in `failed`, given by file and name, once more, the tiles loaded by
+/// the first attempt often make the second one succeed. Returns the outputs which failed again.
+fn retry_failed(failed: Vec<(PathBuf, String)>) -> Vec<(PathBuf, String)> {
+    failed
+        .into_iter()
+        .filter(|(path, name)| {
+            info!("retrying {name}");
+            match parse::from_path(path) {
+                Ok(renderables) => match renderables.into_iter().find(|r| r.name() == name) {
+                    Some(r) => !process_renderable(path.clone(), r),
+                    None => {
+                        error!("{name} is no longer in {path:?}");
+                        true
+                    }
+                },
+                Err(err) => {
+                    error!("could not read file: {}", err);
+                    true
+                }
+            }
+        })
+        .collect()
+}
+
+fn init_logger() {
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
         .format_timestamp(None)
         .init();
+}
 
+fn init() {
     let start = Instant::now();
-    LazyLock::force(&MAP_DATA);
+    LazyLock::force(&CONFIG);
+    let problems = validate_style();
+    if !problems.is_empty() {
+        error!(
+            "the style has {} problems:\n  {}",
+            problems.len(),
+            problems.join("\n  ")
+        );
+        std::process::exit(1);
+    }
     LazyLock::force(&WORLD);
+    let problems = validate_config(&WORLD);
+    if !problems.is_empty() {
+        error!(
+            "the config has {} problems:\n  {}",
+            problems.len(),
+            problems.join("\n  ")
+        );
+        std::process::exit(1);
+    }
     LazyLock::force(&FILE_HASHES);
-    LazyLock::force(&map::SORTERS);
     LazyLock::force(&OUT_PATH);
     assert!(
         std::path::Path::exists(&OUT_PATH.as_ref()),
@@ -287,8 +188,163 @@ fn init() {
     );
 }
 
+/// removes `--in <dir>` and `--out <dir>` from `args`, they replace the directories
+/// in the `in` and `out` files of the resource directory
+fn take_io_paths(args: &mut Vec<String>) -> Result<()> {
+    let mut take = |flag: &str| -> Result<Option<PathBuf>> {
+        let Some(i) = args.iter().position(|arg| arg == flag) else {
+            return Ok(None);
+        };
+        if i + 1 == args.len() {
+            return Err(anyhow!("missing directory after {flag}"));
+        }
+        let dir = args.remove(i + 1);
+        args.remove(i);
+        Ok(Some(dir.into()))
+    };
+    let in_path = take("--in")?;
+    let out_path = take("--out")?;
+    set_io_paths(in_path, out_path);
+    Ok(())
+}
+
+/// `clean-cache [--max-age-days N] [--max-size-mb N]`
+fn clean_cache(args: &[String]) -> Result<()> {
+    let mut max_age = None;
+    let mut max_size = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or(anyhow!("missing value for {arg}"))?;
+        match arg.as_str() {
+            "--max-age-days" => {
+                max_age = Some(Duration::from_secs(value.parse::<u64>()? * 60 * 60 * 24))
+            }
+            "--max-size-mb" => max_size = Some(value.parse::<u64>()? * 1024 * 1024),
+            _ => return Err(anyhow!("unknown argument {arg}")),
+        }
+    }
+    if max_age.is_none() && max_size.is_none() {
+        return Err(anyhow!(
+            "usage: clean-cache [--max-age-days N] [--max-size-mb N]"
+        ));
+    }
+    let (deleted, freed) = MvtGetter::new()?.clean_cache(max_age, max_size)?;
+    info!(
+        "deleted {deleted} cached tiles, freed {:.1} MB",
+        freed as f64 / 1024.0 / 1024.0
+    );
+    Ok(())
+}
+
+/// `--preview <progress> <file>` renders the frame `progress` (0 to 1) of the way through `file`
+fn preview(args: &[String]) -> Result<PathBuf> {
+    let [progress, file] = args else {
+        return Err(anyhow!("usage: --preview <progress from 0 to 1> <file>"));
+    };
+    let progress: f32 = progress.parse()?;
+    if !(0.0..=1.0).contains(&progress) {
+        return Err(anyhow!(
+            "progress has to be between 0 and 1, got {progress}"
+        ));
+    }
+    let renderables = parse::from_path(file)?;
+    if renderables.len() > 1 {
+        info!(
+            "{file} renders {} outputs, the preview shows {}",
+            renderables.len(),
+            renderables[0].name()
+        );
+    }
+    render_preview(&*renderables[0], progress, &WORLD)
+}
+
+/// parses every input file without rendering and returns the names of the ones that failed
+fn validate() -> Result<Vec<String>> {
+    let mut paths: Vec<_> = read_dir(&*IN_PATH)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().and_then(|s| s.to_str()) == Some("txt"))
+        .collect();
+    paths.sort();
+    let mut failed = Vec::new();
+    for path in &paths {
+        let name = path
+            .file_name()
+            .expect("allways has file name")
+            .to_string_lossy();
+        match parse::from_path(path) {
+            Ok(renderables) if renderables.len() > 1 => {
+                info!("{name} ist gültig ({} Ausgaben)", renderables.len())
+            }
+            Ok(_) => info!("{name} ist gültig"),
+            Err(err) => {
+                error!("{name} ist ungültig: {err}");
+                failed.push(name.into_owned());
+            }
+        }
+    }
+    info!(
+        "{} von {} Dateien sind gültig",
+        paths.len() - failed.len(),
+        paths.len()
+    );
+    Ok(failed)
+}
+
 fn main() {
+    init_logger();
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(err) = take_io_paths(&mut args) {
+        error!("{err}");
+        std::process::exit(1);
+    }
+    if args.first().map(String::as_str) == Some("clean-cache") {
+        if let Err(err) = clean_cache(&args[1..]) {
+            error!("could not clean cache: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.first().map(String::as_str) == Some("export-kml") {
+        let Some(dir) = args.get(1) else {
+            error!("usage: export-kml <directory>");
+            std::process::exit(1);
+        };
+        if let Err(err) = export_kml(dir.as_ref()) {
+            error!("could not export kml: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.first().map(String::as_str) == Some("--validate") {
+        init();
+        match validate() {
+            Ok(failed) if failed.is_empty() => return,
+            Ok(failed) => error!("ungültige Dateien: {}", failed.join(", ")),
+            Err(err) => error!("could not validate input files: {err}"),
+        }
+        std::process::exit(1);
+    }
+
+    if args.first().map(String::as_str) == Some("--preview") {
+        init();
+        match preview(&args[1..]) {
+            Ok(path) => info!("preview written to {path:?}"),
+            Err(err) => {
+                error!("could not render preview: {err}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
     init();
+    if args.first().map(String::as_str) == Some("--force") {
+        // the hashes of this run replace the old ones when the program ends
+        FILE_HASHES.lock().expect("not poisoned").clear();
+        info!("ignoring {:?}, every input file is rendered", &*HASHES_PATH);
+    }
     info!("ready");
     loop {
         let mut input = String::new();
@@ -298,6 +354,7 @@ fn main() {
                     info!("program beendet");
                     break;
                 }
+                let mut failed = Vec::new();
                 for file in read_dir(&*IN_PATH).expect("could not read input dir") {
                     if file.is_err() {
                         continue;
@@ -311,17 +368,22 @@ fn main() {
                         path.iter().last().expect("allways has file name")
                     );
                     match parse::from_path(&path) {
-                        Ok(r) => {
-                            if let Some(val) = FILE_HASHES
-                                .lock()
-                                .expect("not poisoned")
-                                .get(&*path.to_string_lossy())
-                                && &*hash_file(&path) == val
-                                && std::path::Path::new(&r.get_file_name()).exists()
-                            {
-                                continue;
+                        Ok(renderables) => {
+                            for r in renderables {
+                                if let Some(val) = FILE_HASHES
+                                    .lock()
+                                    .expect("not poisoned")
+                                    .get(&hashes_key(&path, &*r))
+                                    && *val == FileHashes::new(&path, &*r)
+                                    && std::path::Path::new(&r.get_file_name()).exists()
+                                {
+                                    continue;
+                                }
+                                let name = r.name().to_string();
+                                if !process_renderable(path.clone(), r) {
+                                    failed.push((path.clone(), name));
+                                }
                             }
-                            process_renderable(path, r)
                         }
                         Err(err) => {
                             error!("could not read file: {}", err);
@@ -329,6 +391,17 @@ fn main() {
                         }
                     }
                 }
+                let failed = retry_failed(failed);
+                if !failed.is_empty() {
+                    error!(
+                        "rendering failed twice for: {}",
+                        failed
+                            .iter()
+                            .map(|(_, name)| name.as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                }
             }
             Err(error) => {
                 error!("An error occurred while reading input: {}", error);
@@ -341,9 +414,13 @@ fn main() {
     }
 
     let mut file = File::create(&*HASHES_PATH).expect("could not create file hash file");
-    serde_json::to_writer_pretty::<_, HashMap<String, String>>(
+    serde_json::to_writer_pretty::<_, HashMap<String, FileHashes>>(
         &mut file,
         &*FILE_HASHES.lock().expect("file hashes is not poisoned"),
     )
     .expect("could not  write file hashes");
+
+    let mut file = File::create(&*MANIFEST_PATH).expect("could not create manifest file");
+    serde_json::to_writer_pretty(&mut file, &*MANIFEST.lock().expect("not poisoned"))
+        .expect("could not write manifest");
 }
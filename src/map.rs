@@ -1,23 +1,28 @@
-use std::{collections::HashMap, fs::File, path::PathBuf, sync::LazyLock, time::Instant};
+use std::{collections::HashMap, fs::File, path, sync::OnceLock, time::Instant};
 
 use anyhow::{Result, anyhow};
 use log::{info, trace};
 use serde::{Deserialize, Serialize};
-use skia_safe::{OwnedCanvas, PathFillType};
+use skia_safe::{Canvas, Paint, PathFillType};
 
 use geo_types::{LineString, Polygon, geometry::Geometry};
 use mvt_reader::{Reader, feature::Value};
 
 use crate::{
-    CACHE_PATH, STYLE_PATH,
-    draw::{DrawInstructions, LayerStyle},
+    CACHE_PATH,
+    bounded::{Bounded, QuadTree, Rect},
+    config::Quality,
+    draw::{BACKGROUND_IDX, Color, DrawInstructions, LayerStyle, default_palette, with_palette},
     vec::{Transform, Vector},
 };
 
 mod cache;
-pub use cache::MvtGetter;
+pub use cache::{MvtGetter, TileSource};
 
 const TILE_URL: &'static str = "https://vector.openstreetmap.org/shortbread_v1/{z}/{x}/{y}.mvt";
+/// Scale of the world in pixels, at zoom `z` the world is `TILE_SIZE * 2^z` pixels wide.
+/// It is not the size of the tile rasters, the map is drawn as vectors, and the output
+/// resolution does not depend on it. Larger values show more detail at the same zoom.
 pub const TILE_SIZE: u32 = 2048 * 3;
 
 #[derive(Serialize, Deserialize, PartialEq)]
@@ -79,11 +84,12 @@ impl TileDescr {
     }
 
     fn to_file_name(&self) -> String {
-        format!("{}_{}_{}.mvt", self.z, self.x, self.y)
+        format!("{}_{}_{}.mvt.gz", self.z, self.x, self.y)
     }
 
-    fn to_path(&self) -> PathBuf {
-        CACHE_PATH.join(self.to_file_name())
+    /// name of uncompressed tiles cached by older versions
+    fn to_plain_file_name(&self) -> String {
+        format!("{}_{}_{}.mvt", self.z, self.x, self.y)
     }
 
     pub fn valid(&self) -> bool {
@@ -96,19 +102,24 @@ impl TileDescr {
 pub struct Path(pub Vec<Vector>);
 
 impl Path {
-    pub fn draw(&self, instructions: &DrawInstructions, canvas: &mut OwnedCanvas) {
+    pub fn draw(&self, instructions: &DrawInstructions, canvas: &Canvas) {
+        draw_stroke(&self.to_skia(), instructions, canvas);
+    }
+
+    /// skia path through the points without transforming them
+    fn to_skia(&self) -> skia_safe::Path {
         let mut path = skia_safe::Path::new();
-        if self.0.is_empty() {
+        self.add_to(&mut path);
+        path
+    }
+
+    fn add_to(&self, path: &mut skia_safe::Path) {
+        let Some(first) = self.0.first() else {
             return;
-        }
-        let first = instructions.transform * self.0[0];
+        };
         path.move_to((first.x, first.y));
         for point in self.0.iter().skip(1) {
-            let trans_point = instructions.transform * point;
-            path.line_to((trans_point.x, trans_point.y));
-        }
-        if let Some(style) = instructions.path_style() {
-            canvas.draw_path(&path, &style);
+            path.line_to((point.x, point.y));
         }
     }
 
@@ -131,6 +142,26 @@ impl Path {
     fn reverse(&mut self) {
         self.0.reverse();
     }
+
+    /// sum of the segment lengths
+    #[allow(dead_code)] // for route animations
+    pub fn length(&self) -> f32 {
+        self.0.windows(2).map(|w| (w[1] - w[0]).norm()).sum()
+    }
+
+    /// the point `t` (0 to 1) of the length along the path, panics if the path is empty
+    #[allow(dead_code)] // for route animations
+    pub fn point_at_fraction(&self, t: f32) -> Vector {
+        let mut remaining = self.length() * t.clamp(0.0, 1.0);
+        for w in self.0.windows(2) {
+            let segment = (w[1] - w[0]).norm();
+            if remaining <= segment && segment > 0.0 {
+                return w[0] + (w[1] - w[0]) * (remaining / segment);
+            }
+            remaining -= segment;
+        }
+        *self.0.last().expect("path is not empty")
+    }
 }
 
 pub struct Area {
@@ -139,32 +170,19 @@ pub struct Area {
 }
 
 impl Area {
-    pub fn draw(&self, instructions: &DrawInstructions, canvas: &mut OwnedCanvas) {
+    /// Skia path of the rings without transforming them. It is filled with the even-odd
+    /// rule so holes stay empty even if a ring of the tile data is wound the wrong way,
+    /// islands in lakes are separate areas drawn on top.
+    fn to_skia(&self) -> skia_safe::Path {
         let mut path = skia_safe::Path::new();
-        path.set_fill_type(PathFillType::Winding);
-
-        let mut build_contour = |path_data: &Path| {
-            if path_data.0.is_empty() {
-                return;
+        path.set_fill_type(PathFillType::EvenOdd);
+        for ring in std::iter::once(&self.outer).chain(&self.inner) {
+            if !ring.0.is_empty() {
+                ring.add_to(&mut path);
+                path.close();
             }
-            let first = instructions.transform * path_data.0[0];
-            path.move_to((first.x, first.y));
-            for point in path_data.0.iter().skip(1) {
-                let trans_point = instructions.transform * point;
-                path.line_to((trans_point.x, trans_point.y));
-            }
-            path.close();
-        };
-
-        build_contour(&self.outer);
-
-        for path_data in &self.inner {
-            build_contour(path_data);
-        }
-
-        if let Some(style) = instructions.area_style() {
-            canvas.draw_path(&path, &style);
         }
+        path
     }
 
     /// enforce winding rules
@@ -183,14 +201,68 @@ impl Area {
         }
         had_flip
     }
+
+    /// area weighted centroid of the outer ring,
+    /// the center of the bounding box if the ring has no area
+    #[allow(dead_code)] // for placing labels, nothing draws labels yet
+    pub fn centroid(&self) -> Vector {
+        let points = &self.outer.0;
+        let signed_area_sum = self.outer.get_signed_area_sum();
+        if signed_area_sum == 0.0 {
+            return self.bounding_box().get_center();
+        }
+        let mut sum = Vector::zeros();
+        for (i, p_i) in points.iter().enumerate() {
+            let p_next = points[(i + 1) % points.len()];
+            sum = sum + (*p_i + p_next) * (p_i.x * p_next.y - p_next.x * p_i.y);
+        }
+        sum * (1.0 / (3.0 * signed_area_sum))
+    }
+}
+
+impl Bounded for Path {
+    /// an empty rectangle at the origin if the path has no points
+    fn bounding_box(&self) -> Rect {
+        if self.0.is_empty() {
+            return Rect::default();
+        }
+        Rect::from_points(&self.0)
+    }
+}
+
+impl Bounded for Area {
+    /// bounds of the outer ring, an empty rectangle at the origin if it has no points
+    fn bounding_box(&self) -> Rect {
+        self.outer.bounding_box()
+    }
 }
 
 pub struct MapData {
     pub descr: TileDescr,
     layers: Vec<Layer>,
+    /// built by the first query
+    index: OnceLock<FeatureIndex>,
+}
+
+/// a feature of a tile, the index is into the paths or areas of the layer with `layer` as id
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeatureRef {
+    Path { layer: u8, idx: usize },
+    Area { layer: u8, idx: usize },
+}
+
+/// the bounding boxes of all features of a tile
+struct FeatureIndex {
+    features: Vec<FeatureRef>,
+    tree: QuadTree,
 }
 
 impl MapData {
+    /// true if nothing in this tile is drawn at its zoom level
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
     pub fn get_layer(&self, layer_idx: u8) -> Option<&Layer> {
         for layer in &self.layers {
             if layer.id == layer_idx {
@@ -199,15 +271,208 @@ impl MapData {
         }
         None
     }
+
+    /// Features whose bounding box intersects `area` in tile coordinates (0 to 1),
+    /// they are candidates and do not have to touch `area` themselves.
+    #[allow(dead_code)] // for point queries like checkpoint detection and label placement
+    pub fn features_in(&self, area: &Rect) -> Vec<FeatureRef> {
+        let index = self.index.get_or_init(|| self.build_index());
+        index
+            .tree
+            .query(area)
+            .into_iter()
+            .map(|idx| index.features[idx])
+            .collect()
+    }
+
+    fn build_index(&self) -> FeatureIndex {
+        let mut features = Vec::new();
+        let mut rects = Vec::new();
+        for layer in &self.layers {
+            for (idx, (_, bounds, _)) in layer.paths.iter().enumerate() {
+                features.push(FeatureRef::Path {
+                    layer: layer.id,
+                    idx,
+                });
+                rects.push(*bounds);
+            }
+            for (idx, (_, bounds, _)) in layer.areas.iter().enumerate() {
+                features.push(FeatureRef::Area {
+                    layer: layer.id,
+                    idx,
+                });
+                rects.push(*bounds);
+            }
+        }
+        FeatureIndex {
+            features,
+            tree: QuadTree::new(Rect::new(0.0, 1.0, 0.0, 1.0), rects),
+        }
+    }
 }
 
-#[derive(Deserialize)]
-#[serde(transparent)]
-pub struct Style(Vec<LayerSorter>);
+/// The style is either a list of layers or an object with the layers and a `palette`
+/// which color indices in the layers refer to.
+pub struct Style {
+    layers: Vec<LayerSorter>,
+    palette: Vec<Color>,
+}
+
+/// no tile server has tiles deeper than this
+const MAX_TILE_ZOOM: u64 = 22;
 
 impl Style {
+    /// Checks the style file without panicking on broken color indices,
+    /// every problem is described with the position of the layer it is in.
+    pub fn validate(path: impl AsRef<path::Path>) -> Vec<String> {
+        let value: serde_json::Value = match File::open(path.as_ref()) {
+            Ok(file) => match serde_json::from_reader(file) {
+                Ok(value) => value,
+                Err(err) => return vec![format!("not valid json: {err}")],
+            },
+            Err(err) => return vec![format!("could not open {:?}: {err}", path.as_ref())],
+        };
+        Self::validate_value(&value)
+    }
+
+    fn validate_value(value: &serde_json::Value) -> Vec<String> {
+        let mut problems = Vec::new();
+        let (palette_len, layers) = match value {
+            serde_json::Value::Object(map) => {
+                let palette_len = match map.get("palette") {
+                    Some(palette) => match serde_json::from_value::<Vec<Color>>(palette.clone()) {
+                        Ok(palette) => palette.len(),
+                        Err(err) => {
+                            problems.push(format!("palette: {err}"));
+                            return problems;
+                        }
+                    },
+                    None => default_palette().len(),
+                };
+                let Some(layers) = map.get("layers") else {
+                    problems.push("style has no layers".to_string());
+                    return problems;
+                };
+                (palette_len, layers)
+            }
+            layers => (default_palette().len(), layers),
+        };
+        if palette_len <= BACKGROUND_IDX {
+            problems.push(format!(
+                "palette: needs at least {} colors, has {palette_len}",
+                BACKGROUND_IDX + 1
+            ));
+        }
+        let Some(layers) = layers.as_array() else {
+            problems.push("layers: has to be a list".to_string());
+            return problems;
+        };
+
+        let mut names: HashMap<&str, usize> = HashMap::new();
+        for (i, layer) in layers.iter().enumerate() {
+            let name = layer.get("layer_name").and_then(|name| name.as_str());
+            let at = format!("layers[{i}] ({})", name.unwrap_or("no name"));
+            if let Some(name) = name
+                && let Some(first) = names.insert(name, i)
+            {
+                problems.push(format!("{at}: name is already used by layers[{first}]"));
+            }
+            let mut styles = Vec::new();
+            if let Some(fall_back) = layer.get("fall_back") {
+                styles.push(("fall_back".to_string(), fall_back));
+            }
+            for (j, sub_type) in layer
+                .get("sub_types")
+                .and_then(|s| s.as_array())
+                .into_iter()
+                .flatten()
+                .enumerate()
+            {
+                if let Some(style) = sub_type.get("style") {
+                    styles.push((format!("sub_types[{j}].style"), style));
+                }
+                if let Some(zoom) = sub_type.get("min_zoomlevel")
+                    && !zoom.is_null()
+                    && zoom.as_u64().is_none_or(|z| z > MAX_TILE_ZOOM)
+                {
+                    problems.push(format!(
+                        "{at}: sub_types[{j}].min_zoomlevel has to be between 0 and \
+                        {MAX_TILE_ZOOM}, not {zoom}"
+                    ));
+                }
+            }
+            let len = problems.len();
+            for (field, style) in styles {
+                let colors = [
+                    ("fill", style.get("fill")),
+                    ("stroke", style.get("stroke").and_then(|s| s.get(1))),
+                ];
+                for (key, color) in colors {
+                    if let Some(idx) = color.and_then(|c| c.as_u64())
+                        && idx as usize >= palette_len
+                    {
+                        problems.push(format!(
+                            "{at}: {field}.{key} refers to color {idx}, \
+                            the palette has {palette_len} colors"
+                        ));
+                    }
+                }
+                if let Some(width) = style.get("stroke").and_then(|s| s.get(0))
+                    && width.as_f64().is_none_or(|w| w <= 0.0)
+                {
+                    problems.push(format!(
+                        "{at}: {field}.stroke width has to be positive, not {width}"
+                    ));
+                }
+            }
+            // deserializing panics on color indices out of range
+            if problems.len() == len
+                && palette_len > BACKGROUND_IDX
+                && let Err(err) = with_palette(&vec![Color::new(0, 0, 0); palette_len], || {
+                    serde_json::from_value::<LayerSorter>(layer.clone())
+                })
+            {
+                problems.push(format!("{at}: {err}"));
+            }
+        }
+        problems
+    }
+
+    pub fn from_path(path: impl AsRef<path::Path>) -> Result<Self> {
+        let file = File::open(path)?;
+        let mut style = Self::from_value(serde_json::from_reader(file)?)?;
+        style.retain_non_empty();
+        Ok(style)
+    }
+
+    fn from_value(value: serde_json::Value) -> Result<Self> {
+        let (palette, layers) = match value {
+            serde_json::Value::Object(mut map) => {
+                let palette = match map.remove("palette") {
+                    Some(palette) => serde_json::from_value(palette)?,
+                    None => default_palette(),
+                };
+                let layers = map.remove("layers").ok_or(anyhow!("style has no layers"))?;
+                (palette, layers)
+            }
+            layers => (default_palette(), layers),
+        };
+        if palette.len() <= BACKGROUND_IDX {
+            return Err(anyhow!(
+                "the palette needs at least {} colors",
+                BACKGROUND_IDX + 1
+            ));
+        }
+        let layers = with_palette(&palette, || serde_json::from_value(layers))?;
+        Ok(Self { layers, palette })
+    }
+
+    pub fn palette(&self) -> &[Color] {
+        &self.palette
+    }
+
     pub fn get_layer_idx(&self, name: &str) -> Option<u8> {
-        self.0.iter().enumerate().find_map(|(i, l)| {
+        self.layers.iter().enumerate().find_map(|(i, l)| {
             if l.layer_name == name {
                 Some(i as u8)
             } else {
@@ -217,15 +482,15 @@ impl Style {
     }
 
     pub fn get_sorter(&self, idx: u8) -> &LayerSorter {
-        &self.0[idx as usize]
+        &self.layers[idx as usize]
     }
 
     pub fn max_layer_idx(&self) -> u8 {
-        (self.0.len() - 1) as u8
+        (self.layers.len() - 1) as u8
     }
 
     pub fn retain_non_empty(&mut self) {
-        self.0.retain(|sorter| !sorter.is_empty());
+        self.layers.retain(|sorter| !sorter.is_empty());
     }
 }
 
@@ -307,22 +572,15 @@ impl TypeConditions {
     }
 }
 
-pub static SORTERS: LazyLock<Style> = LazyLock::new(|| {
-    let file = File::open(&*STYLE_PATH).expect("could not decode style");
-    let mut sorter: Style = serde_json::from_reader(file).expect("could not decode style");
-    sorter.retain_non_empty();
-    sorter
-});
-
 impl MapData {
-    pub fn from_reader(tile: TileDescr, reader: Reader) -> Result<Self> {
+    pub fn from_reader(tile: TileDescr, reader: Reader, style: &Style) -> Result<Self> {
         let start = Instant::now();
         let mut layers = Vec::new();
         for meta in reader
             .get_layer_metadata()
             .map_err(|_| anyhow!("could not get layer names"))?
         {
-            let layer_idx = SORTERS.get_layer_idx(&&*meta.name);
+            let layer_idx = style.get_layer_idx(&meta.name);
             if layer_idx.is_none() {
                 continue;
             }
@@ -335,9 +593,10 @@ impl MapData {
                 .get_features(meta.layer_index)
                 .map_err(|_| anyhow!("could not get layer names"))?
             {
-                if let Some(typ) = SORTERS
+                if let Some(typ) = style
                     .get_sorter(layer_idx)
                     .apply(feat.properties.as_ref(), tile.z)
+                    && typ.is_visible()
                 {
                     convert_geometry(
                         feat.geometry,
@@ -359,10 +618,20 @@ impl MapData {
                 info!("had to rewind area")
             }
 
+            if paths.is_empty() && areas.is_empty() {
+                continue;
+            }
+
             layers.push(Layer {
                 id: layer_idx,
-                paths,
-                areas,
+                paths: paths
+                    .into_iter()
+                    .map(|(style, path)| (style, path.bounding_box(), path.to_skia()))
+                    .collect(),
+                areas: areas
+                    .into_iter()
+                    .map(|(style, area)| (style, area.bounding_box(), area.to_skia()))
+                    .collect(),
             })
         }
         trace!(
@@ -374,18 +643,19 @@ impl MapData {
         Ok(MapData {
             descr: tile,
             layers,
+            index: OnceLock::new(),
         })
     }
 }
 
-fn convert_polygon<'a>(
+fn convert_polygon(
     polygon: Polygon<f32>,
     extent: f32,
-    areas: &mut Vec<(&'a LayerStyle, Area)>,
-    typ: &'a LayerStyle,
+    areas: &mut Vec<(LayerStyle, Area)>,
+    typ: &LayerStyle,
 ) {
     areas.push((
-        typ,
+        *typ,
         Area {
             outer: Path(
                 polygon
@@ -403,24 +673,24 @@ fn convert_polygon<'a>(
     ))
 }
 
-fn convert_path<'a>(
+fn convert_path(
     path: LineString<f32>,
     extent: f32,
-    paths: &mut Vec<(&'a LayerStyle, Path)>,
-    typ: &'a LayerStyle,
+    paths: &mut Vec<(LayerStyle, Path)>,
+    typ: &LayerStyle,
 ) {
     paths.push((
-        typ,
+        *typ,
         Path(path.coords().map(|p| Vector::from(p) / extent).collect()),
     ))
 }
 
-fn convert_geometry<'a>(
+fn convert_geometry(
     geometry: Geometry<f32>,
     extent: f32,
-    paths: &mut Vec<(&'a LayerStyle, Path)>,
-    areas: &mut Vec<(&'a LayerStyle, Area)>,
-    typ: &'a LayerStyle,
+    paths: &mut Vec<(LayerStyle, Path)>,
+    areas: &mut Vec<(LayerStyle, Area)>,
+    typ: &LayerStyle,
 ) {
     match geometry {
         Geometry::Line(line) => convert_path(line.into(), extent, paths, typ),
@@ -451,19 +721,287 @@ fn convert_geometry<'a>(
     }
 }
 
+/// The features of a tile with one style layer. Their skia paths are built once in tile
+/// coordinates and only transformed to the screen when drawn.
 pub struct Layer {
     id: u8,
-    paths: Vec<(&'static LayerStyle, Path)>,
-    areas: Vec<(&'static LayerStyle, Area)>,
+    paths: Vec<(LayerStyle, Rect, skia_safe::Path)>,
+    areas: Vec<(LayerStyle, Rect, skia_safe::Path)>,
 }
 
 impl Layer {
-    pub fn draw(&self, canvas: &mut OwnedCanvas, tile_to_screen: Transform, opacity: f32) {
-        for (style, path) in &self.paths {
-            path.draw(&style.to_draw_instructions(tile_to_screen, opacity), canvas);
+    pub fn draw(
+        &self,
+        canvas: &Canvas,
+        tile_to_screen: Transform,
+        opacity: f32,
+        palette: &[Color],
+        quality: Quality,
+    ) {
+        for (style, _, path) in &self.paths {
+            draw_stroke(
+                path,
+                &style.to_draw_instructions(tile_to_screen, opacity, palette, quality),
+                canvas,
+            );
         }
-        for (style, area) in &self.areas {
-            area.draw(&style.to_draw_instructions(tile_to_screen, opacity), canvas);
+        for (style, bounds, path) in &self.areas {
+            if is_too_small(style, *bounds, tile_to_screen) {
+                continue;
+            }
+            draw_fill(
+                path,
+                &style.to_draw_instructions(tile_to_screen, opacity, palette, quality),
+                canvas,
+            );
         }
     }
 }
+
+/// strokes `path` given in untransformed coordinates
+fn draw_stroke(path: &skia_safe::Path, instructions: &DrawInstructions, canvas: &Canvas) {
+    if let Some(paint) = instructions.path_style() {
+        draw_transformed(path, paint, instructions.transform, canvas);
+    }
+}
+
+/// fills `path` given in untransformed coordinates
+fn draw_fill(path: &skia_safe::Path, instructions: &DrawInstructions, canvas: &Canvas) {
+    if let Some(paint) = instructions.area_style() {
+        draw_transformed(path, paint, instructions.transform, canvas);
+    }
+}
+
+/// Draws `path` with `transform` on the canvas matrix so skia transforms the points.
+/// The stroke width of `paint` is in pixels and divided by the scale to stay that wide.
+fn draw_transformed(
+    path: &skia_safe::Path,
+    mut paint: Paint,
+    transform: Transform,
+    canvas: &Canvas,
+) {
+    paint.set_stroke_width(paint.stroke_width() / transform.scale());
+    canvas.save();
+    canvas.concat(&transform.to_matrix());
+    canvas.draw_path(path, &paint);
+    canvas.restore();
+}
+
+/// true if an area with `bounds` is below the minimum pixel size of `style` when drawn with
+/// `transform`
+fn is_too_small(style: &LayerStyle, bounds: Rect, transform: Transform) -> bool {
+    let Some(min_pixel_size) = style.min_pixel_size else {
+        return false;
+    };
+    bounds.width().max(bounds.height()) * transform.scale() < min_pixel_size
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::draw::{LayerBlend, LayerCap, LayerJoin};
+
+    #[test]
+    fn layer_blend() {
+        let style: LayerStyle =
+            serde_json::from_str(r##"{"fill": "#ffffff", "stroke": null}"##).expect("in test");
+        assert_eq!(style.blend, LayerBlend::Normal);
+        let glowing: LayerStyle = serde_json::from_str(
+            r##"{"fill": null, "stroke": [3.0, "#ffcc00"], "blend": "plus"}"##,
+        )
+        .expect("in test");
+        assert_eq!(glowing.blend, LayerBlend::Plus);
+        assert!(serde_json::from_str::<LayerStyle>(r#"{"fill": null, "blend": "glow"}"#).is_err());
+    }
+
+    #[test]
+    fn layer_cap_join() {
+        let style: LayerStyle =
+            serde_json::from_str(r##"{"fill": null, "stroke": [2.0, 1]}"##).expect("in test");
+        assert_eq!((style.cap, style.join), (LayerCap::Round, LayerJoin::Round));
+        let border: LayerStyle = serde_json::from_str(
+            r##"{"fill": null, "stroke": [2.0, 1], "cap": "butt", "join": "miter"}"##,
+        )
+        .expect("in test");
+        assert_eq!(
+            (border.cap, border.join),
+            (LayerCap::Butt, LayerJoin::Miter)
+        );
+        assert!(serde_json::from_str::<LayerStyle>(r#"{"fill": null, "join": "sharp"}"#).is_err());
+    }
+
+    #[test]
+    fn style_problems() {
+        assert!(Style::validate(&*crate::STYLE_PATH).is_empty());
+        let style = serde_json::json!({
+            "palette": vec![serde_json::json!({"r": 0, "g": 0, "b": 0}); 7],
+            "layers": [
+                {"layer_name": "water", "sub_types": [], "fall_back": {"fill": 9, "stroke": null}},
+                {"layer_name": "water", "sub_types": [{
+                    "conditions": [],
+                    "style": {"fill": null, "stroke": [0.0, 1]},
+                    "min_zoomlevel": 30
+                }]},
+                {"layer_name": "roads", "sub_types": "none"},
+            ]
+        });
+        let problems = Style::validate_value(&style);
+        assert_eq!(problems.len(), 5, "{problems:?}");
+        assert!(problems[0].starts_with("layers[0] (water): fall_back.fill refers to color 9"));
+        assert!(problems[1].contains("already used by layers[0]"));
+        assert!(problems[2].contains("min_zoomlevel"));
+        assert!(problems[3].contains("stroke width"));
+        assert!(problems[4].starts_with("layers[2] (roads): invalid type"));
+
+        let mut palette = vec![serde_json::json!({"r": 0, "g": 0, "b": 0}); 7];
+        palette[3] = serde_json::json!({"h": 400, "s": 0.5, "v": 0.5});
+        let problems =
+            Style::validate_value(&serde_json::json!({"palette": palette, "layers": []}));
+        assert_eq!(problems.len(), 1, "{problems:?}");
+        assert!(problems[0].starts_with("palette: "), "{problems:?}");
+    }
+
+    #[test]
+    fn path_fraction() {
+        let path = Path(vec![
+            Vector::new(0.0, 0.0),
+            Vector::new(1.0, 0.0),
+            Vector::new(3.0, 0.0),
+        ]);
+        assert_eq!(path.length(), 3.0);
+        assert_eq!(path.point_at_fraction(0.0), Vector::new(0.0, 0.0));
+        assert_eq!(path.point_at_fraction(0.5), Vector::new(1.5, 0.0));
+        assert_eq!(path.point_at_fraction(1.0), Vector::new(3.0, 0.0));
+        assert_eq!(path.point_at_fraction(2.0), Vector::new(3.0, 0.0));
+
+        let bent = Path(vec![
+            Vector::new(0.0, 0.0),
+            Vector::new(0.0, 2.0),
+            Vector::new(2.0, 2.0),
+        ]);
+        assert_eq!(bent.point_at_fraction(0.5), Vector::new(0.0, 2.0));
+        assert_eq!(bent.point_at_fraction(0.75), Vector::new(1.0, 2.0));
+
+        let point = Path(vec![Vector::new(1.0, 1.0)]);
+        assert_eq!(point.length(), 0.0);
+        assert_eq!(point.point_at_fraction(0.5), Vector::new(1.0, 1.0));
+    }
+
+    #[test]
+    fn area_centroid() {
+        let area = |points: &[(f32, f32)]| Area {
+            outer: Path(points.iter().map(|(x, y)| Vector::new(*x, *y)).collect()),
+            inner: Vec::new(),
+        };
+        let square = area(&[(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]);
+        assert_eq!(square.bounding_box(), Rect::new(0.0, 1.0, 0.0, 1.0));
+        assert_eq!(square.centroid(), Vector::new(0.5, 0.5));
+
+        // the centroid is pulled towards the larger part of an L shape
+        let l_shape = area(&[
+            (0.0, 0.0),
+            (2.0, 0.0),
+            (2.0, 1.0),
+            (1.0, 1.0),
+            (1.0, 2.0),
+            (0.0, 2.0),
+        ]);
+        let centroid = l_shape.centroid();
+        assert!((centroid.x - 5.0 / 6.0).abs() < 1e-6 && (centroid.y - 5.0 / 6.0).abs() < 1e-6);
+
+        let line = area(&[(0.0, 0.0), (2.0, 4.0)]);
+        assert_eq!(line.centroid(), Vector::new(1.0, 2.0));
+        assert_eq!(area(&[]).bounding_box(), Rect::default());
+    }
+
+    #[test]
+    fn donut_hole() {
+        let ring = |min: f32, max: f32| {
+            Path(vec![
+                Vector::new(min, min),
+                Vector::new(max, min),
+                Vector::new(max, max),
+                Vector::new(min, max),
+            ])
+        };
+        // the hole is wound like the outer ring on purpose
+        let donut = Area {
+            outer: ring(0.1, 0.9),
+            inner: vec![ring(0.3, 0.7)],
+        };
+        let instructions = DrawInstructions {
+            fill: Some(Color::new(255, 0, 0)),
+            stroke: None,
+            transform: Transform::new(100.0, Vector::zeros()),
+            opacity: 1.0,
+            quality: Quality::Final,
+            blend: LayerBlend::Normal,
+            cap: LayerCap::Round,
+            join: LayerJoin::Round,
+        };
+        let mut surface = skia_safe::surfaces::raster_n32_premul((100, 100)).expect("in test");
+        surface.canvas().clear(skia_safe::Color::TRANSPARENT);
+        draw_fill(&donut.to_skia(), &instructions, surface.canvas());
+        let image = surface.image_snapshot();
+        let pixels = image.peek_pixels().expect("in test");
+        assert_eq!(pixels.get_color((20, 50)).a(), 255);
+        assert_eq!(pixels.get_color((50, 50)).a(), 0);
+        assert_eq!(pixels.get_color((95, 50)).a(), 0);
+    }
+
+    #[test]
+    fn min_pixel_size() {
+        let style: LayerStyle =
+            serde_json::from_str(r#"{"fill": 0, "stroke": null, "min_pixel_size": 3.0}"#)
+                .expect("in test");
+        let square = Area {
+            outer: Path(vec![
+                Vector::new(0.0, 0.0),
+                Vector::new(1.0, 0.0),
+                Vector::new(1.0, 1.0),
+            ]),
+            inner: Vec::new(),
+        }
+        .bounding_box();
+        let at_scale = |scale| Transform::new(scale, Vector::zeros());
+        assert!(is_too_small(&style, square, at_scale(2.0)));
+        assert!(!is_too_small(&style, square, at_scale(3.0)));
+        let without_min: LayerStyle =
+            serde_json::from_str(r#"{"fill": 0, "stroke": null}"#).expect("in test");
+        assert!(!is_too_small(&without_min, square, at_scale(0.1)));
+    }
+
+    #[test]
+    fn style_palette() {
+        let layers = r#"[{"layer_name": "land", "sub_types": [], "fall_back": {"fill": 1, "stroke": null}}]"#;
+        let style = Style::from_value(
+            serde_json::from_str(&format!(
+                r##"{{"palette": ["#000000", "#102030"], "layers": {layers}}}"##
+            ))
+            .expect("in test"),
+        )
+        .expect("in test");
+        assert_eq!(
+            format!("{:?}", style.palette()[BACKGROUND_IDX]),
+            "Color { #102030 a: 1 }"
+        );
+
+        let old =
+            Style::from_value(serde_json::from_str(layers).expect("in test")).expect("in test");
+        assert_eq!(
+            format!("{:?}", old.palette()[BACKGROUND_IDX]),
+            format!("{:?}", default_palette()[BACKGROUND_IDX])
+        );
+
+        assert!(
+            Style::from_value(
+                serde_json::from_str(&format!(
+                    r##"{{"palette": ["#000000", "#102030"], "layers": {}}}"##,
+                    layers.replace("\"fill\": 1", "\"fill\": 2")
+                ))
+                .expect("in test")
+            )
+            .is_err()
+        );
+    }
+}
@@ -1,62 +1,236 @@
 use anyhow::{Result, anyhow};
-use log::{debug, info};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use log::{debug, info, warn};
 use reqwest::blocking::Client;
 use std::{
     collections::{HashMap, HashSet},
     fs::{self, File},
-    io::Write,
+    io::{Read, Write},
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
-use super::{CACHE_PATH, MapData, TileDescr};
+use super::{CACHE_PATH, MapData, Style, TileDescr};
+use crate::{CONFIG, error::RenderError};
+
+/// where the tiles which are not cached yet come from
+pub trait TileSource: Send + Sync {
+    /// the mvt data of `tile`, gzipped or not
+    fn fetch(&mut self, tile: TileDescr) -> Result<Vec<u8>, RenderError>;
+}
+
+/// the tile server, with the connections and rate limit of the config
+struct HttpTileSource {
+    client: Client,
+    limiter: Option<RateLimiter>,
+}
+
+impl HttpTileSource {
+    fn new() -> Result<Self> {
+        Ok(Self {
+            client: tile_client()?,
+            limiter: CONFIG
+                .tile_downloads
+                .requests_per_second
+                .map(RateLimiter::new),
+        })
+    }
+}
+
+impl TileSource for HttpTileSource {
+    fn fetch(&mut self, tile: TileDescr) -> Result<Vec<u8>, RenderError> {
+        if let Some(limiter) = &mut self.limiter {
+            limiter.wait();
+        }
+        debug!("requesting tile: z={} x={} y={}", tile.z, tile.x, tile.y);
+        let response = self.client.get(tile.to_url()).send()?;
+        Ok(response.bytes()?.to_vec())
+    }
+}
+
+/// Serves the tiles in a directory named like the cache without any network,
+/// every other tile is an error.
+#[cfg(test)]
+pub struct MockTileSource {
+    tiles: HashMap<TileDescr, Vec<u8>>,
+}
+
+#[cfg(test)]
+impl MockTileSource {
+    pub fn from_dir(dir: &std::path::Path) -> Result<Self> {
+        let mut tiles = HashMap::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            if let Some(tile) = tile_from_file_name(&file_name) {
+                tiles.insert(tile, fs::read(&path)?);
+            }
+        }
+        Ok(Self { tiles })
+    }
+
+    /// the tiles bundled in `test_files/tiles`
+    pub fn fixtures() -> Self {
+        Self::from_dir(FIXTURE_DIR.as_ref()).expect("fixtures are readable")
+    }
+}
+
+#[cfg(test)]
+impl TileSource for MockTileSource {
+    fn fetch(&mut self, tile: TileDescr) -> Result<Vec<u8>, RenderError> {
+        self.tiles.get(&tile).cloned().ok_or_else(|| {
+            RenderError::Other(anyhow!(
+                "{tile:?} is not one of the test fixtures, add it to {FIXTURE_DIR}"
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+const FIXTURE_DIR: &str = "test_files/tiles";
 
 pub struct MvtGetter {
     pub file_cache: HashSet<TileDescr>,
     pub mem_cache: HashMap<TileDescr, MapData>,
-    client: Client,
+    cache_path: PathBuf,
+    source: Box<dyn TileSource>,
 }
 
 impl MvtGetter {
     pub fn new() -> Result<Self> {
+        Self::with_cache_dir(CACHE_PATH.to_path_buf())
+    }
+
+    /// creates a getter which caches the tiles in `cache_path` instead of the default cache
+    pub fn with_cache_dir(cache_path: PathBuf) -> Result<Self> {
+        Self::with_source(cache_path, Box::new(HttpTileSource::new()?))
+    }
+
+    /// a getter of the bundled test tiles which never touches the network
+    #[cfg(test)]
+    pub fn fixtures() -> Self {
+        Self::with_source(FIXTURE_DIR.into(), Box::new(MockTileSource::fixtures()))
+            .expect("fixtures are readable")
+    }
+
+    /// creates a getter which caches in `cache_path` the tiles it gets from `source`
+    pub fn with_source(cache_path: PathBuf, source: Box<dyn TileSource>) -> Result<Self> {
         let mut file_cache = HashSet::new();
-        if !fs::exists(&*CACHE_PATH)? {
-            fs::create_dir(&*CACHE_PATH)?;
+        if !fs::exists(&cache_path)? {
+            fs::create_dir(&cache_path)?;
         }
-        for entry in fs::read_dir(&*CACHE_PATH)? {
+        for entry in fs::read_dir(&cache_path)? {
             if let Ok(entry) = entry {
                 let path = entry.path();
-                if path.extension().and_then(|s| s.to_str()) != Some("mvt") {
-                    continue;
-                }
-                let mut split = path
+                let file_name = path
                     .file_name()
                     .expect("valid because from read_dir")
-                    .to_str()
-                    .expect("these file names are valid utf-8")
-                    .strip_suffix(".mvt")
-                    .expect("checked above")
-                    .split("_");
-                file_cache.insert(TileDescr {
-                    z: split
-                        .next()
-                        .expect("the file names in cache are valid")
-                        .parse()?,
-                    x: split
-                        .next()
-                        .expect("the file names in cache are valid")
-                        .parse()?,
-                    y: split
-                        .next()
-                        .expect("the file names in cache are valid")
-                        .parse()?,
-                });
+                    .to_string_lossy();
+                if let Some(tile) = tile_from_file_name(&file_name) {
+                    file_cache.insert(tile);
+                }
             }
         }
         Ok(Self {
             file_cache,
             mem_cache: HashMap::new(),
-            client: Client::new(),
+            cache_path,
+            source,
         })
     }
+
+    fn tile_path(&self, tile: TileDescr) -> PathBuf {
+        self.cache_path.join(tile.to_file_name())
+    }
+
+    fn plain_tile_path(&self, tile: TileDescr) -> PathBuf {
+        self.cache_path.join(tile.to_plain_file_name())
+    }
+}
+
+/// client which keeps connections to the tile server open as set in the config
+fn tile_client() -> Result<Client> {
+    let config = &CONFIG.tile_downloads;
+    Ok(Client::builder()
+        .pool_idle_timeout(Duration::from_secs(config.pool_idle_timeout_s))
+        .pool_max_idle_per_host(config.pool_max_idle_per_host)
+        .tcp_keepalive(config.tcp_keepalive_s.map(Duration::from_secs))
+        .build()?)
+}
+
+/// token bucket allowing `rate` requests per second with bursts of up to `rate` requests
+struct RateLimiter {
+    rate: f32,
+    tokens: f32,
+    last: Instant,
+}
+
+impl RateLimiter {
+    /// `rate` has to be positive, the config rejects other rates
+    fn new(rate: f32) -> Self {
+        Self {
+            rate,
+            tokens: rate.max(1.0),
+            last: Instant::now(),
+        }
+    }
+
+    /// takes a token and returns how long to wait before the request may be sent
+    fn reserve(&mut self, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f32();
+        self.last = now;
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate.max(1.0)) - 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f32(-self.tokens / self.rate)
+        }
+    }
+
+    fn wait(&mut self) {
+        let wait = self.reserve(Instant::now());
+        if !wait.is_zero() {
+            info!("throttling tile downloads for {wait:?}");
+            thread::sleep(wait);
+        }
+    }
+}
+
+/// returns `None` for files which are not cached tiles,
+/// tile files whose name is not `z_x_y.mvt` or `z_x_y.mvt.gz` are skipped with a warning
+fn tile_from_file_name(file_name: &str) -> Option<TileDescr> {
+    let stem = file_name
+        .strip_suffix(".mvt.gz")
+        .or_else(|| file_name.strip_suffix(".mvt"))?;
+    let parts: Vec<u32> = stem
+        .split('_')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()
+        .unwrap_or_default();
+    match parts[..] {
+        [z, x, y] if z < 32 && (TileDescr { z, x, y }).valid() => Some(TileDescr { z, x, y }),
+        _ => {
+            warn!("skipping {file_name} in the tile cache, it is not named z_x_y.mvt");
+            None
+        }
+    }
+}
+
+fn is_gzip(buf: &[u8]) -> bool {
+    buf.starts_with(&[0x1f, 0x8b])
+}
+
+fn decompress(buf: &[u8]) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    GzDecoder::new(buf).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn compress(buf: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(buf)?;
+    Ok(encoder.finish()?)
 }
 
 impl MvtGetter {
@@ -64,52 +238,122 @@ impl MvtGetter {
         self.mem_cache.get(&tile)
     }
 
-    fn try_load_from_file(&mut self, tile: TileDescr) -> Result<()> {
-        let data = fs::read(tile.to_path())?;
+    /// loads the gzipped tile and falls back to an uncompressed `.mvt`
+    fn try_load_from_file(&mut self, tile: TileDescr, style: &Style) -> Result<()> {
+        let path = self.tile_path(tile);
+        let data = if path.exists() {
+            decompress(&fs::read(path)?)?
+        } else {
+            fs::read(self.plain_tile_path(tile))?
+        };
         self.mem_cache.insert(
             tile,
             MapData::from_reader(
                 tile,
                 mvt_reader::Reader::new(data)
                     .map_err(|_| anyhow!("could not create Mvt Reader"))?,
+                style,
             )?,
         );
         return Ok(());
     }
 
-    pub fn load_tile(&mut self, tile: TileDescr) -> Result<()> {
+    pub fn load_tile(&mut self, tile: TileDescr, style: &Style) -> Result<(), RenderError> {
         if self.mem_cache.contains_key(&tile) {
             return Ok(());
         }
 
         if self.file_cache.contains(&tile) {
-            match self.try_load_from_file(tile) {
+            match self.try_load_from_file(tile, style) {
                 Ok(_) => return Ok(()),
-                Err(_) => {
-                    info!("kicked {tile:?} out of file cache");
-                    self.file_cache.remove(&tile);
+                Err(err) => {
+                    warn!("cached {tile:?} is corrupt, downloading it again: {err}");
+                    self.remove_from_file_cache(tile)?;
                 }
             }
         }
 
-        debug!("requesting tile: z={} x={} y={}", tile.z, tile.x, tile.y);
-        let response = self.client.get(&tile.to_url()).send()?;
-        let bytes = response.bytes()?;
-        let buf = bytes.to_vec();
-        let mut file = File::create(&tile.to_path())?;
-        file.write_all(&buf)?;
-        let data = MapData::from_reader(
-            tile,
-            mvt_reader::Reader::new(buf).map_err(|_| anyhow!("could not create Mvt Reader"))?,
-        )?;
+        let bytes = self.source.fetch(tile)?;
+        let (buf, compressed) = if is_gzip(&bytes) {
+            (decompress(&bytes)?, bytes.to_vec())
+        } else {
+            (bytes.to_vec(), compress(&bytes)?)
+        };
+        let reader = mvt_reader::Reader::new(buf)
+            .map_err(|_| RenderError::MvtParse("could not create Mvt Reader".to_string()))?;
+        let data = MapData::from_reader(tile, reader, style)
+            .map_err(|err| RenderError::MvtParse(err.to_string()))?;
+        let mut file = File::create(self.tile_path(tile))?;
+        file.write_all(&compressed)?;
         self.file_cache.insert(tile);
         self.mem_cache.insert(tile, data);
         Ok(())
     }
 
-    pub fn load_tiles(&mut self, tiles: &[TileDescr]) -> Result<()> {
+    /// deletes all cached files of the tile
+    fn remove_from_file_cache(&mut self, tile: TileDescr) -> Result<()> {
+        self.file_cache.remove(&tile);
+        for path in [self.tile_path(tile), self.plain_tile_path(tile)] {
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+        info!("kicked {tile:?} out of file cache");
+        Ok(())
+    }
+
+    /// Deletes cached tiles last modified more than `max_age` ago, then deletes the least
+    /// recently modified tiles until the cache takes at most `max_size` bytes.
+    /// Returns the number of deleted files and the number of freed bytes.
+    pub fn clean_cache(
+        &mut self,
+        max_age: Option<Duration>,
+        max_size: Option<u64>,
+    ) -> Result<(usize, u64)> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.cache_path)? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_name = path
+                .file_name()
+                .expect("valid because from read_dir")
+                .to_string_lossy();
+            if tile_from_file_name(&file_name).is_none() {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            files.push((metadata.modified()?, metadata.len(), path));
+        }
+        files.sort_by_key(|(modified, _, _)| *modified);
+
+        let now = SystemTime::now();
+        let mut total_size: u64 = files.iter().map(|(_, len, _)| len).sum();
+        let mut deleted = 0;
+        let mut freed = 0;
+        for (modified, len, path) in files {
+            let too_old =
+                max_age.is_some_and(|age| now.duration_since(modified).unwrap_or_default() > age);
+            let too_big = max_size.is_some_and(|size| total_size > size);
+            if !too_old && !too_big {
+                continue;
+            }
+            fs::remove_file(path)?;
+            total_size -= len;
+            deleted += 1;
+            freed += len;
+        }
+
+        let file_cache = std::mem::take(&mut self.file_cache);
+        self.file_cache = file_cache
+            .into_iter()
+            .filter(|tile| self.tile_path(*tile).exists() || self.plain_tile_path(*tile).exists())
+            .collect();
+        Ok((deleted, freed))
+    }
+
+    pub fn load_tiles(&mut self, tiles: &[TileDescr], style: &Style) -> Result<(), RenderError> {
         for tile in tiles {
-            self.load_tile(*tile)?
+            self.load_tile(*tile, style)?
         }
         Ok(())
     }
@@ -118,14 +362,152 @@ impl MvtGetter {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::STYLE_PATH;
+
+    /// a getter with an empty cache in `dir` which gets the fixtures
+    fn fixture_getter(dir: &std::path::Path) -> MvtGetter {
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir).expect("in test");
+        MvtGetter::with_source(dir.to_path_buf(), Box::new(MockTileSource::fixtures()))
+            .expect("in test")
+    }
+
     #[test]
     fn cache() {
-        let mut getter = MvtGetter::new().expect("in test");
+        let style = Style::from_path(&*STYLE_PATH).expect("in test");
+        let dir = std::env::temp_dir().join("rod-animations-cache");
+        let mut getter = fixture_getter(&dir);
         let tile = TileDescr { z: 7, x: 66, y: 44 };
-        getter.load_tile(tile).expect("could not get tile");
+        getter.load_tile(tile, &style).expect("could not get tile");
+        let missing = TileDescr { z: 7, x: 0, y: 0 };
+        assert!(getter.load_tile(missing, &style).is_err());
         drop(getter);
 
-        let new_getter = MvtGetter::new().expect("could not create cached getter");
+        let new_getter = MvtGetter::with_cache_dir(dir.clone()).expect("in test");
         assert!(new_getter.file_cache.contains(&tile));
+        assert!(!new_getter.file_cache.contains(&missing));
+        fs::remove_dir_all(dir).expect("in test");
+    }
+
+    #[test]
+    fn rate_limiter() {
+        let mut limiter = RateLimiter::new(2.0);
+        let now = Instant::now();
+        assert_eq!(limiter.reserve(now), Duration::ZERO);
+        assert_eq!(limiter.reserve(now), Duration::ZERO);
+        assert_eq!(limiter.reserve(now), Duration::from_millis(500));
+        assert_eq!(limiter.reserve(now), Duration::from_secs(1));
+
+        let later = now + Duration::from_secs(10);
+        assert_eq!(limiter.reserve(later), Duration::ZERO);
+        assert_eq!(limiter.reserve(later), Duration::ZERO);
+        assert!(!limiter.reserve(later).is_zero());
+    }
+
+    #[test]
+    fn corrupt_cache() {
+        let style = Style::from_path(&*STYLE_PATH).expect("in test");
+        let tile = TileDescr { z: 7, x: 66, y: 44 };
+        let dir = std::env::temp_dir().join("rod-animations-corrupt-cache");
+        fixture_getter(&dir);
+        fs::write(dir.join(tile.to_file_name()), b"not a tile").expect("in test");
+        fs::write(dir.join(tile.to_plain_file_name()), b"not a tile").expect("in test");
+
+        let mut getter = MvtGetter::with_source(dir.clone(), Box::new(MockTileSource::fixtures()))
+            .expect("in test");
+        getter.load_tile(tile, &style).expect("could not get tile");
+        assert!(getter.get_tile(tile).is_some());
+        assert!(!dir.join(tile.to_plain_file_name()).exists());
+
+        let mut new_getter = MvtGetter::with_cache_dir(dir.clone()).expect("in test");
+        new_getter
+            .try_load_from_file(tile, &style)
+            .expect("cache was not repaired");
+        fs::remove_dir_all(dir).expect("in test");
+    }
+
+    #[test]
+    fn malformed_file_names() {
+        let dir = std::env::temp_dir().join("rod-animations-malformed-cache");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("in test");
+        let tile = TileDescr { z: 7, x: 66, y: 44 };
+        for name in [
+            tile.to_file_name(),
+            "foo.mvt".to_string(),
+            "1_2.mvt".to_string(),
+            "1_2_3_4.mvt.gz".to_string(),
+            "1_x_3.mvt".to_string(),
+            "1_5_3.mvt".to_string(),
+            "40_0_0.mvt".to_string(),
+            "notes.txt".to_string(),
+        ] {
+            fs::write(dir.join(name), b"").expect("in test");
+        }
+
+        let getter = MvtGetter::with_cache_dir(dir.clone()).expect("startup survives");
+        assert_eq!(getter.file_cache, HashSet::from([tile]));
+        fs::remove_dir_all(dir).expect("in test");
+    }
+
+    #[test]
+    fn clean_cache() {
+        let dir = std::env::temp_dir().join("rod-animations-clean-cache");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("in test");
+        let now = SystemTime::now();
+        let tiles = [
+            TileDescr { z: 3, x: 1, y: 1 },
+            TileDescr { z: 3, x: 1, y: 2 },
+            TileDescr { z: 3, x: 1, y: 3 },
+        ];
+        for (i, tile) in tiles.iter().enumerate() {
+            let path = dir.join(tile.to_file_name());
+            fs::write(&path, [0; 100]).expect("in test");
+            File::options()
+                .write(true)
+                .open(path)
+                .expect("in test")
+                .set_modified(now - Duration::from_secs(60 * 60 * 24 * (3 - i as u64)))
+                .expect("in test");
+        }
+
+        let mut getter = MvtGetter::with_cache_dir(dir.clone()).expect("in test");
+        let day = Duration::from_secs(60 * 60 * 24);
+        assert_eq!(
+            getter
+                .clean_cache(Some(day * 5 / 2), None)
+                .expect("in test"),
+            (1, 100)
+        );
+        assert!(!getter.file_cache.contains(&tiles[0]));
+        assert_eq!(
+            getter.clean_cache(None, Some(150)).expect("in test"),
+            (1, 100)
+        );
+        assert!(!getter.file_cache.contains(&tiles[1]));
+        assert!(getter.file_cache.contains(&tiles[2]));
+        fs::remove_dir_all(dir).expect("in test");
+    }
+
+    #[test]
+    fn gzip_cache() {
+        let style = Style::from_path(&*STYLE_PATH).expect("in test");
+        let tile = TileDescr { z: 7, x: 66, y: 44 };
+        let dir = std::env::temp_dir().join("rod-animations-gzip-cache");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("in test");
+        let data = fs::read("test_files/tiles/7_66_44.mvt").expect("in test");
+        fs::write(
+            dir.join(tile.to_file_name()),
+            compress(&data).expect("in test"),
+        )
+        .expect("in test");
+
+        let mut getter = MvtGetter::with_cache_dir(dir.clone()).expect("in test");
+        assert!(getter.file_cache.contains(&tile));
+        getter.try_load_from_file(tile, &style).expect("in test");
+        assert!(getter.get_tile(tile).is_some());
+        fs::remove_dir_all(dir).expect("in test");
     }
 }
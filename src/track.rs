@@ -3,9 +3,14 @@ use std::{collections::HashMap, io, path, sync::LazyLock};
 use anyhow::{Result, anyhow};
 use chrono::NaiveDateTime;
 
-use crate::{PEOPLE, TRACK_PATH, draw::Pin, lat_long_to_vec, vec::Vector};
+use log::warn;
 
-pub fn get_checkpoints() -> Result<HashMap<String, (Vector, Pin)>> {
+use crate::{
+    CONFIG, PEOPLE, TRACK_PATH, draw::PinGroup, lat_long_to_vec, vec::Vector, vec_to_lat_long,
+};
+
+/// the checkpoints in the order of the route
+pub fn get_checkpoints() -> Result<Vec<(String, (Vector, PinGroup))>> {
     [
         ("Grenoble", lat_long_to_vec(45.242976, 5.644920)),
         ("Avignon", lat_long_to_vec(43.921494, 4.779126)),
@@ -16,57 +21,102 @@ pub fn get_checkpoints() -> Result<HashMap<String, (Vector, Pin)>> {
         ),
     ]
     .iter()
-    .map(|(name, pos)| match Pin::load(name, 1888.0, 4672.0) {
+    .map(|(name, pos)| match PinGroup::load(name, 1888.0, 4672.0) {
         Ok(pin) => Ok((name.to_string(), (*pos, pin))),
-        Err(_) => Err(anyhow!("could not get pin")),
+        Err(err) => Err(anyhow!("could not get pin of {name}: {err}")),
     })
     .collect()
 }
 
+/// positions and times of the departures and arrivals set in the config,
+/// unknown checkpoints and invalid times are skipped with a warning
+pub fn get_checkpoint_events(checkpoints: &[(String, (Vector, PinGroup))]) -> Vec<(Vector, u32)> {
+    let Some(events) = &CONFIG.checkpoint_events else {
+        return Vec::new();
+    };
+    let mut result = Vec::new();
+    for (name, times) in &events.times {
+        let Some((_, (position, _))) = checkpoints.iter().find(|(n, _)| n == name) else {
+            warn!("unknown checkpoint in checkpoint_events: {name}");
+            continue;
+        };
+        for time in times {
+            match NaiveDateTime::parse_from_str(time, TIME_FORMAT)
+                .ok()
+                .and_then(|t| u32::try_from((t - *TIME_ZERO).num_seconds()).ok())
+            {
+                Some(time) => result.push((*position, time)),
+                None => warn!("invalid time of checkpoint {name}: {time}"),
+            }
+        }
+    }
+    result
+}
+
 pub fn get_tracks() -> Result<HashMap<String, Track>> {
     let mut tracks = HashMap::new();
     for name in PEOPLE {
-        let pin = Pin::load(name, 1731.0, 5488.0)?;
+        let pin = PinGroup::load(name, 1731.0, 5488.0)?;
         let path = TRACK_PATH.join(format!("{name}.txt"));
         tracks.insert(name.to_string(), Track::from_file(&path, pin)?);
     }
     Ok(tracks)
 }
 
+/// format of the timestamps in the tracks, ISO 8601 without a time zone
+pub const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
 pub const TIME_ZERO: LazyLock<NaiveDateTime> = LazyLock::new(|| {
-    NaiveDateTime::parse_from_str("2025-04-14T00:00:00", "%Y-%m-%dT%H:%M:%S")
-        .expect("is valid format")
+    NaiveDateTime::parse_from_str("2025-04-14T00:00:00", TIME_FORMAT).expect("is valid format")
 });
 
 pub struct TrackingPoint {
     pub time: u32,
     pub position: Vector,
+    /// meters traveled since the first point
+    pub distance: f32,
+    /// meters above sea level, if the track has it
+    pub altitude: Option<f32>,
+}
+
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// great circle distance in meters between two (lat, lon) pairs in degrees
+pub fn haversine(a: (f32, f32), b: (f32, f32)) -> f32 {
+    let (phi_a, phi_b) = ((a.0 as f64).to_radians(), (b.0 as f64).to_radians());
+    let d_phi = phi_b - phi_a;
+    let d_lambda = (b.1 as f64 - a.1 as f64).to_radians();
+    let h =
+        (d_phi / 2.0).sin().powi(2) + phi_a.cos() * phi_b.cos() * (d_lambda / 2.0).sin().powi(2);
+    (2.0 * EARTH_RADIUS * h.sqrt().asin()) as f32
 }
 
 pub struct Track {
     pub points: Vec<TrackingPoint>,
-    pub pin: Pin,
+    pub pin: PinGroup,
 }
 
 impl Track {
-    pub fn from_file(path: impl AsRef<path::Path>, pin: Pin) -> Result<Self> {
+    pub fn from_file(path: impl AsRef<path::Path>, pin: PinGroup) -> Result<Self> {
         let file = std::fs::File::open(path)?;
         let s = io::read_to_string(file)?;
-        let mut points = Vec::new();
+        let mut coords = Vec::new();
         for line in s.lines() {
             let mut split = line.split(",");
             let lat = split.next().expect("tracks have valid format").parse()?;
             let lon = split.next().expect("tracks have valid format").parse()?;
-            let position = lat_long_to_vec(lat, lon);
             let time = (NaiveDateTime::parse_from_str(
                 split.next().expect("tracks have valid format"),
-                "%Y-%m-%dT%H:%M:%S",
+                TIME_FORMAT,
             )? - *TIME_ZERO)
                 .num_seconds() as u32;
-
-            points.push(TrackingPoint { time, position })
+            let altitude = split.next().map(|s| s.trim().parse()).transpose()?;
+            coords.push((lat, lon, time, altitude));
         }
-        Ok(Self { pin, points })
+        Ok(Self {
+            pin,
+            points: tracking_points(coords),
+        })
     }
 
     pub fn get_position(&self, time: u32) -> Option<Vector> {
@@ -94,6 +144,31 @@ impl Track {
         }
     }
 
+    /// meters traveled up to `time`
+    pub fn distance_until(&self, time: u32) -> f32 {
+        distance_until(&self.points, time)
+    }
+
+    /// meters above sea level at `time`, `None` if the track has no altitude there
+    pub fn get_altitude(&self, time: u32) -> Option<f32> {
+        altitude_at(&self.points, time)
+    }
+
+    /// speed in meters per second at `time`, `None` outside of the track.
+    /// It comes from the great circle distances between the points,
+    /// so it is the speed on the ground and not the speed on the mercator map.
+    pub fn get_velocity(&self, time: u32) -> Option<f32> {
+        velocity_at(&self.points, time)
+    }
+
+    /// KML document with the track as a line named `name`
+    pub fn to_kml(&self, name: &str) -> String {
+        kml_document(&kml_line_string(
+            name,
+            self.points.iter().map(|pt| pt.position),
+        ))
+    }
+
     pub fn valid_times(&self) -> String {
         let t_0 = chrono::Duration::seconds(self.points[0].time as i64);
         let t_1 =
@@ -109,3 +184,207 @@ impl Track {
         )
     }
 }
+
+/// KML document with a point for every checkpoint and the route connecting them
+pub fn checkpoints_to_kml(checkpoints: &[(String, (Vector, PinGroup))]) -> String {
+    let mut placemarks: String = checkpoints
+        .iter()
+        .map(|(name, (position, _))| {
+            let (lat, lon) = vec_to_lat_long(*position);
+            format!(
+                "<Placemark><name>{}</name>\
+                <Point><coordinates>{lon},{lat}</coordinates></Point></Placemark>\n",
+                kml_escape(name)
+            )
+        })
+        .collect();
+    placemarks.push_str(&kml_line_string(
+        "Route",
+        checkpoints.iter().map(|(_, (position, _))| *position),
+    ));
+    kml_document(&placemarks)
+}
+
+fn kml_document(content: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <kml xmlns=\"http://www.opengis.net/kml/2.2\"><Document>\n{content}</Document></kml>\n"
+    )
+}
+
+/// placemark with a line through `positions`
+fn kml_line_string(name: &str, positions: impl Iterator<Item = Vector>) -> String {
+    let coordinates: Vec<String> = positions
+        .map(|position| {
+            let (lat, lon) = vec_to_lat_long(position);
+            format!("{lon},{lat}")
+        })
+        .collect();
+    format!(
+        "<Placemark><name>{}</name>\
+        <LineString><coordinates>{}</coordinates></LineString></Placemark>\n",
+        kml_escape(name),
+        coordinates.join(" ")
+    )
+}
+
+fn kml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// turns (lat, lon, time, altitude) into points with the distance traveled
+fn tracking_points(
+    coords: impl IntoIterator<Item = (f32, f32, u32, Option<f32>)>,
+) -> Vec<TrackingPoint> {
+    let mut last_coords = None;
+    let mut distance = 0.0;
+    coords
+        .into_iter()
+        .map(|(lat, lon, time, altitude)| {
+            if let Some(last) = last_coords {
+                distance += haversine(last, (lat, lon));
+            }
+            last_coords = Some((lat, lon));
+            TrackingPoint {
+                time,
+                position: lat_long_to_vec(lat, lon),
+                distance,
+                altitude,
+            }
+        })
+        .collect()
+}
+
+fn velocity_at(points: &[TrackingPoint], time: u32) -> Option<f32> {
+    let (p0, p1) = match points.binary_search_by_key(&time, |pt| pt.time) {
+        // on a point the speed is averaged over both neighbours, at the ends only one exists
+        Ok(idx) => (
+            &points[idx.saturating_sub(1)],
+            &points[(idx + 1).min(points.len() - 1)],
+        ),
+        Err(idx) if idx == 0 || idx == points.len() => return None,
+        Err(idx) => (&points[idx - 1], &points[idx]),
+    };
+    if p1.time == p0.time {
+        return None;
+    }
+    Some((p1.distance - p0.distance) / (p1.time - p0.time) as f32)
+}
+
+fn distance_until(points: &[TrackingPoint], time: u32) -> f32 {
+    match points.binary_search_by_key(&time, |pt| pt.time) {
+        Ok(idx) => points[idx].distance,
+        Err(0) => 0.0,
+        Err(idx) if idx == points.len() => points[idx - 1].distance,
+        Err(idx) => {
+            let (p0, p1) = (&points[idx - 1], &points[idx]);
+            let fraction = (time - p0.time) as f32 / (p1.time - p0.time) as f32;
+            p0.distance + (p1.distance - p0.distance) * fraction
+        }
+    }
+}
+
+fn altitude_at(points: &[TrackingPoint], time: u32) -> Option<f32> {
+    match points.binary_search_by_key(&time, |pt| pt.time) {
+        Ok(idx) => points[idx].altitude,
+        Err(0) => points.first()?.altitude,
+        Err(idx) if idx == points.len() => points[idx - 1].altitude,
+        Err(idx) => {
+            let (p0, p1) = (&points[idx - 1], &points[idx]);
+            let (a0, a1) = (p0.altitude?, p1.altitude?);
+            let fraction = (time - p0.time) as f32 / (p1.time - p0.time) as f32;
+            Some(a0 + (a1 - a0) * fraction)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn distance() {
+        // one degree of latitude is about 111 km
+        let d = haversine((45.0, 5.0), (46.0, 5.0));
+        assert!((d - 111_195.0).abs() < 100.0, "{d}");
+
+        let points: Vec<_> = [(0, 0.0), (100, 1000.0), (200, 1500.0)]
+            .into_iter()
+            .map(|(time, distance)| TrackingPoint {
+                time,
+                position: Vector::zeros(),
+                distance,
+                altitude: None,
+            })
+            .collect();
+        assert_eq!(distance_until(&points, 0), 0.0);
+        assert_eq!(distance_until(&points, 50), 500.0);
+        assert_eq!(distance_until(&points, 150), 1250.0);
+        assert_eq!(distance_until(&points, 1000), 1500.0);
+    }
+
+    #[test]
+    fn altitude() {
+        let mut points: Vec<_> = [(0, Some(200.0)), (100, Some(400.0)), (200, None)]
+            .into_iter()
+            .map(|(time, altitude)| TrackingPoint {
+                time,
+                position: Vector::zeros(),
+                distance: 0.0,
+                altitude,
+            })
+            .collect();
+        assert_eq!(altitude_at(&points, 0), Some(200.0));
+        assert_eq!(altitude_at(&points, 25), Some(250.0));
+        assert_eq!(altitude_at(&points, 150), None);
+        assert_eq!(altitude_at(&points, 1000), None);
+        points.pop();
+        assert_eq!(altitude_at(&points, 1000), Some(400.0));
+        assert_eq!(altitude_at(&[], 0), None);
+    }
+
+    #[test]
+    fn kml() {
+        let line = kml_line_string(
+            "A & B",
+            [lat_long_to_vec(45.0, 5.0), lat_long_to_vec(46.0, 6.0)].into_iter(),
+        );
+        assert!(line.contains("<name>A &amp; B</name>"));
+        let coordinates: Vec<(f32, f32)> = line
+            .split("<coordinates>")
+            .nth(1)
+            .and_then(|s| s.split("</coordinates>").next())
+            .expect("in test")
+            .split(' ')
+            .map(|pair| {
+                let (lon, lat) = pair.split_once(',').expect("in test");
+                (lon.parse().expect("in test"), lat.parse().expect("in test"))
+            })
+            .collect();
+        assert_eq!(coordinates.len(), 2);
+        assert!((coordinates[1].0 - 6.0).abs() < 1e-3 && (coordinates[1].1 - 46.0).abs() < 1e-3);
+        assert!(kml_document(&line).starts_with("<?xml"));
+    }
+
+    #[test]
+    fn velocity() {
+        // 10 m/s eastwards at 60° north, where the map stretches distances by two
+        let meters_per_degree = haversine((60.0, 5.0), (60.0, 6.0));
+        let points = tracking_points((0..10).map(|i| {
+            (
+                60.0,
+                5.0 + i as f32 * 600.0 / meters_per_degree,
+                i * 60,
+                None,
+            )
+        }));
+        for time in [0, 30, 60, 300, 540] {
+            let v = velocity_at(&points, time).expect("in test");
+            assert!((v - 10.0).abs() < 0.01, "{time}: {v}");
+        }
+        assert_eq!(velocity_at(&points, 541), None);
+        assert_eq!(velocity_at(&points[..1], 0), None);
+    }
+}
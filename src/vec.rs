@@ -1,6 +1,7 @@
 use std::ops::{Add, Div, Mul, Neg};
 
 use geo_types::Coord;
+use serde::{Deserialize, Serialize};
 
 macro_rules! impl_op_for_refs {
     ($t:ty, $trait:ident, $method:ident) => {
@@ -55,7 +56,9 @@ macro_rules! complete_group {
     };
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+/// in json a world coordinate is written as `[x, y]`
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "(f32, f32)", into = "(f32, f32)")]
 pub struct Vector {
     pub x: f32,
     pub y: f32,
@@ -78,6 +81,19 @@ impl From<&Coord<f32>> for Vector {
         }
     }
 }
+
+impl From<(f32, f32)> for Vector {
+    fn from((x, y): (f32, f32)) -> Self {
+        Vector { x, y }
+    }
+}
+
+impl From<Vector> for (f32, f32) {
+    fn from(value: Vector) -> Self {
+        (value.x, value.y)
+    }
+}
+
 impl Vector {
     pub fn new(x: f32, y: f32) -> Self {
         Self { x, y }
@@ -147,31 +163,57 @@ impl Div<f32> for Vector {
 
 impl_op_for_refs!(Vector, f32, Div, div);
 
+/// Scales and then translates, in `f64` so world coordinates near 0.5 keep sub-pixel precision
+/// at high zoom where their scaled values are in the tens of millions.
 #[derive(Clone, Copy, Debug)]
 pub struct Transform {
-    pub(super) scale: f32,
-    pub(super) translation: Vector,
+    pub(super) scale: f64,
+    pub(super) translation: (f64, f64),
 }
 
 impl Transform {
     pub fn new(scale: f32, translation: Vector) -> Self {
+        Self::precise(scale as f64, (translation.x as f64, translation.y as f64))
+    }
+
+    /// like `new` with the full precision of `f64`
+    pub fn precise(scale: f64, translation: (f64, f64)) -> Self {
         assert!(scale.is_finite(), "scale was not finite");
         assert!(scale > 0.0, "scale was not positive");
-        assert!(translation.is_finite(), "translation was not finite");
+        assert!(
+            translation.0.is_finite() && translation.1.is_finite(),
+            "translation was not finite"
+        );
         Self { scale, translation }
     }
 
+    /// factor by which lengths are multiplied
+    pub fn scale(&self) -> f32 {
+        self.scale as f32
+    }
+
     pub fn identity() -> Self {
         Self {
             scale: 1.0,
-            translation: Vector::zeros(),
+            translation: (0.0, 0.0),
         }
     }
 
+    /// the same transform as a skia matrix, for drawing with it on the canvas matrix
+    pub fn to_matrix(&self) -> skia_safe::Matrix {
+        skia_safe::Matrix::scale_translate(
+            (self.scale as f32, self.scale as f32),
+            (self.translation.0 as f32, self.translation.1 as f32),
+        )
+    }
+
     pub fn invert(&self) -> Transform {
         Self {
             scale: 1.0 / self.scale,
-            translation: -self.translation / self.scale,
+            translation: (
+                -self.translation.0 / self.scale,
+                -self.translation.1 / self.scale,
+            ),
         }
     }
 }
@@ -179,7 +221,10 @@ impl Transform {
 impl Mul<Vector> for Transform {
     type Output = Vector;
     fn mul(self, rhs: Vector) -> Self::Output {
-        self.scale * rhs + self.translation
+        Vector::new(
+            (self.scale * rhs.x as f64 + self.translation.0) as f32,
+            (self.scale * rhs.y as f64 + self.translation.1) as f32,
+        )
     }
 }
 
@@ -191,7 +236,10 @@ impl Mul for Transform {
     fn mul(self, rhs: Self) -> Self::Output {
         Transform {
             scale: self.scale * rhs.scale,
-            translation: self.scale * rhs.translation + self.translation,
+            translation: (
+                self.scale * rhs.translation.0 + self.translation.0,
+                self.scale * rhs.translation.1 + self.translation.1,
+            ),
         }
     }
 }
@@ -234,7 +282,32 @@ mod test {
         for t in ts {
             let new = t * t.invert();
             assert!((1.0 - new.scale).abs() < 0.000001);
-            assert!(new.translation.norm() < 0.000001);
+            assert!(new.translation.0.abs() < 0.000001 && new.translation.1.abs() < 0.000001);
+        }
+    }
+
+    #[test]
+    fn transform_matrix() {
+        let t = Transform::new(256.0 * 3.7, Vector::new(-12345.5, 678.25));
+        let matrix = t.to_matrix();
+        for p in [
+            Vector::new(0.0, 0.0),
+            Vector::new(1.0, 1.0),
+            Vector::new(0.25, 0.75),
+            Vector::new(-0.1, 1.3),
+        ] {
+            let expected = t * p;
+            let mapped = matrix.map_point((p.x, p.y));
+            assert!((mapped.x - expected.x).abs() < 1e-3 && (mapped.y - expected.y).abs() < 1e-3);
         }
     }
+
+    #[test]
+    fn serde() {
+        let vec = Vector::new(-12345.5, 678.25);
+        let json = serde_json::to_string(&vec).expect("in test");
+        assert_eq!(json, "[-12345.5,678.25]");
+        assert_eq!(serde_json::from_str::<Vector>(&json).expect("in test"), vec);
+        assert!(serde_json::from_str::<Vector>("[1.0]").is_err());
+    }
 }